@@ -0,0 +1,355 @@
+use std::sync::OnceLock;
+use crate::game::Color;
+
+// Sliding attacks via magic bitboards. For every square the relevant blockers
+// (the rays minus the board edge) are multiplied by a magic number, and the top
+// bits of the product index a table of precomputed attack sets.
+
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+// Found offline by random trial (sparse candidates, no collisions allowed);
+// an index size of exactly the number of mask bits is used for every square.
+static ROOK_MAGICS: [u64; 64] = [
+    0x2080002080400010, 0x00C0002001401000, 0x2100110008402002, 0x0880080081041000,
+    0x0200020020041008, 0x2300040008010012, 0x0C00283004008201, 0x0180010000407A80,
+    0x0168800080400020, 0x0010400040201000, 0x1001002001001048, 0x1001002408100100,
+    0x0801000408010012, 0x4001000209000400, 0x08A20004C8020001, 0x2002801145002280,
+    0x0080860021004200, 0x001000C009402002, 0x00B0002004002800, 0x100A808010020800,
+    0x8101010008000410, 0x0244008002000480, 0x0000040010810208, 0x2000020000448534,
+    0x4104400480008033, 0x0000810100204000, 0x0440430900200010, 0x4600240900100100,
+    0x0060080080040080, 0x0001000300080400, 0x0004084400011002, 0x0023040200008041,
+    0x0580050043002080, 0x0400804002802008, 0x0001002001004010, 0x1000200901001000,
+    0x4410800801800C00, 0xA012003806001004, 0x0020100104008802, 0x0004808402000041,
+    0x0010400170898000, 0x0080500020004004, 0x1040408012020020, 0x8010040008004040,
+    0x2001080100110004, 0x0000020004008080, 0x0021010810040002, 0x0800008C43020024,
+    0x0000800021005100, 0x0070201040008080, 0x0000D04282006A00, 0x0010014400080240,
+    0x0001080110050100, 0x0012000810240600, 0x0402000801040200, 0x028100108A004100,
+    0x0050800300102045, 0x8208210040120882, 0x8010600101183441, 0x020B000910006045,
+    0x0241001002480005, 0x0081000400880241, 0x0000009008024124, 0x0048122980410402,
+];
+static BISHOP_MAGICS: [u64; 64] = [
+    0x0848020822040013, 0x8010A40085821200, 0x0008008430840822, 0x0808048108040000,
+    0x1304042100008104, 0x5001012010204023, 0x81048801B8200420, 0x200A008084012000,
+    0x0040102001042084, 0x840A505042428020, 0x0000700102202920, 0x44101C0C10800002,
+    0x0040040422000000, 0x0180020802090202, 0x4020020811041202, 0x000104308C042000,
+    0x4140661002424400, 0x0028012008010460, 0x0188062102002A00, 0x0014004840102008,
+    0x0105000290400002, 0x8001022200410400, 0x104A041918013446, 0x008A000082008238,
+    0x04A0060008100430, 0x0008220008820801, 0x2508041208005010, 0x4008080200202020,
+    0x2441001013004000, 0x0030008060407000, 0x4008108000420800, 0x0012021050290100,
+    0x0210080482200500, 0xCC01112048100480, 0x0020402806500440, 0x00048E0080580080,
+    0x0040102020020080, 0x0028010440080807, 0x4601041108008800, 0x8040810E04104200,
+    0x901210110400088A, 0xA003080212081050, 0x00C1004048401004, 0x900000A014400800,
+    0x0008021040405401, 0x4020008206002090, 0x0004190424030100, 0x0424008A02026250,
+    0x8004088250900040, 0x1C00430088A04200, 0x0001020094040001, 0x8040210020880061,
+    0x2010040450442032, 0x0800840850044001, 0x0004040802140004, 0x0004080A04222020,
+    0x8088802110022000, 0x1081A10416114400, 0x0205010A24060820, 0x0000000720411080,
+    0x1008000208430400, 0x580C026028810840, 0x802020441020A110, 0x12C0022401020018,
+];
+
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupancy: u64) -> usize {
+        self.offset + ((occupancy & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct SlidingTables {
+    rook: Vec<Magic>,
+    bishop: Vec<Magic>,
+    attacks: Vec<u64>,
+}
+
+static TABLES: OnceLock<SlidingTables> = OnceLock::new();
+
+fn tables() -> &'static SlidingTables {
+    TABLES.get_or_init(|| {
+        let mut attacks = Vec::new();
+        let rook = (0..64)
+            .map(|square| build_magic(square, ROOK_MAGICS[square], &ROOK_DIRECTIONS, &mut attacks))
+            .collect();
+        let bishop = (0..64)
+            .map(|square| build_magic(square, BISHOP_MAGICS[square], &BISHOP_DIRECTIONS, &mut attacks))
+            .collect();
+        SlidingTables { rook, bishop, attacks }
+    })
+}
+
+fn build_magic(square: usize, magic: u64, directions: &[(i32, i32)], attacks: &mut Vec<u64>) -> Magic {
+    let mask = relevant_blockers(square, directions);
+    let bits = mask.count_ones();
+    let entry = Magic { mask, magic, shift: 64 - bits, offset: attacks.len() };
+    attacks.resize(attacks.len() + (1 << bits), 0);
+
+    // Walk every subset of the mask (Carry-Rippler) and fill its slot
+    let mut subset: u64 = 0;
+    loop {
+        let index = entry.index(subset);
+        let expected = slide(square, subset, directions);
+        debug_assert!(attacks[index] == 0 || attacks[index] == expected,
+                      "magic collision on square {}", square);
+        attacks[index] = expected;
+
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == 0 {
+            break;
+        }
+    }
+
+    entry
+}
+
+// Squares along the rays whose occupancy can change the attack set. The last
+// square of each ray never blocks anything beyond it, so it is left out.
+fn relevant_blockers(square: usize, directions: &[(i32, i32)]) -> u64 {
+    let mut mask = 0;
+    let (file, rank) = ((square % 8) as i32, (square / 8) as i32);
+    for &(df, dr) in directions {
+        let (mut f, mut r) = (file + df, rank + dr);
+        while on_board(f + df, r + dr) {
+            mask |= 1 << (r * 8 + f);
+            f += df;
+            r += dr;
+        }
+    }
+    mask
+}
+
+// Naive ray tracer, used to fill the tables and as a reference in the tests
+fn slide(square: usize, occupancy: u64, directions: &[(i32, i32)]) -> u64 {
+    let mut attacks = 0;
+    let (file, rank) = ((square % 8) as i32, (square / 8) as i32);
+    for &(df, dr) in directions {
+        let (mut f, mut r) = (file + df, rank + dr);
+        while on_board(f, r) {
+            let bit = 1 << (r * 8 + f);
+            attacks |= bit;
+            if occupancy & bit != 0 {
+                break;
+            }
+            f += df;
+            r += dr;
+        }
+    }
+    attacks
+}
+
+fn on_board(file: i32, rank: i32) -> bool {
+    (0..8).contains(&file) && (0..8).contains(&rank)
+}
+
+// Knight, king and pawn attacks do not depend on occupancy, so they are
+// plain tables built at compile time.
+const KNIGHT_OFFSETS: [(i32, i32); 8] = [(1, 2), (2, 1), (2, -1), (1, -2),
+                                         (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+const KING_OFFSETS: [(i32, i32); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1),
+                                       (-1, 0), (-1, -1), (0, -1), (1, -1)];
+
+const fn leaper_table(offsets: &[(i32, i32)]) -> [u64; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let (file, rank) = ((square % 8) as i32, (square / 8) as i32);
+        let mut i = 0;
+        while i < offsets.len() {
+            let (f, r) = (file + offsets[i].0, rank + offsets[i].1);
+            if f >= 0 && f < 8 && r >= 0 && r < 8 {
+                table[square] |= 1 << (r * 8 + f);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+static KNIGHT_ATTACKS: [u64; 64] = leaper_table(&KNIGHT_OFFSETS);
+static KING_ATTACKS: [u64; 64] = leaper_table(&KING_OFFSETS);
+static WHITE_PAWN_ATTACKS: [u64; 64] = leaper_table(&[(-1, 1), (1, 1)]);
+static BLACK_PAWN_ATTACKS: [u64; 64] = leaper_table(&[(-1, -1), (1, -1)]);
+
+// BETWEEN[a][b] holds the squares strictly between a and b when they share
+// a rank, file or diagonal. Walking out from each square along the eight
+// king directions, every square reached gets the path walked so far.
+const fn between_table() -> [[u64; 64]; 64] {
+    let mut table = [[0; 64]; 64];
+    let mut square = 0;
+    while square < 64 {
+        let mut i = 0;
+        while i < KING_OFFSETS.len() {
+            let (df, dr) = KING_OFFSETS[i];
+            let (mut f, mut r) = ((square % 8) as i32 + df, (square / 8) as i32 + dr);
+            let mut path = 0;
+            while f >= 0 && f < 8 && r >= 0 && r < 8 {
+                let to = (r * 8 + f) as usize;
+                table[square][to] = path;
+                path |= 1 << to;
+                f += df;
+                r += dr;
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+static BETWEEN: [[u64; 64]; 64] = between_table();
+
+// Squares strictly between two squares on a shared rank, file or
+// diagonal; 0 for squares not in line
+pub fn between(a: usize, b: usize) -> u64 {
+    BETWEEN[a][b]
+}
+
+pub fn knight_attacks(square: usize) -> u64 {
+    KNIGHT_ATTACKS[square]
+}
+
+pub fn king_attacks(square: usize) -> u64 {
+    KING_ATTACKS[square]
+}
+
+// Squares a pawn of `color` on `square` attacks (its capture targets)
+pub fn pawn_attacks(color: Color, square: usize) -> u64 {
+    match color {
+        Color::White => WHITE_PAWN_ATTACKS[square],
+        Color::Black => BLACK_PAWN_ATTACKS[square],
+    }
+}
+
+pub fn rook_attacks(square: usize, occupancy: u64) -> u64 {
+    let tables = tables();
+    tables.attacks[tables.rook[square].index(occupancy)]
+}
+
+pub fn bishop_attacks(square: usize, occupancy: u64) -> u64 {
+    let tables = tables();
+    tables.attacks[tables.bishop[square].index(occupancy)]
+}
+
+pub fn queen_attacks(square: usize, occupancy: u64) -> u64 {
+    rook_attacks(square, occupancy) | bishop_attacks(square, occupancy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    // xorshift64, so the random occupancies are the same on every run
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn rook_attacks_on_empty_board() {
+        for square in 0..64 {
+            assert_eq!(rook_attacks(square, 0).count_ones(), 14);
+        }
+    }
+
+    #[test]
+    fn bishop_attacks_from_corner_and_center() {
+        // a1 sees the long diagonal, d4 sees 13 squares on an empty board
+        assert_eq!(bishop_attacks(0, 0), 0x8040_2010_0804_0200);
+        assert_eq!(bishop_attacks(27, 0).count_ones(), 13);
+    }
+
+    #[test]
+    fn rook_attacks_stop_at_blockers() {
+        // Rook on a1, blockers on a3 and c1
+        let occupancy = (1 << 16) | (1 << 2);
+        let expected = (1 << 8) | (1 << 16) | (1 << 1) | (1 << 2);
+        assert_eq!(rook_attacks(0, occupancy), expected);
+    }
+
+    #[test]
+    fn leaper_attacks() {
+        // Knight on a1 reaches b3 and c2, on d4 eight squares
+        assert_eq!(knight_attacks(0), (1 << 17) | (1 << 10));
+        assert_eq!(knight_attacks(27).count_ones(), 8);
+        assert_eq!(king_attacks(0), (1 << 1) | (1 << 8) | (1 << 9));
+        assert_eq!(king_attacks(27).count_ones(), 8);
+        // Pawn on e4 attacks d5/f5 for White and d3/f3 for Black, a-pawns only one side
+        assert_eq!(pawn_attacks(Color::White, 28), (1 << 35) | (1 << 37));
+        assert_eq!(pawn_attacks(Color::Black, 28), (1 << 19) | (1 << 21));
+        assert_eq!(pawn_attacks(Color::White, 8), 1 << 17);
+    }
+
+    #[test]
+    fn between_needs_a_shared_line() {
+        // a1 to a4 passes a2 and a3; a1 and b3 aren't in line
+        assert_eq!(between(0, 24), (1 << 8) | (1 << 16));
+        assert_eq!(between(0, 17), 0);
+        assert_eq!(between(0, 1), 0);
+        assert_eq!(between(63, 0), 0x0040_2010_0804_0200);
+        for a in 0..64 {
+            for b in 0..64 {
+                assert_eq!(between(a, b), between(b, a));
+                let expected = if rook_attacks(a, 0) & (1 << b) != 0 {
+                    rook_attacks(a, 1 << b) & rook_attacks(b, 1 << a)
+                } else if bishop_attacks(a, 0) & (1 << b) != 0 {
+                    bishop_attacks(a, 1 << b) & bishop_attacks(b, 1 << a)
+                } else {
+                    0
+                };
+                assert_eq!(between(a, b), expected, "{} {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn queen_attacks_are_union_of_rook_and_bishop() {
+        let occupancy = 0x0000_1824_0042_1800;
+        for square in 0..64 {
+            assert_eq!(queen_attacks(square, occupancy),
+                       rook_attacks(square, occupancy) | bishop_attacks(square, occupancy));
+        }
+    }
+
+    #[test]
+    fn magic_lookups_match_ray_tracer_for_random_occupancies() {
+        let mut state = 0x2545_F491_4F6C_DD1D;
+        for _ in 0..2000 {
+            // AND of two draws gives roughly a quarter of the board occupied
+            let occupancy = next_random(&mut state) & next_random(&mut state);
+            for square in 0..64 {
+                assert_eq!(rook_attacks(square, occupancy),
+                           slide(square, occupancy, &ROOK_DIRECTIONS),
+                           "rook on {} with occupancy {:#018x}", square, occupancy);
+                assert_eq!(bishop_attacks(square, occupancy),
+                           slide(square, occupancy, &BISHOP_DIRECTIONS),
+                           "bishop on {} with occupancy {:#018x}", square, occupancy);
+            }
+        }
+    }
+
+    #[test]
+    fn magic_lookup_timing() {
+        let mut state = 0x9E37_79B9_7F4A_7C15;
+        let occupancies: Vec<u64> = (0..1000)
+            .map(|_| next_random(&mut state) & next_random(&mut state))
+            .collect();
+        tables();
+
+        let start = Instant::now();
+        let mut checksum = 0;
+        for occupancy in &occupancies {
+            for square in 0..64 {
+                checksum ^= queen_attacks(square, *occupancy);
+            }
+        }
+        println!("{} queen lookups in {:?} (checksum {:#x})",
+                 occupancies.len() * 64, start.elapsed(), checksum);
+    }
+}
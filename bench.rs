@@ -0,0 +1,72 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+use crate::game::*;
+
+// Middlegames, an endgame and a tactical position. The list and the fresh
+// table per position keep the node count of a depth identical across runs,
+// so a change in it means the search itself changed.
+const BENCH_POSITIONS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bq1rk1/pp2bppp/2n2n2/2pp4/3P4/2PBPN2/PP1N1PPP/R2QK2R w KQ - 0 9",
+    "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+];
+
+pub struct BenchResult {
+    pub positions: usize,
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    pub fn nodes_per_second(&self) -> u64 {
+        let micros = self.elapsed.as_micros().max(1);
+        (self.nodes as u128 * 1_000_000 / micros) as u64
+    }
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} positions, {} nodes, {} ms, {} nps", self.positions, self.nodes,
+               self.elapsed.as_millis(), self.nodes_per_second())
+    }
+}
+
+// Searches every bench position to `depth` and prints the totals
+pub fn bench(depth: u8) -> BenchResult {
+    let start = Instant::now();
+    let mut nodes = 0;
+    for fen in BENCH_POSITIONS {
+        let mut game = Game::read_FEN(fen);
+        game.search(depth);
+        nodes += game.nodes;
+    }
+
+    let result = BenchResult { positions: BENCH_POSITIONS.len(), nodes, elapsed: start.elapsed() };
+    println!("{}", result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bench_node_count_is_reproducible() {
+        let first = bench(3);
+        let second = bench(3);
+        assert_eq!(first.positions, BENCH_POSITIONS.len());
+        assert!(first.nodes > 0);
+        assert_eq!(first.nodes, second.nodes);
+        assert!(bench(4).nodes > first.nodes);
+    }
+
+    #[test]
+    fn nodes_per_second_and_summary() {
+        let result = BenchResult { positions: 2, nodes: 3000, elapsed: Duration::from_millis(1500) };
+        assert_eq!(result.nodes_per_second(), 2000);
+        assert_eq!(result.to_string(), "2 positions, 3000 nodes, 1500 ms, 2000 nps");
+    }
+}
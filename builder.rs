@@ -0,0 +1,121 @@
+use crate::game::*;
+
+// Sets up a game field by field. Fields left alone default to the initial
+// board, White to move, no castling, no en passant square and clocks 0 and
+// 1. `build` writes the fields out as a FEN and reads it back, so a built
+// game passes exactly the checks of try_read_FEN.
+#[derive(Debug, Clone)]
+pub struct GameBuilder {
+    board: String,
+    active_color: Color,
+    castling_rights: CastlingRights,
+    en_passant: Option<Square>,
+    halfmove_clock: usize,
+    fullmove_number: usize,
+}
+
+impl Default for GameBuilder {
+    fn default() -> GameBuilder {
+        GameBuilder {
+            board: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR".to_string(),
+            active_color: Color::White,
+            castling_rights: CastlingRights::NONE,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+}
+
+impl GameBuilder {
+    pub fn new() -> GameBuilder {
+        GameBuilder::default()
+    }
+
+    // The piece placement field of a FEN, e.g. "4k3/8/8/8/8/8/8/4K3"
+    pub fn pieces_from_fen(mut self, board: &str) -> GameBuilder {
+        self.board = board.to_string();
+        self
+    }
+
+    pub fn active_color(mut self, color: Color) -> GameBuilder {
+        self.active_color = color;
+        self
+    }
+
+    pub fn castling_rights(mut self, rights: CastlingRights) -> GameBuilder {
+        self.castling_rights = rights;
+        self
+    }
+
+    pub fn en_passant(mut self, square: Option<Square>) -> GameBuilder {
+        self.en_passant = square;
+        self
+    }
+
+    pub fn clocks(mut self, halfmove_clock: usize, fullmove_number: usize) -> GameBuilder {
+        self.halfmove_clock = halfmove_clock;
+        self.fullmove_number = fullmove_number;
+        self
+    }
+
+    pub fn to_fen(&self) -> String {
+        let color = match self.active_color {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+        let mut castling: String = CASTLING_SIDES.iter().zip(['K', 'Q', 'k', 'q'])
+            .filter(|(right, _)| self.castling_rights.contains(**right))
+            .map(|(_, ch)| ch)
+            .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        let en_passant = self.en_passant.map_or("-".to_string(), |square| square.to_string());
+        format!("{} {} {} {} {} {}", self.board, color, castling, en_passant,
+                self.halfmove_clock, self.fullmove_number)
+    }
+
+    pub fn build(&self) -> Result<Game, FenError> {
+        Game::try_read_FEN(&self.to_fen())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_from_board_and_side_to_move() {
+        let game = GameBuilder::new()
+            .pieces_from_fen("4k3/8/8/8/8/8/4P3/4K3")
+            .active_color(Color::Black)
+            .build()
+            .unwrap();
+        assert_eq!(game.to_FEN(), "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1");
+        assert_eq!(GameBuilder::new().build().unwrap().to_FEN(),
+                   "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1");
+    }
+
+    #[test]
+    fn every_field_reaches_the_game() {
+        let game = GameBuilder::new()
+            .pieces_from_fen("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR")
+            .castling_rights(CastlingRights::WHITEKINGSIDE | CastlingRights::BLACKQUEENSIDE)
+            .en_passant(Some("d6".parse().unwrap()))
+            .clocks(0, 3)
+            .build()
+            .unwrap();
+        assert_eq!(game.to_FEN(), "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w Kq d6 0 3");
+    }
+
+    #[test]
+    fn build_applies_the_fen_checks() {
+        assert!(matches!(GameBuilder::new().pieces_from_fen("8/8/9/8/8/8/8/8").build(), Err(FenError::Board(_))));
+        // White to move with Black in check
+        let checked = GameBuilder::new().pieces_from_fen("4k3/8/8/8/8/8/8/4RK2").build();
+        assert_eq!(checked.err(), Some(FenError::OpponentInCheck(Color::Black)));
+        let no_pawn = GameBuilder::new().en_passant(Some("e6".parse().unwrap())).build();
+        assert!(matches!(no_pawn, Err(FenError::ImpossibleEnPassant(_))));
+    }
+}
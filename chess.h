@@ -0,0 +1,58 @@
+#ifndef CHESS_ENGINE_H
+#define CHESS_ENGINE_H
+
+/* Generated by cbindgen from ffi.rs; do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define CHESS_OK 0
+
+#define CHESS_NULL_POINTER -1
+
+#define CHESS_INVALID_UTF8 -2
+
+#define CHESS_INVALID_FEN -3
+
+#define CHESS_ILLEGAL_MOVE -4
+
+#define CHESS_NO_MOVE -5
+
+#define CHESS_BUFFER_TOO_SMALL -6
+
+typedef struct Game Game;
+
+// A new game at the initial position. Release it with chess_game_free.
+struct Game *chess_game_new(void);
+
+// Releases a game from chess_game_new. Null is ignored.
+//
+// # Safety
+// `game` must be null or a handle from chess_game_new not yet freed.
+void chess_game_free(struct Game *game);
+
+// Sets up the position of a NUL-terminated FEN. The game is unchanged
+// when the FEN is invalid.
+//
+// # Safety
+// `game` must be a live handle and `fen` null or a NUL-terminated string.
+int chess_set_fen(struct Game *game, const char *fen);
+
+// Searches `depth` plies and writes the best move in coordinate notation
+// ("e2e4", "e7e8q") to `buffer` as a NUL-terminated string. Six bytes are
+// always enough. Depths below 1 search 1 ply and depths above 64 search 64.
+//
+// # Safety
+// `game` must be a live handle and `buffer` valid for `length` bytes.
+int chess_best_move(struct Game *game, int depth, char *buffer, size_t length);
+
+// Plays a move in coordinate notation if it is legal.
+//
+// # Safety
+// `game` must be a live handle and `mv` null or a NUL-terminated string.
+int chess_make_move(struct Game *game, const char *mv);
+
+#endif  /* CHESS_ENGINE_H */
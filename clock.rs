@@ -0,0 +1,258 @@
+use std::num::NonZeroU32;
+use std::time::{Duration, Instant};
+use crate::game::Color;
+
+// Where a clock reads the time from: how long since some fixed start
+pub trait TimeSource {
+    fn now(&self) -> Duration;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WallClock {
+    start: Instant,
+}
+
+impl WallClock {
+    pub fn new() -> WallClock {
+        WallClock { start: Instant::now() }
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> WallClock {
+        WallClock::new()
+    }
+}
+
+impl TimeSource for WallClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TimeControl {
+    SuddenDeath(Duration),
+    // Fischer: `increment` is added after every move
+    Increment { base: Duration, increment: Duration },
+    // `time` for every `moves` moves, added again each time they are played
+    MovesToGo { moves: NonZeroU32, time: Duration },
+}
+
+// Remaining time of both players under a time control. Only the side
+// whose turn was started is running.
+#[derive(Debug, Clone)]
+pub struct Clock<T: TimeSource = WallClock> {
+    control: TimeControl,
+    remaining: [Duration; 2],
+    moves_played: [u32; 2],
+    // Side to move and when its turn started
+    running: Option<(Color, Duration)>,
+    source: T,
+}
+
+impl Clock<WallClock> {
+    pub fn new(control: TimeControl) -> Clock<WallClock> {
+        Clock::with_source(control, WallClock::new())
+    }
+}
+
+impl<T: TimeSource> Clock<T> {
+    pub fn with_source(control: TimeControl, source: T) -> Clock<T> {
+        let initial = match control {
+            TimeControl::SuddenDeath(time) => time,
+            TimeControl::Increment { base, .. } => base,
+            TimeControl::MovesToGo { time, .. } => time,
+        };
+        Clock { control, remaining: [initial; 2], moves_played: [0; 2], running: None, source }
+    }
+
+    // Time left for `color`, counting its turn if it is running
+    pub fn remaining(&self, color: Color) -> Duration {
+        let remaining = self.remaining[color as usize];
+        match self.running {
+            Some((running, started)) if running == color => {
+                remaining.saturating_sub(self.source.now().saturating_sub(started))
+            }
+            _ => remaining,
+        }
+    }
+
+    // Moves `color` still has to make before the next time control, if any
+    pub fn moves_to_go(&self, color: Color) -> Option<u32> {
+        match self.control {
+            TimeControl::MovesToGo { moves, .. } => {
+                Some(moves.get() - self.moves_played[color as usize] % moves.get())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn increment(&self) -> Duration {
+        match self.control {
+            TimeControl::Increment { increment, .. } => increment,
+            _ => Duration::ZERO,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    // Starts `color`'s clock, stopping the other side's turn first
+    pub fn start_turn(&mut self, color: Color) {
+        if self.running.is_some() {
+            self.stop_turn();
+        }
+        self.running = Some((color, self.source.now()));
+    }
+
+    // Stops the running clock, charging the time used and crediting the
+    // increment or the next control's time. A player who has already run
+    // out gets nothing back. Returns the time the turn took.
+    pub fn stop_turn(&mut self) -> Duration {
+        let (color, started) = match self.running.take() {
+            Some(running) => running,
+            None => return Duration::ZERO,
+        };
+        let used = self.source.now().saturating_sub(started);
+        let index = color as usize;
+        if used >= self.remaining[index] {
+            self.remaining[index] = Duration::ZERO;
+            return used;
+        }
+
+        self.remaining[index] -= used;
+        self.moves_played[index] += 1;
+        match self.control {
+            TimeControl::SuddenDeath(_) => (),
+            TimeControl::Increment { increment, .. } => self.remaining[index] += increment,
+            TimeControl::MovesToGo { moves, time } => {
+                if self.moves_played[index].is_multiple_of(moves.get()) {
+                    self.remaining[index] += time;
+                }
+            }
+        }
+        used
+    }
+
+    // `color` has run out of time and loses on time
+    pub fn flag(&self, color: Color) -> bool {
+        self.remaining(color) == Duration::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // Time that only moves when the test says so; copies share it
+    #[derive(Debug, Clone, Default)]
+    struct ManualTime(Rc<Cell<Duration>>);
+
+    impl ManualTime {
+        fn advance(&self, millis: u64) {
+            self.0.set(self.0.get() + Duration::from_millis(millis));
+        }
+    }
+
+    impl TimeSource for ManualTime {
+        fn now(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn sudden_death_counts_down_the_side_to_move() {
+        let time = ManualTime::default();
+        let mut clock = Clock::with_source(TimeControl::SuddenDeath(ms(1000)), time.clone());
+
+        clock.start_turn(Color::White);
+        time.advance(300);
+        assert_eq!(clock.remaining(Color::White), ms(700));
+        assert_eq!(clock.remaining(Color::Black), ms(1000));
+
+        clock.start_turn(Color::Black);
+        time.advance(200);
+        assert_eq!(clock.stop_turn(), ms(200));
+        assert_eq!(clock.remaining(Color::White), ms(700));
+        assert_eq!(clock.remaining(Color::Black), ms(800));
+        assert!(!clock.is_running());
+
+        // Stopped clocks don't run
+        time.advance(5000);
+        assert_eq!(clock.remaining(Color::Black), ms(800));
+        assert_eq!(clock.moves_to_go(Color::White), None);
+
+        let wall = Clock::new(TimeControl::SuddenDeath(ms(1000)));
+        assert_eq!(wall.remaining(Color::White), ms(1000));
+    }
+
+    #[test]
+    fn increment_is_added_after_each_move() {
+        let time = ManualTime::default();
+        let control = TimeControl::Increment { base: ms(1000), increment: ms(100) };
+        let mut clock = Clock::with_source(control, time.clone());
+        assert_eq!(clock.increment(), ms(100));
+
+        clock.start_turn(Color::White);
+        time.advance(50);
+        clock.stop_turn();
+        assert_eq!(clock.remaining(Color::White), ms(1050));
+    }
+
+    #[test]
+    fn moves_to_go_adds_time_per_control() {
+        let time = ManualTime::default();
+        let control = TimeControl::MovesToGo { moves: NonZeroU32::new(2).unwrap(), time: ms(1000) };
+        let mut clock = Clock::with_source(control, time.clone());
+        assert_eq!(clock.moves_to_go(Color::Black), Some(2));
+
+        for (moves_to_go, remaining) in [(1, 900), (2, 1800), (1, 1700)] {
+            clock.start_turn(Color::Black);
+            time.advance(100);
+            clock.stop_turn();
+            assert_eq!(clock.moves_to_go(Color::Black), Some(moves_to_go));
+            assert_eq!(clock.remaining(Color::Black), ms(remaining));
+        }
+    }
+
+    #[test]
+    fn running_out_flags_the_player() {
+        let time = ManualTime::default();
+        let control = TimeControl::Increment { base: ms(500), increment: ms(1000) };
+        let mut clock = Clock::with_source(control, time.clone());
+
+        clock.start_turn(Color::White);
+        time.advance(499);
+        assert!(!clock.flag(Color::White));
+        time.advance(1);
+        assert!(clock.flag(Color::White));
+        assert!(!clock.flag(Color::Black));
+
+        // Moving after the flag fell earns no increment
+        clock.stop_turn();
+        assert!(clock.flag(Color::White));
+        assert_eq!(clock.remaining(Color::White), Duration::ZERO);
+    }
+
+    #[test]
+    fn clones_keep_their_own_turn() {
+        let time = ManualTime::default();
+        let mut clock = Clock::with_source(TimeControl::SuddenDeath(ms(1000)), time.clone());
+        clock.start_turn(Color::White);
+        let snapshot = clock.clone();
+        time.advance(400);
+        clock.stop_turn();
+
+        assert_eq!(clock.remaining(Color::White), ms(600));
+        assert!(snapshot.is_running());
+        assert_eq!(snapshot.remaining(Color::White), ms(600));
+    }
+}
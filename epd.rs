@@ -0,0 +1,179 @@
+use std::fmt;
+use crate::game::*;
+use crate::movegen::Move;
+
+// The operations of an EPD record that the test-suite runner understands;
+// other opcodes are skipped.
+#[derive(Debug, Default, PartialEq)]
+pub struct EpdOps {
+    pub bm: Vec<Move>,
+    pub am: Vec<Move>,
+    pub id: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EpdError {
+    MissingFields(String),
+    Fen(FenError),
+    Move(String),
+    Operand(String),
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpdError::MissingFields(line) => write!(f, "EPD needs four position fields: '{}'", line),
+            EpdError::Fen(err) => write!(f, "{}", err),
+            EpdError::Move(msg) => write!(f, "{}", msg),
+            EpdError::Operand(op) => write!(f, "Invalid operand: '{}'", op),
+        }
+    }
+}
+
+// Parses "<board> <color> <castling> <en passant> <op> <operands>; ..." with
+// the `bm`/`am` moves in SAN resolved against the position.
+pub fn parse_epd(line: &str) -> Result<(Game, EpdOps), EpdError> {
+    let mut rest = line.trim();
+    let mut fields = Vec::new();
+    for _ in 0..4 {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            return Err(EpdError::MissingFields(line.to_string()));
+        }
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+
+    let mut game = Game::try_read_FEN(&format!("{} 0 1", fields.join(" "))).map_err(EpdError::Fen)?;
+    let mut ops = EpdOps::default();
+
+    for operation in rest.split(';') {
+        let operation = operation.trim();
+        if operation.is_empty() {
+            continue;
+        }
+
+        let end = operation.find(char::is_whitespace).unwrap_or(operation.len());
+        let (opcode, operands) = (&operation[..end], operation[end..].trim());
+        match opcode {
+            "bm" | "am" => {
+                let mut moves = Vec::new();
+                for san in operands.split_whitespace() {
+                    moves.push(game.san_to_move(san).map_err(EpdError::Move)?);
+                }
+                if opcode == "bm" {
+                    ops.bm = moves;
+                } else {
+                    ops.am = moves;
+                }
+            }
+            "id" => ops.id = Some(operands.trim_matches('"').to_string()),
+            "hmvc" => game.halfmove_clock = operands.parse()
+                .map_err(|_| EpdError::Operand(operation.to_string()))?,
+            "fmvn" => game.fullmove_number = operands.parse()
+                .map_err(|_| EpdError::Operand(operation.to_string()))?,
+            _ => (),
+        }
+    }
+
+    Ok((game, ops))
+}
+
+#[derive(Debug, Default)]
+pub struct EpdSuiteResult {
+    pub positions: usize,
+    pub solved: usize,
+    // Id (or line number) and the move the engine chose, for each miss
+    pub failed: Vec<(String, String)>,
+}
+
+impl fmt::Display for EpdSuiteResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Solved {}/{} positions", self.solved, self.positions)?;
+        for (id, played) in &self.failed {
+            write!(f, "\n  {}: played {}", id, played)?;
+        }
+        Ok(())
+    }
+}
+
+// Searches every position of an EPD suite (one record per line, `#` starts
+// a comment) to a fixed depth. A position counts as solved when the move
+// found is one of its `bm` moves and none of its `am` moves.
+pub fn run_epd_suite(suite: &str, depth: u8) -> Result<EpdSuiteResult, EpdError> {
+    let mut result = EpdSuiteResult::default();
+
+    for (number, line) in suite.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (mut game, ops) = parse_epd(line)?;
+        let (best, _) = game.search(depth);
+        result.positions += 1;
+
+        let solved = match best {
+            Some(mv) => (ops.bm.is_empty() || ops.bm.contains(&mv)) && !ops.am.contains(&mv),
+            None => false,
+        };
+
+        if solved {
+            result.solved += 1;
+        } else {
+            let id = ops.id.unwrap_or_else(|| format!("line {}", number + 1));
+            let played = match best {
+                Some(mv) => game.move_to_san(&mv),
+                None => "nothing".to_string(),
+            };
+            result.failed.push((id, played));
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_epd_fields_and_operations() {
+        let (game, ops) = parse_epd("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - \
+                                     bm Bb5 Bc4; am Ng5; id \"Ruy.001\"; c0 \"comment\";").unwrap();
+        assert_eq!(game.to_FEN(), "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 1");
+        assert_eq!(ops.bm, vec![game.san_to_move("Bb5").unwrap(), game.san_to_move("Bc4").unwrap()]);
+        assert_eq!(ops.am, vec![game.san_to_move("Ng5").unwrap()]);
+        assert_eq!(ops.id, Some("Ruy.001".to_string()));
+    }
+
+    #[test]
+    fn parse_epd_move_counters() {
+        let (game, ops) = parse_epd("4k3/8/8/8/8/8/8/4K3 b - - hmvc 12; fmvn 40;").unwrap();
+        assert_eq!((game.halfmove_clock, game.fullmove_number), (12, 40));
+        assert_eq!(ops, EpdOps::default());
+    }
+
+    #[test]
+    fn parse_epd_errors() {
+        assert!(matches!(parse_epd("4k3/8/8/8/8/8/8/4K3 w"), Err(EpdError::MissingFields(_))));
+        assert!(matches!(parse_epd("4k3/8/8/8/8/8/8/4K3 x - - bm Kd2;"), Err(EpdError::Fen(_))));
+        assert!(matches!(parse_epd("4k3/8/8/8/8/8/8/4K3 w - - bm Ke3;"), Err(EpdError::Move(_))));
+        assert!(matches!(parse_epd("4k3/8/8/8/8/8/8/4K3 w - - hmvc x;"), Err(EpdError::Operand(_))));
+    }
+
+    #[test]
+    fn run_epd_suite_counts_solved_positions() {
+        let suite = "# mate, a free queen and a poisoned pawn
+6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"mate.1\";
+4k3/8/8/3q4/8/8/3R4/4K3 w - - bm Rxd5; id \"queen.1\";
+4k3/8/4p3/3p4/8/8/8/3QK3 w - - am Qxd5; id \"poison.1\";
+4k3/8/8/3q4/8/8/3R4/4K3 w - - bm Kf2; id \"wrong.1\";
+";
+        let result = run_epd_suite(suite, 3).unwrap();
+        assert_eq!(result.positions, 4);
+        assert_eq!(result.solved, 3);
+        assert_eq!(result.failed, vec![("wrong.1".to_string(), "Rxd5".to_string())]);
+        assert!(result.to_string().starts_with("Solved 3/4 positions"));
+    }
+}
@@ -0,0 +1,806 @@
+use crate::attacks::*;
+use crate::game::*;
+use crate::utils::*;
+
+// Piece-square tables from White's point of view, indexed by square: the first
+// row is rank 1 (a1..h1) and the last rank 8. Black reads the same table at
+// `square ^ 56`, which flips the rank and keeps the file.
+static PAWN_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,   5,  10,  25,  25,  10,   5,   5,
+     10,  10,  20,  30,  30,  20,  10,  10,
+     50,  50,  50,  50,  50,  50,  50,  50,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+static KNIGHT_TABLE: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+static BISHOP_TABLE: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+static ROOK_TABLE: [i32; 64] = [
+      0,   0,   0,   5,   5,   0,   0,   0,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+      5,  10,  10,  10,  10,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+static QUEEN_TABLE: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+static KING_TABLE: [i32; 64] = [
+     20,  30,  10,   0,   0,  10,  30,  20,
+     20,  20,   0,   0,   0,   0,  20,  20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+];
+// Endgame tables for the pieces whose best squares change once the queens
+// and most pieces are gone: the king heads for the centre and passers run.
+static PAWN_ENDGAME_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     10,  10,  10,  10,  10,  10,  10,  10,
+     10,  10,  10,  10,  10,  10,  10,  10,
+     20,  20,  20,  20,  20,  20,  20,  20,
+     30,  30,  30,  30,  30,  30,  30,  30,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     80,  80,  80,  80,  80,  80,  80,  80,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+static KING_ENDGAME_TABLE: [i32; 64] = [
+    -50, -30, -30, -30, -30, -30, -30, -50,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -50, -40, -30, -20, -20, -30, -40, -50,
+];
+
+// The phase is MIDGAME_PHASE with all minor and major pieces on the board
+// and 0 once they are all gone
+pub const MIDGAME_PHASE: i32 = 256;
+
+// Phase weights of the non-pawn pieces; the starting army adds up to 24
+const PHASE_WEIGHTS: [(PieceType, i32); 4] = [(PieceType::Knight, 1), (PieceType::Bishop, 1),
+                                              (PieceType::Rook, 2), (PieceType::Queen, 4)];
+const TOTAL_PHASE_WEIGHT: i32 = 24;
+
+// Passed pawn knowledge only applies at or below this phase
+const ENDGAME_PHASE: i32 = MIDGAME_PHASE / 4;
+// At most a minor piece and a rook traded
+const OPENING_PHASE: i32 = MIDGAME_PHASE * 7 / 8;
+
+// The stage of the game by the non-pawn material left, for callers that
+// only need to know which side of a threshold the phase is on
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Phase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+// Every weight of the evaluation, so that a tuner can vary them. The
+// defaults are the values the engine plays with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalParams {
+    // Indexed by PieceType
+    pub piece_values: [i32; 6],
+    // Piece-square values are scaled by this, in percent
+    pub psqt_scale: i32,
+    pub doubled_pawn_penalty: i32,
+    pub isolated_pawn_penalty: i32,
+    // Indexed by rank counted from the pawn's own side
+    pub passed_pawn_bonus: [i32; 8],
+    pub passed_pawn_endgame_bonus: [i32; 8],
+    // Per square of king distance to the square in front of a passed pawn
+    pub king_passer_distance_weight: i32,
+    // Mop-up against a lone king: per square the king stands from the
+    // centre, and per square the attacking king is closer than the
+    // farthest apart
+    pub mopup_center_weight: i32,
+    pub mopup_king_distance_weight: i32,
+    pub pawn_shield_bonus: i32,
+    pub half_open_file_penalty: i32,
+    pub open_file_penalty: i32,
+    // Per square next to the king that an enemy piece attacks
+    pub king_zone_attack_penalty: i32,
+    pub bishop_pair_bonus: i32,
+    // Rooks on files without pawns of their own side
+    pub rook_open_file_bonus: i32,
+    pub rook_half_open_file_bonus: i32,
+    // Per square a piece can move to that isn't occupied by its own side
+    pub mobility_weights: [(PieceType, i32); 4],
+}
+
+pub const DEFAULT_EVAL_PARAMS: EvalParams = EvalParams {
+    piece_values: [PieceType::Pawn.material_value(), PieceType::Rook.material_value(),
+                   PieceType::Knight.material_value(), PieceType::Bishop.material_value(),
+                   PieceType::Queen.material_value(), PieceType::King.material_value()],
+    psqt_scale: 100,
+    doubled_pawn_penalty: 15,
+    isolated_pawn_penalty: 15,
+    passed_pawn_bonus: [0, 5, 10, 20, 35, 60, 100, 0],
+    passed_pawn_endgame_bonus: [0, 10, 20, 40, 70, 120, 200, 0],
+    king_passer_distance_weight: 10,
+    mopup_center_weight: 10,
+    mopup_king_distance_weight: 4,
+    pawn_shield_bonus: 10,
+    half_open_file_penalty: 15,
+    open_file_penalty: 25,
+    king_zone_attack_penalty: 5,
+    bishop_pair_bonus: 30,
+    rook_open_file_bonus: 20,
+    rook_half_open_file_bonus: 10,
+    mobility_weights: [(PieceType::Knight, 4), (PieceType::Bishop, 5), (PieceType::Rook, 2), (PieceType::Queen, 1)],
+};
+
+impl Default for EvalParams {
+    fn default() -> EvalParams {
+        DEFAULT_EVAL_PARAMS
+    }
+}
+
+// Rank counted from `color`'s side, 0 for its back rank
+fn relative_rank(color: Color, square: usize) -> usize {
+    let rank = Square::from_index(square).rank() as usize;
+    match color {
+        Color::White => rank,
+        Color::Black => 7 - rank,
+    }
+}
+
+// King moves between two squares
+fn chebyshev_distance(a: usize, b: usize) -> i32 {
+    let (a, b) = (Square::from_index(a), Square::from_index(b));
+    let files = (a.file() as i32 - b.file() as i32).abs();
+    let ranks = (a.rank() as i32 - b.rank() as i32).abs();
+    files.max(ranks)
+}
+
+// Files plus ranks from the four centre squares: 0 on e4, 6 in a corner
+fn center_distance(square: usize) -> i32 {
+    let square = Square::from_index(square);
+    let from_center = |line: u8| (3 - line as i32).max(line as i32 - 4);
+    from_center(square.file()) + from_center(square.rank())
+}
+
+fn manhattan_distance(a: usize, b: usize) -> i32 {
+    let (a, b) = (Square::from_index(a), Square::from_index(b));
+    (a.file() as i32 - b.file() as i32).abs() + (a.rank() as i32 - b.rank() as i32).abs()
+}
+
+// Midgame and endgame tables
+fn piece_square_tables(pt: PieceType) -> (&'static [i32; 64], &'static [i32; 64]) {
+    match pt {
+        PieceType::Pawn => (&PAWN_TABLE, &PAWN_ENDGAME_TABLE),
+        PieceType::Knight => (&KNIGHT_TABLE, &KNIGHT_TABLE),
+        PieceType::Bishop => (&BISHOP_TABLE, &BISHOP_TABLE),
+        PieceType::Rook => (&ROOK_TABLE, &ROOK_TABLE),
+        PieceType::Queen => (&QUEEN_TABLE, &QUEEN_TABLE),
+        PieceType::King => (&KING_TABLE, &KING_ENDGAME_TABLE),
+    }
+}
+
+// Midgame and endgame piece-square values
+pub fn piece_square_value(pt: PieceType, color: Color, square: usize) -> (i32, i32) {
+    let index = match color {
+        Color::White => square,
+        Color::Black => square ^ 56,
+    };
+    let (midgame, endgame) = piece_square_tables(pt);
+    (midgame[index], endgame[index])
+}
+
+// Blends midgame and endgame scores by the phase
+pub fn taper(midgame: i32, endgame: i32, phase: i32) -> i32 {
+    (midgame * phase + endgame * (MIDGAME_PHASE - phase)) / MIDGAME_PHASE
+}
+
+impl Game {
+    // 0 (pawn endgame) to MIDGAME_PHASE, from the non-pawn material left
+    pub fn phase_value(&self) -> i32 {
+        let mut weight = 0;
+        for (pt, value) in PHASE_WEIGHTS {
+            for color in [Color::White, Color::Black] {
+                weight += value * self.count_pieces(color, pt) as i32;
+            }
+        }
+        (weight.min(TOTAL_PHASE_WEIGHT) * MIDGAME_PHASE + TOTAL_PHASE_WEIGHT / 2) / TOTAL_PHASE_WEIGHT
+    }
+
+    pub fn game_phase(&self) -> Phase {
+        match self.phase_value() {
+            phase if phase >= OPENING_PHASE => Phase::Opening,
+            phase if phase > ENDGAME_PHASE => Phase::Middlegame,
+            _ => Phase::Endgame,
+        }
+    }
+
+    // Doubled and isolated pawn penalties plus passed pawn bonuses
+    fn pawn_structure_score(&self, color: Color, params: &EvalParams) -> i32 {
+        let pawns = self.pieces_of(color, PieceType::Pawn);
+        let mut score = 0;
+
+        for (file, mask) in FILE_MASKS.iter().enumerate() {
+            let count = (pawns & mask).count_ones() as i32;
+            if count > 1 {
+                score -= params.doubled_pawn_penalty * (count - 1);
+            }
+            if count > 0 && pawns & adjacent_files_mask(file) == 0 {
+                score -= params.isolated_pawn_penalty * count;
+            }
+        }
+
+        let mut remaining = pawns;
+        while remaining != 0 {
+            let square = remaining.trailing_zeros() as usize;
+            remaining &= remaining - 1;
+
+            if self.is_passed_pawn(color, square) {
+                score += params.passed_pawn_bonus[relative_rank(color, square)];
+            }
+        }
+
+        score
+    }
+
+    // No enemy pawn in front of the pawn on its own or an adjacent file
+    fn is_passed_pawn(&self, color: Color, square: usize) -> bool {
+        self.pieces_of(color.opposite(), PieceType::Pawn) & passed_pawn_mask(color, square) == 0
+    }
+
+    // Once few pieces are left, passed pawns are worth far more the further
+    // they are, and so is having the own king near them and the enemy king
+    // away from the square in front of them
+    pub fn passed_pawn_endgame(&self, color: Color, params: &EvalParams) -> i32 {
+        if self.game_phase() != Phase::Endgame {
+            return 0;
+        }
+        let (king, enemy_king) = match (self.king_square(color), self.king_square(color.opposite())) {
+            (Some(king), Some(enemy_king)) => (king, enemy_king),
+            _ => return 0,
+        };
+
+        let mut score = 0;
+        let mut pawns = self.pieces_of(color, PieceType::Pawn);
+        while pawns != 0 {
+            let square = pawns.trailing_zeros() as usize;
+            pawns &= pawns - 1;
+            if !self.is_passed_pawn(color, square) {
+                continue;
+            }
+
+            let stop = match color {
+                Color::White => square + 8,
+                Color::Black => square - 8,
+            };
+            score += params.passed_pawn_endgame_bonus[relative_rank(color, square)]
+                + params.king_passer_distance_weight
+                    * (chebyshev_distance(enemy_king, stop) - chebyshev_distance(king, stop));
+        }
+        score
+    }
+
+    // Pawns in front of the king, missing pawns on the files around it and
+    // enemy pieces bearing on the squares next to it. Only meaningful in the
+    // middlegame, so evaluate() tapers it out with the phase.
+    fn king_safety(&self, color: Color, params: &EvalParams) -> i32 {
+        let king = match self.king_square(color) {
+            Some(king) => king,
+            None => return 0,
+        };
+        let pawns = self.pieces_of(color, PieceType::Pawn);
+        let enemy_pawns = self.pieces_of(color.opposite(), PieceType::Pawn);
+        let file = Square::from_index(king).file() as usize;
+        let files = FILE_MASKS[file] | adjacent_files_mask(file);
+        let mut score = 0;
+
+        // The two ranks in front of the king
+        let shield_ranks = ranks_ahead(color, king) & match color {
+            Color::White => !ranks_ahead(color, (king + 16).min(63)),
+            Color::Black => !ranks_ahead(color, king.saturating_sub(16)),
+        };
+        score += params.pawn_shield_bonus * (pawns & files & shield_ranks).count_ones() as i32;
+
+        for mask in FILE_MASKS {
+            if files & mask == 0 || pawns & mask != 0 {
+                continue;
+            }
+            score -= if enemy_pawns & mask == 0 {
+                params.open_file_penalty
+            } else {
+                params.half_open_file_penalty
+            };
+        }
+
+        let zone = king_attacks(king);
+        let occupancy = self.occupancy();
+        for pt in [PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen] {
+            let mut attackers = self.pieces_of(color.opposite(), pt);
+            while attackers != 0 {
+                let from = attackers.trailing_zeros() as usize;
+                attackers &= attackers - 1;
+                let attacks = match pt {
+                    PieceType::Knight => knight_attacks(from),
+                    PieceType::Bishop => bishop_attacks(from, occupancy),
+                    PieceType::Rook => rook_attacks(from, occupancy),
+                    _ => queen_attacks(from, occupancy),
+                };
+                score -= params.king_zone_attack_penalty * (attacks & zone).count_ones() as i32;
+            }
+        }
+
+        score
+    }
+
+    // With a rook or queen against a bare king, material alone doesn't show
+    // the way to mate: reward driving the king to the edge and bringing
+    // the attacking king up. From White's point of view, 0 otherwise.
+    pub fn mopup_score(&self, params: &EvalParams) -> i32 {
+        for attacker in [Color::White, Color::Black] {
+            let defender = attacker.opposite();
+            let majors = self.pieces_of(attacker, PieceType::Rook) | self.pieces_of(attacker, PieceType::Queen);
+            let lone_king = self.position.color_bitboards[defender as usize]
+                == self.pieces_of(defender, PieceType::King);
+            if majors == 0 || !lone_king {
+                continue;
+            }
+            let (king, enemy_king) = match (self.king_square(attacker), self.king_square(defender)) {
+                (Some(king), Some(enemy_king)) => (king, enemy_king),
+                _ => return 0,
+            };
+            let score = params.mopup_center_weight * center_distance(enemy_king)
+                + params.mopup_king_distance_weight * (14 - manhattan_distance(king, enemy_king));
+            return match attacker {
+                Color::White => score,
+                Color::Black => -score,
+            };
+        }
+        0
+    }
+
+    // Bishop pair bonus plus bonuses for rooks on open and half-open files
+    pub fn imbalance_score(&self, color: Color, params: &EvalParams) -> i32 {
+        let pawns = self.pieces_of(color, PieceType::Pawn);
+        let enemy_pawns = self.pieces_of(color.opposite(), PieceType::Pawn);
+        let mut score = 0;
+
+        if self.count_pieces(color, PieceType::Bishop) >= 2 {
+            score += params.bishop_pair_bonus;
+        }
+
+        for rook in self.pieces_iter(color).filter(|piece| piece.piece_type == PieceType::Rook) {
+            let file = FILE_MASKS[file_of(rook.position) as usize];
+            if pawns & file != 0 {
+                continue;
+            }
+            score += if enemy_pawns & file == 0 {
+                params.rook_open_file_bonus
+            } else {
+                params.rook_half_open_file_bonus
+            };
+        }
+
+        score
+    }
+
+    // Weighted count of the squares the minor and major pieces attack,
+    // leaving out those held by their own side
+    pub fn mobility(&self, color: Color, params: &EvalParams) -> i32 {
+        let occupancy = self.occupancy();
+        let own = self.position.color_bitboards[color as usize];
+        let mut score = 0;
+        for (pt, weight) in params.mobility_weights {
+            let mut pieces = self.pieces_of(color, pt);
+            while pieces != 0 {
+                let from = pieces.trailing_zeros() as usize;
+                pieces &= pieces - 1;
+                let attacks = match pt {
+                    PieceType::Knight => knight_attacks(from),
+                    PieceType::Bishop => bishop_attacks(from, occupancy),
+                    PieceType::Rook => rook_attacks(from, occupancy),
+                    _ => queen_attacks(from, occupancy),
+                };
+                score += weight * (attacks & !own).count_ones() as i32;
+            }
+        }
+        score
+    }
+
+    // Material plus piece-square score from scratch; `psqt` holds the
+    // same value maintained by make_move and unmake_move
+    pub fn compute_psqt(&self) -> (i32, i32) {
+        self.compute_psqt_with(&DEFAULT_EVAL_PARAMS)
+    }
+
+    fn compute_psqt_with(&self, params: &EvalParams) -> (i32, i32) {
+        let mut midgame = 0;
+        let mut endgame = 0;
+        for piece in self.all_pieces() {
+            let square = piece.position.trailing_zeros() as usize;
+            let material = params.piece_values[piece.piece_type as usize];
+            let (mg, eg) = piece_square_value(piece.piece_type, piece.color, square);
+            let (mg, eg) = (mg * params.psqt_scale / 100, eg * params.psqt_scale / 100);
+            match piece.color {
+                Color::White => {
+                    midgame += material + mg;
+                    endgame += material + eg;
+                }
+                Color::Black => {
+                    midgame -= material + mg;
+                    endgame -= material + eg;
+                }
+            }
+        }
+        (midgame, endgame)
+    }
+
+    // Static evaluation in centipawns from the side to move's point of view
+    pub fn evaluate(&self) -> i32 {
+        self.evaluate_with(&DEFAULT_EVAL_PARAMS)
+    }
+
+    // The same evaluation under other weights, for tuning them
+    pub fn evaluate_with(&self, params: &EvalParams) -> i32 {
+        self.evaluate_terms(self.compute_psqt_with(params), params)
+    }
+
+    // Same as evaluate() but reading the incremental material and
+    // piece-square score, for use inside the search
+    pub fn evaluate_incremental(&self) -> i32 {
+        self.evaluate_terms(self.psqt, &DEFAULT_EVAL_PARAMS)
+    }
+
+    fn evaluate_terms(&self, (mut midgame, endgame): (i32, i32), params: &EvalParams) -> i32 {
+        midgame += self.king_safety(Color::White, params) - self.king_safety(Color::Black, params);
+
+        let score = taper(midgame, endgame, self.phase_value())
+            + self.pawn_structure_score(Color::White, params)
+            - self.pawn_structure_score(Color::Black, params)
+            + self.mobility(Color::White, params)
+            - self.mobility(Color::Black, params)
+            + self.imbalance_score(Color::White, params)
+            - self.imbalance_score(Color::Black, params)
+            + self.passed_pawn_endgame(Color::White, params)
+            - self.passed_pawn_endgame(Color::Black, params)
+            + self.mopup_score(params);
+        score * self.side_sign()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::Move;
+
+    const PARAMS: EvalParams = DEFAULT_EVAL_PARAMS;
+
+    #[test]
+    fn initial_position_is_balanced() {
+        assert_eq!(Game::initialize().evaluate(), 0);
+    }
+
+    #[test]
+    fn evaluation_is_relative_to_side_to_move() {
+        let white = Game::read_FEN("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        let black = Game::read_FEN("4k3/8/8/8/8/8/8/3QK3 b - - 0 1");
+        assert!(white.evaluate() > 800);
+        assert_eq!(black.evaluate(), -white.evaluate());
+    }
+
+    #[test]
+    fn centralized_knight_is_better() {
+        let center = Game::read_FEN("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1");
+        let corner = Game::read_FEN("4k3/8/8/8/8/8/8/N3K3 w - - 0 1");
+        assert!(center.evaluate() > corner.evaluate());
+    }
+
+    #[test]
+    fn game_phase_follows_non_pawn_material() {
+        assert_eq!(Game::initialize().phase_value(), MIDGAME_PHASE);
+        assert_eq!(Game::read_FEN("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1").phase_value(), 0);
+        assert_eq!(Game::read_FEN("3qk3/8/8/8/8/8/8/3QK3 w - - 0 1").phase_value(), MIDGAME_PHASE / 3);
+    }
+
+    #[test]
+    fn game_phase_classifies_by_material() {
+        assert_eq!(Game::initialize().game_phase(), Phase::Opening);
+        // Queens off
+        let middlegame = Game::read_FEN("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1");
+        assert_eq!(middlegame.game_phase(), Phase::Middlegame);
+        // A rook each
+        let endgame = Game::read_FEN("r3k3/pppp4/8/8/8/8/PPPP4/R3K3 w - - 0 1");
+        assert_eq!(endgame.game_phase(), Phase::Endgame);
+        assert_eq!(Game::read_FEN("4k3/8/8/8/8/8/8/4K3 w - - 0 1").game_phase(), Phase::Endgame);
+    }
+
+    #[test]
+    fn king_placement_depends_on_phase() {
+        // Kings and pawns only: the central king is better
+        let central = Game::read_FEN("4k3/pppp4/8/8/4K3/8/PPPP4/8 w - - 0 1");
+        let sheltered = Game::read_FEN("4k3/pppp4/8/8/8/8/PPPP4/6K1 w - - 0 1");
+        assert!(central.evaluate() > sheltered.evaluate());
+
+        // With everything on the board the king belongs behind its pawns
+        let central = Game::read_FEN("rnbqkbnr/pppppppp/8/8/4K3/8/PPPPPPPP/RNBQ1BNR w kq - 0 1");
+        assert!(central.evaluate() < Game::initialize().evaluate());
+    }
+
+    #[test]
+    fn doubled_and_isolated_pawns_are_penalized() {
+        let healthy = Game::read_FEN("4k3/3pp3/8/8/8/8/3PP3/4K3 w - - 0 1");
+        assert_eq!(healthy.pawn_structure_score(Color::White, &PARAMS), 0);
+        assert_eq!(healthy.pawn_structure_score(Color::Black, &PARAMS), 0);
+
+        let doubled = Game::read_FEN("4k3/3pp3/8/8/8/4P3/4P3/4K3 w - - 0 1");
+        assert_eq!(doubled.pawn_structure_score(Color::White, &PARAMS),
+                   -PARAMS.doubled_pawn_penalty - 2 * PARAMS.isolated_pawn_penalty);
+        assert!(doubled.evaluate() < healthy.evaluate());
+    }
+
+    #[test]
+    fn passed_pawns_are_rewarded_by_rank() {
+        let fifth = Game::read_FEN("4k3/p7/8/3P4/8/8/8/4K3 w - - 0 1");
+        let sixth = Game::read_FEN("4k3/p7/3P4/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(fifth.pawn_structure_score(Color::White, &PARAMS), PARAMS.passed_pawn_bonus[4] - PARAMS.isolated_pawn_penalty);
+        assert!(sixth.pawn_structure_score(Color::White, &PARAMS) > fifth.pawn_structure_score(Color::White, &PARAMS));
+
+        // The mirrored position scores the same for Black
+        let black = Game::read_FEN("4k3/8/8/8/3p4/8/P7/4K3 w - - 0 1");
+        assert_eq!(black.pawn_structure_score(Color::Black, &PARAMS), fifth.pawn_structure_score(Color::White, &PARAMS));
+
+        // An enemy pawn in front on an adjacent file stops it being passed
+        let blocked = Game::read_FEN("4k3/4p3/8/3P4/8/8/8/4K3 w - - 0 1");
+        assert_eq!(blocked.pawn_structure_score(Color::White, &PARAMS), -PARAMS.isolated_pawn_penalty);
+    }
+
+    #[test]
+    fn advancing_a_passed_pawn_in_the_endgame_gains() {
+        // The king escorts the pawn up the e-file, the defender is stuck in the corner
+        let mut previous = 0;
+        for fen in ["k7/8/8/8/8/3KP3/8/8 w - - 0 1",
+                    "k7/8/8/8/3KP3/8/8/8 w - - 0 1",
+                    "k7/8/8/3KP3/8/8/8/8 w - - 0 1",
+                    "k7/8/3KP3/8/8/8/8/8 w - - 0 1",
+                    "k7/3KP3/8/8/8/8/8/8 w - - 0 1"] {
+            let game = Game::read_FEN(fen);
+            assert!(game.evaluate() > previous, "{}", fen);
+            assert!(game.passed_pawn_endgame(Color::White, &PARAMS) > 0, "{}", fen);
+            previous = game.evaluate();
+        }
+
+        // The defending king in front of the pawn cuts the bonus
+        let blocked = Game::read_FEN("8/8/8/4k3/8/4P3/8/4K3 w - - 0 1");
+        let escorted = Game::read_FEN("k7/8/8/8/4K3/4P3/8/8 w - - 0 1");
+        assert!(blocked.passed_pawn_endgame(Color::White, &PARAMS) < escorted.passed_pawn_endgame(Color::White, &PARAMS));
+        assert_eq!(blocked.passed_pawn_endgame(Color::Black, &PARAMS), 0);
+
+        // With the heavy pieces still on it doesn't apply
+        let middlegame = Game::read_FEN("r2qk2r/8/8/8/8/4P3/8/R2QK2R w - - 0 1");
+        assert_eq!(middlegame.passed_pawn_endgame(Color::White, &PARAMS), 0);
+    }
+
+    #[test]
+    fn sheltered_king_is_safer_than_an_exposed_one() {
+        let castled = Game::read_FEN("r5k1/5ppp/8/8/8/8/5PPP/1Q4K1 w - - 0 1");
+        let exposed = Game::read_FEN("r5k1/5ppp/8/8/8/8/PPP5/1Q4K1 w - - 0 1");
+        assert_eq!(castled.king_safety(Color::White, &PARAMS), 3 * PARAMS.pawn_shield_bonus);
+        assert!(castled.king_safety(Color::White, &PARAMS) > exposed.king_safety(Color::White, &PARAMS));
+        assert!(castled.evaluate() > exposed.evaluate());
+
+        // A queen bearing on the squares around the king
+        let quiet = Game::read_FEN("r5k1/5ppp/8/8/8/8/5PPP/2Q3K1 b - - 0 1");
+        let attacked = Game::read_FEN("r5k1/5ppp/8/8/8/2Q5/5PPP/6K1 b - - 0 1");
+        assert!(attacked.king_safety(Color::Black, &PARAMS) < quiet.king_safety(Color::Black, &PARAMS));
+    }
+
+    #[test]
+    fn king_safety_fades_in_the_endgame() {
+        // Kings and blocked pawns only; the kings' endgame squares are
+        // worth the same
+        let castled = Game::read_FEN("4k3/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let exposed = Game::read_FEN("4k3/5ppp/8/8/8/8/5PPP/1K6 w - - 0 1");
+        assert!(castled.king_safety(Color::White, &PARAMS) > exposed.king_safety(Color::White, &PARAMS));
+        assert_eq!(castled.evaluate(), exposed.evaluate());
+    }
+
+    #[test]
+    fn cramped_pieces_have_less_mobility() {
+        // The same bishop hemmed in by its own pawns on a1 or free on d4
+        let cramped = Game::read_FEN("4k3/8/8/8/8/1P6/PP6/B3K3 w - - 0 1");
+        let free = Game::read_FEN("4k3/8/8/8/3B4/1P6/PP6/4K3 w - - 0 1");
+        assert_eq!(cramped.mobility(Color::White, &PARAMS), 0);
+        assert_eq!(free.mobility(Color::White, &PARAMS), 11 * 5);
+
+        // Captures count, squares held by the own side don't
+        let game = Game::read_FEN("4k3/8/8/8/8/8/1p6/N3K3 w - - 0 1");
+        assert_eq!(game.mobility(Color::White, &PARAMS), 2 * 4);
+        assert_eq!(Game::initialize().mobility(Color::White, &PARAMS), Game::initialize().mobility(Color::Black, &PARAMS));
+    }
+
+    #[test]
+    fn bishop_pair_bonus_needs_both_bishops() {
+        // Bxb5 Nxb5 trades White's light-squared bishop for Black's only one
+        let mut game = Game::read_FEN("4k3/8/3n4/1b6/8/8/8/2B1KB2 w - - 0 1");
+        assert_eq!(game.imbalance_score(Color::White, &PARAMS), PARAMS.bishop_pair_bonus);
+        assert_eq!(game.imbalance_score(Color::Black, &PARAMS), 0);
+
+        game.make_move(&Move::new(5, 33));
+        assert_eq!(game.imbalance_score(Color::White, &PARAMS), PARAMS.bishop_pair_bonus);
+        game.make_move(&Move::new(43, 33));
+        assert_eq!(game.imbalance_score(Color::White, &PARAMS), 0);
+        assert_eq!(game.imbalance_score(Color::Black, &PARAMS), 0);
+    }
+
+    #[test]
+    fn rooks_prefer_open_files() {
+        let open = Game::read_FEN("4k3/pp6/8/8/8/8/PP6/3RK3 w - - 0 1");
+        let half_open = Game::read_FEN("4k3/3p4/8/8/8/8/PP6/3RK3 w - - 0 1");
+        let closed = Game::read_FEN("4k3/8/8/8/8/8/3P4/3RK3 w - - 0 1");
+        assert_eq!(open.imbalance_score(Color::White, &PARAMS), PARAMS.rook_open_file_bonus);
+        assert_eq!(half_open.imbalance_score(Color::White, &PARAMS), PARAMS.rook_half_open_file_bonus);
+        assert_eq!(closed.imbalance_score(Color::White, &PARAMS), 0);
+        assert_eq!(Game::initialize().imbalance_score(Color::White, &PARAMS), PARAMS.bishop_pair_bonus);
+    }
+
+    #[test]
+    fn mopup_drives_the_lone_king_to_a_corner() {
+        // The black king on e5, f6, g7 and h8
+        let mut previous = None;
+        for fen in ["8/8/8/4k3/8/2K5/8/1Q6 w - - 0 1", "8/8/5k2/8/8/2K5/8/1Q6 w - - 0 1",
+                    "8/6k1/8/8/8/2K5/8/1Q6 w - - 0 1", "7k/8/8/8/8/2K5/8/1Q6 w - - 0 1"] {
+            let game = Game::read_FEN(fen);
+            let scores = (game.mopup_score(&PARAMS), game.evaluate());
+            if let Some((mopup, evaluation)) = previous {
+                assert!(scores.0 > mopup && scores.1 > evaluation, "{}: {:?}", fen, scores);
+            }
+            previous = Some(scores);
+        }
+
+        // The attacking king closing in counts too
+        let far = Game::read_FEN("7k/8/8/8/8/8/8/K5R1 w - - 0 1");
+        let near = Game::read_FEN("7k/8/5K2/8/8/8/8/6R1 w - - 0 1");
+        assert!(near.mopup_score(&PARAMS) > far.mopup_score(&PARAMS));
+        assert_eq!(Game::read_FEN("7K/8/8/8/8/8/8/k5r1 w - - 0 1").mopup_score(&PARAMS), -far.mopup_score(&PARAMS));
+
+        // Only against a bare king, and only with a rook or queen
+        assert_eq!(Game::read_FEN("7k/7p/8/8/8/8/8/K6R w - - 0 1").mopup_score(&PARAMS), 0);
+        assert_eq!(Game::read_FEN("7k/8/8/8/8/8/8/K5BN w - - 0 1").mopup_score(&PARAMS), 0);
+        assert_eq!(Game::initialize().mopup_score(&PARAMS), 0);
+    }
+
+    #[test]
+    fn evaluate_uses_the_default_params() {
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                    "7k/8/8/8/8/2K5/8/1Q6 b - - 0 1"] {
+            let game = Game::read_FEN(fen);
+            assert_eq!(game.evaluate(), game.evaluate_with(&EvalParams::default()), "{}", fen);
+            assert_eq!(game.evaluate(), game.evaluate_incremental(), "{}", fen);
+        }
+
+        // A knight up: worth more with dearer knights, the rest unchanged
+        let game = Game::read_FEN("4k3/8/8/8/8/8/8/4KN2 w - - 0 1");
+        let mut params = EvalParams::default();
+        params.piece_values[PieceType::Knight as usize] += 100;
+        assert_eq!(game.evaluate_with(&params), game.evaluate() + 100);
+        params.psqt_scale = 0;
+        assert_ne!(game.evaluate_with(&params), game.evaluate() + 100);
+    }
+
+    #[test]
+    fn piece_square_values_mirror_between_colors() {
+        for pt in PIECE_TYPES {
+            for square in 0..64 {
+                assert_eq!(piece_square_value(pt, Color::White, square),
+                           piece_square_value(pt, Color::Black, square ^ 56), "{:?} {}", pt, square);
+            }
+        }
+        // A pawn one step from promoting: e7 for White, e2 for Black
+        assert_eq!(piece_square_value(PieceType::Pawn, Color::White, 52).0, 50);
+        assert_eq!(piece_square_value(PieceType::Pawn, Color::Black, 12).0, 50);
+        // Castled kings on g1 and g8
+        assert_eq!(piece_square_value(PieceType::King, Color::White, 6).0, 30);
+        assert_eq!(piece_square_value(PieceType::King, Color::Black, 62).0, 30);
+    }
+
+    #[test]
+    fn evaluation_is_color_symmetric() {
+        // Random playouts from a few positions, mirrored at every step
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"] {
+            let mut game = Game::read_FEN(fen);
+            for _ in 0..60 {
+                let mirrored = game.mirror_vertical();
+                assert_eq!(game.evaluate(), mirrored.evaluate(), "{}", game.to_FEN());
+
+                let moves = game.legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                game.make_move(&moves[state as usize % moves.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_score_matches_recompute() {
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                    "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"] {
+            let mut game = Game::read_FEN(fen);
+            assert_eq!(game.psqt, game.compute_psqt());
+            let mut played = Vec::new();
+            for step in 0..200 {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+
+                // Mostly forward, with an occasional step back
+                let moves = game.legal_moves();
+                if moves.is_empty() || (step % 3 == 2 && !played.is_empty()) {
+                    match played.pop() {
+                        Some((mv, undo)) => game.unmake_move(&mv, undo),
+                        None => break,
+                    }
+                } else {
+                    let mv = moves[state as usize % moves.len()];
+                    let undo = game.make_move(&mv);
+                    played.push((mv, undo));
+                }
+                assert_eq!(game.psqt, game.compute_psqt(), "{}", game.to_FEN());
+                assert_eq!(game.evaluate_incremental(), game.evaluate());
+            }
+            while let Some((mv, undo)) = played.pop() {
+                game.unmake_move(&mv, undo);
+            }
+            assert_eq!(game.psqt, Game::read_FEN(fen).psqt);
+        }
+    }
+}
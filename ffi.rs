@@ -0,0 +1,186 @@
+// C interface for embedding the engine, built into the static and shared
+// libraries with the capi feature. chess.h is generated from this file by
+// `cbindgen --output chess.h`, which copies the `///` comments below into
+// it. Every function returns one of the CHESS_* codes; none of them panics
+// across the boundary on bad input.
+use std::ffi::{c_char, c_int, CStr};
+use std::ptr;
+use crate::game::*;
+use crate::search::MAX_DEPTH;
+
+pub const CHESS_OK: c_int = 0;
+pub const CHESS_NULL_POINTER: c_int = -1;
+pub const CHESS_INVALID_UTF8: c_int = -2;
+pub const CHESS_INVALID_FEN: c_int = -3;
+pub const CHESS_ILLEGAL_MOVE: c_int = -4;
+pub const CHESS_NO_MOVE: c_int = -5;
+pub const CHESS_BUFFER_TOO_SMALL: c_int = -6;
+
+unsafe fn read_str<'a>(text: *const c_char) -> Result<&'a str, c_int> {
+    if text.is_null() {
+        return Err(CHESS_NULL_POINTER);
+    }
+    CStr::from_ptr(text).to_str().map_err(|_| CHESS_INVALID_UTF8)
+}
+
+/// A new game at the initial position. Release it with chess_game_free.
+#[no_mangle]
+pub extern "C" fn chess_game_new() -> *mut Game {
+    Box::into_raw(Box::new(Game::initialize()))
+}
+
+/// Releases a game from chess_game_new. Null is ignored.
+///
+/// # Safety
+/// `game` must be null or a handle from chess_game_new not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn chess_game_free(game: *mut Game) {
+    if !game.is_null() {
+        drop(Box::from_raw(game));
+    }
+}
+
+/// Sets up the position of a NUL-terminated FEN. The game is unchanged
+/// when the FEN is invalid.
+///
+/// # Safety
+/// `game` must be a live handle and `fen` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn chess_set_fen(game: *mut Game, fen: *const c_char) -> c_int {
+    let game = match game.as_mut() {
+        Some(game) => game,
+        None => return CHESS_NULL_POINTER,
+    };
+    let fen = match read_str(fen) {
+        Ok(fen) => fen,
+        Err(code) => return code,
+    };
+    match Game::try_read_FEN(fen) {
+        Ok(parsed) => {
+            *game = parsed;
+            CHESS_OK
+        }
+        Err(_) => CHESS_INVALID_FEN,
+    }
+}
+
+/// Searches `depth` plies and writes the best move in coordinate notation
+/// ("e2e4", "e7e8q") to `buffer` as a NUL-terminated string. Six bytes are
+/// always enough. Depths below 1 search 1 ply and depths above 64 search 64.
+///
+/// # Safety
+/// `game` must be a live handle and `buffer` valid for `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chess_best_move(game: *mut Game, depth: c_int, buffer: *mut c_char,
+                                         length: usize) -> c_int {
+    let game = match game.as_mut() {
+        Some(game) => game,
+        None => return CHESS_NULL_POINTER,
+    };
+    if buffer.is_null() {
+        return CHESS_NULL_POINTER;
+    }
+
+    let (best, _) = game.search(search_depth(depth));
+    let text = match best {
+        Some(mv) => mv.to_string(),
+        None => return CHESS_NO_MOVE,
+    };
+    if text.len() + 1 > length {
+        return CHESS_BUFFER_TOO_SMALL;
+    }
+    ptr::copy_nonoverlapping(text.as_ptr() as *const c_char, buffer, text.len());
+    *buffer.add(text.len()) = 0;
+    CHESS_OK
+}
+
+// The search's own limit, so no C caller can ask for more plies than it has
+// room for
+fn search_depth(depth: c_int) -> u8 {
+    depth.clamp(1, MAX_DEPTH as c_int) as u8
+}
+
+/// Plays a move in coordinate notation if it is legal.
+///
+/// # Safety
+/// `game` must be a live handle and `mv` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn chess_make_move(game: *mut Game, mv: *const c_char) -> c_int {
+    let game = match game.as_mut() {
+        Some(game) => game,
+        None => return CHESS_NULL_POINTER,
+    };
+    let text = match read_str(mv) {
+        Ok(text) => text,
+        Err(code) => return code,
+    };
+    match game.uci_to_move(text) {
+        Ok(mv) => {
+            game.make_move(&mv);
+            CHESS_OK
+        }
+        Err(_) => CHESS_ILLEGAL_MOVE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    fn best_move(game: *mut Game, length: usize) -> (c_int, String) {
+        let mut buffer = vec![0x7f as c_char; length.max(1)];
+        let code = unsafe { chess_best_move(game, 2, buffer.as_mut_ptr(), length) };
+        let text = if code == CHESS_OK {
+            unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_str().unwrap().to_string()
+        } else {
+            String::new()
+        };
+        (code, text)
+    }
+
+    #[test]
+    fn game_handle_round_trip() {
+        let game = chess_game_new();
+        let fen = CString::new("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert_eq!(unsafe { chess_set_fen(game, fen.as_ptr()) }, CHESS_OK);
+        assert_eq!(best_move(game, 5), (CHESS_OK, "a1a8".to_string()));
+        assert_eq!(best_move(game, 4).0, CHESS_BUFFER_TOO_SMALL);
+
+        let mate = CString::new("a1a8").unwrap();
+        assert_eq!(unsafe { chess_make_move(game, mate.as_ptr()) }, CHESS_OK);
+        assert_eq!(unsafe { (*game).to_FEN() }, "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1");
+        assert_eq!(best_move(game, 8).0, CHESS_NO_MOVE);
+        unsafe { chess_game_free(game) };
+    }
+
+    #[test]
+    fn depths_are_clamped_to_the_search_limit() {
+        assert_eq!(search_depth(c_int::MIN), 1);
+        assert_eq!(search_depth(0), 1);
+        assert_eq!(search_depth(7), 7);
+        assert_eq!(search_depth(64), 64);
+        assert_eq!(search_depth(65), 64);
+        assert_eq!(search_depth(c_int::MAX), 64);
+    }
+
+    #[test]
+    fn bad_input_gives_error_codes() {
+        let game = chess_game_new();
+        let bad_fen = CString::new("rnbqkbnr/pppppppp w").unwrap();
+        let illegal = CString::new("e2e5").unwrap();
+        let not_utf8 = CString::new(vec![0xff, 0xfe]).unwrap();
+        unsafe {
+            assert_eq!(chess_set_fen(game, bad_fen.as_ptr()), CHESS_INVALID_FEN);
+            assert_eq!(chess_set_fen(game, not_utf8.as_ptr()), CHESS_INVALID_UTF8);
+            assert_eq!(chess_set_fen(game, ptr::null()), CHESS_NULL_POINTER);
+            assert_eq!(chess_make_move(game, illegal.as_ptr()), CHESS_ILLEGAL_MOVE);
+            assert_eq!(chess_make_move(ptr::null_mut(), illegal.as_ptr()), CHESS_NULL_POINTER);
+            assert_eq!(chess_best_move(game, 1, ptr::null_mut(), 8), CHESS_NULL_POINTER);
+            // Nothing above changed the game
+            assert_eq!((*game).to_FEN(), Game::initialize().to_FEN());
+            chess_game_free(game);
+            chess_game_free(ptr::null_mut());
+        }
+    }
+}
@@ -1,62 +1,170 @@
 use bitflags::bitflags;
 use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Instant;
+use crate::eval::piece_square_value;
+use crate::movegen::Move;
 use crate::utils::*;
-type PiecePosition = u64;
+use crate::tt::{TranspositionTable, DEFAULT_TT_MB};
+use crate::zobrist::*;
+pub type PiecePosition = u64;
 
-pub fn bit_to_position(bit: PiecePosition) -> Result<String, String> {
-    if bit == 0 {
-        return Err("No piece present!".to_string());
-    } else {
-        let onebit_index = bit_scan(bit);
-        return Ok(index_to_position(onebit_index));
+// A square of the board: 0 is a1, 7 is h1 and 63 is h8
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "u8"))]
+pub struct Square(pub u8);
+
+impl Square {
+    pub fn from_index(index: usize) -> Square {
+        Square(index as u8)
+    }
+
+    // Both counted from 0, so a1 is (0, 0)
+    pub fn from_file_rank(file: u8, rank: u8) -> Square {
+        Square(rank * 8 + file)
+    }
+
+    // The lowest set bit; None for an empty bitboard
+    pub fn from_bit(bit: PiecePosition) -> Option<Square> {
+        if bit == 0 {
+            None
+        } else {
+            Some(Square(bit_scan(bit & bit.wrapping_neg()) as u8))
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn file(self) -> u8 {
+        self.0 % 8
+    }
+
+    pub fn rank(self) -> u8 {
+        self.0 / 8
+    }
+
+    pub fn to_bit(self) -> PiecePosition {
+        1 << self.0
     }
 }
 
-pub fn position_to_bit(position: &str) -> Result<PiecePosition, String> {
-    if position.len() != 2 {
-        return Err(format!("Invalid length: {}, string: '{}'", position.len(), position));
+// Coordinates such as "e4"
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", COL_MAP[self.file() as usize], self.rank() + 1)
     }
+}
+
+// Why a coordinate string or bit doesn't name a square
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PositionError {
+    WrongLength(usize),
+    BadFile(char),
+    BadRank(char),
+    // A bitboard with no bit set
+    NoSquare,
+    // An index past h8
+    BadIndex(u8),
+}
 
-    let bytes = position.as_bytes();
-    let byte0 = bytes[0];
-    if byte0 < 97 || byte0 >= 97 + 8 {
-        return Err(format!("Invalid column character: {}, string: '{}'", byte0 as char, position));
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::WrongLength(length) => write!(f, "Invalid length: {}", length),
+            PositionError::BadFile(ch) => write!(f, "Invalid column character: {}", ch),
+            PositionError::BadRank(ch) => write!(f, "Invalid row character: {}", ch),
+            PositionError::NoSquare => write!(f, "No piece present!"),
+            PositionError::BadIndex(index) => write!(f, "Invalid square index: {}", index),
+        }
     }
+}
+
+impl FromStr for Square {
+    type Err = PositionError;
+
+    fn from_str(position: &str) -> Result<Square, PositionError> {
+        let chars: Vec<char> = position.chars().collect();
+        if chars.len() != 2 {
+            return Err(PositionError::WrongLength(chars.len()));
+        }
 
-    let column = (byte0 - 97) as u32;
+        let file = match chars[0] {
+            ch @ 'a'..='h' => ch as u8 - b'a',
+            ch => return Err(PositionError::BadFile(ch)),
+        };
+        let rank = match chars[1] {
+            ch @ '1'..='8' => ch as u8 - b'1',
+            ch => return Err(PositionError::BadRank(ch)),
+        };
 
-    let byte1 = bytes[1];
-    let row;
+        Ok(Square::from_file_rank(file, rank))
+    }
+}
+
+impl TryFrom<u8> for Square {
+    type Error = PositionError;
 
-    match (byte1 as char).to_digit(10) {
-        Some(number) => if number < 1 || number > 8 {
-            return Err(format!("Invalid row character: {}, string: '{}'", byte1 as char, position));
+    fn try_from(index: u8) -> Result<Square, PositionError> {
+        if index < 64 {
+            Ok(Square(index))
         } else {
-            row = number - 1;
-        },
-        None => return Err(format!("Invalid row character: {}, string '{}'", byte1 as char, position)),
+            Err(PositionError::BadIndex(index))
+        }
     }
+}
 
-    let square_number = row * 8 + column;
-    let bit = (1 as u64) << square_number;
+pub fn bit_to_position(bit: PiecePosition) -> Result<String, PositionError> {
+    match Square::from_bit(bit) {
+        Some(square) => Ok(square.to_string()),
+        None => Err(PositionError::NoSquare),
+    }
+}
 
-    Ok(bit)
+pub fn position_to_bit(position: &str) -> Result<PiecePosition, PositionError> {
+    position.parse::<Square>().map(Square::to_bit)
 }
 
-static COL_MAP: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
+pub static COL_MAP: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
 pub fn index_to_position(index: usize) -> String {
-    let column = index % 8;
-    let row = index / 8 + 1;
-    return format!("{}{}", COL_MAP[column], row);
+    Square::from_index(index).to_string()
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Color {
     White,
     Black
 }
 
-#[derive(Debug, PartialEq)]
+impl Color {
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
+// The side to move field of a FEN
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Color, String> {
+        match text {
+            "w" => Ok(Color::White),
+            "b" => Ok(Color::Black),
+            _ => Err(format!("Invalid color: '{}'", text)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PieceType {
     Pawn,
     Rook,
@@ -66,34 +174,98 @@ pub enum PieceType {
     King
 }
 
-#[derive(Debug, PartialEq)]
+pub const PIECE_TYPES: [PieceType; 6] = [PieceType::Pawn, PieceType::Rook, PieceType::Knight,
+                                         PieceType::Bishop, PieceType::Queen, PieceType::King];
+
+impl PieceType {
+    // Centipawns; the king is never traded so it carries no material
+    pub const fn material_value(&self) -> i32 {
+        match self {
+            PieceType::Pawn => 100,
+            PieceType::Knight => 320,
+            PieceType::Bishop => 330,
+            PieceType::Rook => 500,
+            PieceType::Queen => 900,
+            PieceType::King => 0,
+        }
+    }
+
+    // FEN letter, uppercase for White
+    pub fn to_char(self, color: Color) -> char {
+        let ch = match self {
+            PieceType::Pawn => 'p',
+            PieceType::Rook => 'r',
+            PieceType::Knight => 'n',
+            PieceType::Bishop => 'b',
+            PieceType::Queen => 'q',
+            PieceType::King => 'k',
+        };
+        match color {
+            Color::White => ch.to_ascii_uppercase(),
+            Color::Black => ch,
+        }
+    }
+
+    // The type of a FEN letter of either case
+    pub fn from_char(ch: char) -> Option<PieceType> {
+        match ch.to_ascii_lowercase() {
+            'p' => Some(PieceType::Pawn),
+            'r' => Some(PieceType::Rook),
+            'n' => Some(PieceType::Knight),
+            'b' => Some(PieceType::Bishop),
+            'q' => Some(PieceType::Queen),
+            'k' => Some(PieceType::King),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for PieceType {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<PieceType, String> {
+        let mut chars = text.chars();
+        match (chars.next().and_then(PieceType::from_char), chars.next()) {
+            (Some(pt), None) => Ok(pt),
+            _ => Err(format!("Invalid piece type: '{}'", text)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Piece {
-    position: PiecePosition,
-    color: Color,
-    piece_type: PieceType
+    pub position: PiecePosition,
+    pub color: Color,
+    pub piece_type: PieceType
 }
 
 impl Piece {
-    fn to_string(&self) -> String {
-        let mut result = match self.piece_type {
-            PieceType::Pawn => "p ",
-            PieceType::Rook => "r ",
-            PieceType::Knight => "n ",
-            PieceType::Bishop => "b ",
-            PieceType::Queen => "q ",
-            PieceType::King => "k ",
-        }.to_string();
-
-        if self.color == Color::White {
-            result.make_ascii_uppercase();
+    fn unicode_char(&self) -> char {
+        match (self.color, self.piece_type) {
+            (Color::White, PieceType::King) => '♔',
+            (Color::White, PieceType::Queen) => '♕',
+            (Color::White, PieceType::Rook) => '♖',
+            (Color::White, PieceType::Bishop) => '♗',
+            (Color::White, PieceType::Knight) => '♘',
+            (Color::White, PieceType::Pawn) => '♙',
+            (Color::Black, PieceType::King) => '♚',
+            (Color::Black, PieceType::Queen) => '♛',
+            (Color::Black, PieceType::Rook) => '♜',
+            (Color::Black, PieceType::Bishop) => '♝',
+            (Color::Black, PieceType::Knight) => '♞',
+            (Color::Black, PieceType::Pawn) => '♟',
         }
+    }
 
-        result
+    fn fen_char(&self) -> char {
+        self.piece_type.to_char(self.color)
     }
 }   
 
 #[derive(Debug, Copy, Clone)]
-pub enum Square {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Occupant {
     Empty,
     Occupied(usize),
 }
@@ -113,153 +285,827 @@ bitflags! {
     }
 }
 
+// One entry per right, in the order of the bits above
+pub const CASTLING_SIDES: [CastlingRights; 4] = [CastlingRights::WHITEKINGSIDE,
+                                                 CastlingRights::WHITEQUEENSIDE,
+                                                 CastlingRights::BLACKKINGSIDE,
+                                                 CastlingRights::BLACKQUEENSIDE];
+
+// Rook home squares h1, a1, h8 and a8
+pub const CLASSICAL_CASTLING_ROOKS: [usize; 4] = [7, 0, 63, 56];
+
+// Where the pieces stand and whose turn it is, without the clocks, rights
+// and history of the game. Small and Copy, so it can serve as a key.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Position {
+    // Occupancy per color and per piece type
+    pub color_bitboards: [u64; 2],
+    pub piece_bitboards: [u64; 6],
+    pub active_color: Color,
+}
+
+impl Position {
+    pub fn empty() -> Position {
+        Position { color_bitboards: [0; 2], piece_bitboards: [0; 6], active_color: Color::White }
+    }
+
+    pub fn occupancy(&self) -> u64 {
+        self.color_bitboards[0] | self.color_bitboards[1]
+    }
+
+    pub fn pieces_of(&self, color: Color, pt: PieceType) -> u64 {
+        self.color_bitboards[color as usize] & self.piece_bitboards[pt as usize]
+    }
+
+    pub fn piece_on(&self, square: usize) -> Option<(Color, PieceType)> {
+        let bit = 1 << square;
+        let color = if self.color_bitboards[Color::White as usize] & bit != 0 {
+            Color::White
+        } else if self.color_bitboards[Color::Black as usize] & bit != 0 {
+            Color::Black
+        } else {
+            return None;
+        };
+        let pt = PIECE_TYPES.into_iter().find(|&pt| self.piece_bitboards[pt as usize] & bit != 0)?;
+        Some((color, pt))
+    }
+
+    fn toggle(&mut self, bit: PiecePosition, color: Color, pt: PieceType) {
+        self.color_bitboards[color as usize] ^= bit;
+        self.piece_bitboards[pt as usize] ^= bit;
+    }
+}
+
 // Game type to own the data
+#[derive(Clone)]
 pub struct Game {
+    // Bitboards and side to move; `pieces` and `squares` are kept in sync
+    pub position: Position,
     pub pieces: Vec<Piece>,
-    pub squares: Vec<Square>,
-    pub active_color: Color,
+    pub squares: Vec<Occupant>,
     pub castling_rights: CastlingRights,
+    // Home square of the rook for each entry of CASTLING_SIDES
+    pub castling_rooks: [usize; 4],
+    // Castling is written with rook files in FEN and played as the king
+    // capturing its own rook
+    pub chess960: bool,
     pub en_passant: Option<PiecePosition>,
     pub halfmove_clock: usize,
     pub fullmove_number: usize,
+    // Zobrist hash of the position, updated incrementally
+    pub hash: u64,
+    // Material plus piece-square score from White's side as (midgame,
+    // endgame), updated incrementally like the hash
+    pub psqt: (i32, i32),
+    // Hashes of the positions before each move played, for repetitions.
+    // The current position is `hash`, so a game set up from a FEN starts
+    // with an empty history and counts repetitions from there on; earlier
+    // occurrences the FEN's halfmove clock hints at can't be known.
+    pub history: Vec<u64>,
+    // Length of `history` at the search's most recent null move. A null
+    // move isn't a move of the game, so repetitions are only looked for
+    // among the positions after it.
+    pub repetition_floor: usize,
+    // Moves played since the game was set up at `start_fen`
+    pub move_history: Vec<Move>,
+    pub start_fen: String,
+    // The side that resigned, ending the game whatever the position
+    pub resigned: Option<Color>,
+    pub tt: TranspositionTable,
+    // Triangular principal variation table: pv[ply] is the best line found
+    // from that ply of the last search
+    pub pv: Vec<Vec<Move>>,
+    // Positions visited by the last search, quiescence included
+    pub nodes: u64,
+    // A timed search gives up once this passes, setting `stopped`
+    pub deadline: Option<Instant>,
+    pub stopped: bool,
+    // Raised by another thread to stop the search the way `deadline` does
+    pub stop_signal: Option<Arc<AtomicBool>>,
+    // Two quiet moves per ply that caused a beta cutoff, newest first
+    pub killers: Vec<[Option<Move>; 2]>,
+    // Butterfly history: cutoff credit of quiet moves by [from][to]
+    pub history_scores: Vec<[i32; 64]>,
+    // Order quiet moves by killers and history; off leaves them in
+    // generation order
+    pub ordering_heuristics: bool,
+    // Try the best move the transposition table holds for a node first
+    pub hash_move_ordering: bool,
+    // Search late quiet moves at reduced depth first
+    pub late_move_reductions: bool,
+    // Search a ply deeper in check or with a single legal reply
+    pub search_extensions: bool,
+    // Centipawns a draw costs the side the search is run for; negative
+    // values make it seek draws
+    pub contempt: i32,
+    // Endgame tables probed at the root, set with set_tablebase_path
+    #[cfg(feature = "syzygy")]
+    pub tablebase: Option<Arc<shakmaty_syzygy::Tablebase<shakmaty::Chess>>>,
 }
 
 impl Game {
 
+    #[cfg(test)]
     fn push_piece_and_square(&mut self, position: usize, color: Color,
                              piece_type: PieceType, index: &mut usize) {
-        self.pieces.push(Piece { position: (1 as u64) << position,
-                                 color: color,
-                                 piece_type: piece_type });
-        self.squares.push(Square::Occupied(*index));
+        self.pieces.push(Piece { position: 1u64 << position,
+                                 color,
+                                 piece_type });
+        self.squares.push(Occupant::Occupied(*index));
+        self.toggle_bitboards(1 << position, color, piece_type);
         *index += 1;
     }
 
+    #[cfg(test)]
     fn push_empty_square(&mut self) {
-        self.squares.push(Square::Empty);
+        self.squares.push(Occupant::Empty);
     }
 
     pub fn initialize() -> Game {
         Game::read_FEN("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
     }
 
-    pub fn to_string(&self) -> String {
-        let mut board = "".to_owned();
-        let mut temp = "".to_owned();
+    pub fn empty() -> Game {
+        Game {
+            position: Position::empty(),
+            pieces: vec![],
+            squares: vec![Occupant::Empty; 64],
+            castling_rights: CastlingRights::NONE,
+            castling_rooks: CLASSICAL_CASTLING_ROOKS,
+            chess960: false,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: castling_key(CastlingRights::NONE),
+            psqt: (0, 0),
+            history: Vec::new(),
+            repetition_floor: 0,
+            move_history: Vec::new(),
+            start_fen: "8/8/8/8/8/8/8/8 w - - 0 1".to_string(),
+            resigned: None,
+            tt: TranspositionTable::new(DEFAULT_TT_MB),
+            pv: Vec::new(),
+            nodes: 0,
+            deadline: None,
+            stopped: false,
+            stop_signal: None,
+            killers: Vec::new(),
+            history_scores: Vec::new(),
+            ordering_heuristics: true,
+            hash_move_ordering: true,
+            late_move_reductions: true,
+            search_extensions: true,
+            contempt: 0,
+            #[cfg(feature = "syzygy")]
+            tablebase: None,
+        }
+    }
 
-        for (i, square) in self.squares.iter().enumerate() {
-            match square {
-                Square::Empty => temp.push_str(&index_to_position(i)),
-                Square::Occupied(idx) => temp.push_str(&self.pieces[*idx].to_string()),
+    // A game at `position` with no castling rights and fresh clocks
+    pub fn from_position(position: Position) -> Game {
+        let mut game = Game::empty();
+        game.position.active_color = position.active_color;
+        for square in 0..64 {
+            if let Some((color, pt)) = position.piece_on(square) {
+                game.set_piece(1 << square, color, pt);
             }
+        }
+        game.hash = game.compute_hash();
+        game.start_fen = game.to_FEN();
+        game
+    }
 
-            if (i + 1) % 8 == 0 {
-                temp.push_str("\n");
-                board.insert_str(0, &temp);
-                temp.clear();
+    pub fn occupancy(&self) -> u64 {
+        self.position.occupancy()
+    }
+
+    pub fn pieces_of(&self, color: Color, pt: PieceType) -> u64 {
+        self.position.pieces_of(color, pt)
+    }
+
+    pub fn all_pieces(&self) -> impl Iterator<Item = &Piece> {
+        self.pieces.iter()
+    }
+
+    pub fn pieces_iter(&self, color: Color) -> impl Iterator<Item = &Piece> {
+        self.pieces.iter().filter(move |piece| piece.color == color)
+    }
+
+    pub fn count_pieces(&self, color: Color, pt: PieceType) -> usize {
+        self.pieces_iter(color).filter(|piece| piece.piece_type == pt).count()
+    }
+
+    // White's material minus Black's, in centipawns
+    pub fn material_balance(&self) -> i32 {
+        self.all_pieces()
+            .map(|piece| match piece.color {
+                Color::White => piece.piece_type.material_value(),
+                Color::Black => -piece.piece_type.material_value(),
+            })
+            .sum()
+    }
+
+    // Half-moves played since the initial position, as the move number and
+    // side to move tell it: 0 at the start, 1 after 1.e4
+    pub fn ply(&self) -> usize {
+        let black = (self.position.active_color == Color::Black) as usize;
+        self.fullmove_number.saturating_sub(1) * 2 + black
+    }
+
+    // +1 with White to move and -1 with Black, turning White's scores into
+    // the side to move's and back
+    pub fn side_sign(&self) -> i32 {
+        match self.position.active_color {
+            Color::White => 1,
+            Color::Black => -1,
+        }
+    }
+
+    pub fn king_square(&self, color: Color) -> Option<usize> {
+        match self.pieces_of(color, PieceType::King) {
+            0 => None,
+            king => Some(king.trailing_zeros() as usize),
+        }
+    }
+
+    fn toggle_bitboards(&mut self, bit: PiecePosition, color: Color, pt: PieceType) {
+        self.position.toggle(bit, color, pt);
+        self.hash ^= piece_key(color, pt, bit_scan(bit));
+
+        let (mg, eg) = piece_square_value(pt, color, bit_scan(bit));
+        let (mg, eg) = (pt.material_value() + mg, pt.material_value() + eg);
+        // White pieces count up and Black ones down; removing one undoes that
+        let placed = self.position.color_bitboards[color as usize] & bit != 0;
+        let sign = match (color, placed) {
+            (Color::White, true) | (Color::Black, false) => 1,
+            _ => -1,
+        };
+        self.psqt.0 += sign * mg;
+        self.psqt.1 += sign * eg;
+    }
+
+    // Removes the piece on `index`, returning it with its slot in `pieces`.
+    // The last piece is swapped into the freed slot.
+    pub(crate) fn take_piece(&mut self, index: usize) -> Option<(Piece, usize)> {
+        let idx = match self.squares[index] {
+            Occupant::Occupied(idx) => idx,
+            Occupant::Empty => return None,
+        };
+
+        self.squares[index] = Occupant::Empty;
+        let piece = self.pieces.swap_remove(idx);
+        self.toggle_bitboards(piece.position, piece.color, piece.piece_type);
+
+        if idx < self.pieces.len() {
+            let moved = bit_scan(self.pieces[idx].position);
+            self.squares[moved] = Occupant::Occupied(idx);
+        }
+
+        Some((piece, idx))
+    }
+
+    // Exact inverse of `take_piece`
+    pub(crate) fn restore_piece(&mut self, piece: Piece, idx: usize) {
+        let last = self.pieces.len();
+        self.toggle_bitboards(piece.position, piece.color, piece.piece_type);
+        self.squares[bit_scan(piece.position)] = Occupant::Occupied(last);
+        self.pieces.push(piece);
+
+        if idx != last {
+            self.pieces.swap(idx, last);
+            self.squares[bit_scan(self.pieces[idx].position)] = Occupant::Occupied(idx);
+            self.squares[bit_scan(self.pieces[last].position)] = Occupant::Occupied(last);
+        }
+    }
+
+    // Moves the piece on `from` to the empty square `to`
+    pub(crate) fn relocate_piece(&mut self, from: usize, to: usize) {
+        if let Occupant::Occupied(idx) = self.squares[from] {
+            let piece = self.pieces[idx];
+            let to_bit = 1 << to;
+            self.toggle_bitboards(1 << from, piece.color, piece.piece_type);
+            self.toggle_bitboards(to_bit, piece.color, piece.piece_type);
+            self.pieces[idx].position = to_bit;
+            self.squares[from] = Occupant::Empty;
+            self.squares[to] = Occupant::Occupied(idx);
+        }
+    }
+
+    // Moves king and rook together; either may land on the other's start
+    // square, as happens in Chess960
+    pub(crate) fn castle_pieces(&mut self, king_from: usize, king_to: usize,
+                                rook_from: usize, rook_to: usize) {
+        if let (Occupant::Occupied(king), Occupant::Occupied(rook)) = (self.squares[king_from], self.squares[rook_from]) {
+            for (idx, from, to) in [(king, king_from, king_to), (rook, rook_from, rook_to)] {
+                let piece = self.pieces[idx];
+                self.toggle_bitboards(1 << from, piece.color, piece.piece_type);
+                self.toggle_bitboards(1 << to, piece.color, piece.piece_type);
+                self.pieces[idx].position = 1 << to;
             }
+            self.squares[king_from] = Occupant::Empty;
+            self.squares[rook_from] = Occupant::Empty;
+            self.squares[king_to] = Occupant::Occupied(king);
+            self.squares[rook_to] = Occupant::Occupied(rook);
         }
-        board.insert_str(0, &temp);
+    }
 
-        board 
+    pub(crate) fn change_piece_type(&mut self, index: usize, pt: PieceType) {
+        if let Occupant::Occupied(idx) = self.squares[index] {
+            let piece = self.pieces[idx];
+            self.toggle_bitboards(piece.position, piece.color, piece.piece_type);
+            self.toggle_bitboards(piece.position, piece.color, pt);
+            self.pieces[idx].piece_type = pt;
+        }
     }
 
+    // Puts a piece on `square`, replacing any already there. Nothing
+    // changes unless `square` is a single bit, and a pawn is never put on
+    // the first or last rank, where it would have no moves to generate.
+    pub fn set_piece(&mut self, square: PiecePosition, color: Color, pt: PieceType) {
+        if square.count_ones() != 1 || (pt == PieceType::Pawn && square & (RANK_MASKS[0] | RANK_MASKS[7]) != 0) {
+            return;
+        }
 
-    #[allow(non_snake_case)]
-    pub fn read_FEN(fen: &str) -> Game {
+        let index = bit_scan(square);
+        let before = self.occupant_kind(index);
+        match self.squares[index] {
+            Occupant::Occupied(idx) => {
+                let old = self.pieces[idx];
+                self.toggle_bitboards(square, old.color, old.piece_type);
+                self.pieces[idx].color = color;
+                self.pieces[idx].piece_type = pt;
+            }
+            Occupant::Empty => {
+                self.pieces.push(Piece { position: square, color, piece_type: pt });
+                self.squares[index] = Occupant::Occupied(self.pieces.len() - 1);
+            }
+        }
+        self.toggle_bitboards(square, color, pt);
+        self.update_rights_after_edit(index, before);
+        self.restart_history();
+    }
+
+    pub fn remove_piece(&mut self, square: PiecePosition) {
+        if square.count_ones() != 1 {
+            return;
+        }
+
+        let index = bit_scan(square);
+        let before = self.occupant_kind(index);
+        self.take_piece(index);
+        self.update_rights_after_edit(index, before);
+        self.restart_history();
+    }
+
+    // An edited position is where the game now starts: the moves and
+    // positions before the edit don't lead to it
+    fn restart_history(&mut self) {
+        self.history.clear();
+        self.move_history.clear();
+        self.start_fen = self.to_FEN();
+    }
+
+    fn occupant_kind(&self, index: usize) -> Option<(Color, PieceType)> {
+        match self.squares[index] {
+            Occupant::Occupied(idx) => Some((self.pieces[idx].color, self.pieces[idx].piece_type)),
+            Occupant::Empty => None,
+        }
+    }
+
+    // Keeps castling rights and en passant true to the board once the piece
+    // on `index` is no longer `before`: a king leaving its square gives up
+    // both rights of its color and a rook leaving its home square the right
+    // it castles with, and en passant is off once the pawn or the squares it
+    // passed are touched
+    fn update_rights_after_edit(&mut self, index: usize, before: Option<(Color, PieceType)>) {
+        if self.occupant_kind(index) == before {
+            return;
+        }
+
+        let mut rights = self.castling_rights;
+        if let Some((color, PieceType::King)) = before {
+            rights.remove(CASTLING_SIDES[color as usize * 2] | CASTLING_SIDES[color as usize * 2 + 1]);
+        }
+        for (side, &rook) in self.castling_rooks.iter().enumerate() {
+            if rook == index {
+                rights.remove(CASTLING_SIDES[side]);
+            }
+        }
+        self.hash ^= castling_key(self.castling_rights) ^ castling_key(rights);
+        self.castling_rights = rights;
+
+        if let Some(target) = self.en_passant {
+            if matches!(bit_scan(target).abs_diff(index), 0 | 8) {
+                self.hash ^= en_passant_key(target);
+                self.en_passant = None;
+            }
+        }
+    }
+
+    // The position with ranks swapped and colors exchanged, so White to
+    // move becomes the same position for Black to move
+    pub fn mirror_vertical(&self) -> Game {
+        let mut game = self.remapped(|square| square ^ 56, true);
+        game.position.active_color = self.position.active_color.opposite();
+        for side in 0..4 {
+            game.castling_rooks[side ^ 2] = self.castling_rooks[side] ^ 56;
+            if self.castling_rights.contains(CASTLING_SIDES[side]) {
+                game.castling_rights |= CASTLING_SIDES[side ^ 2];
+            }
+        }
+        game.en_passant = self.en_passant.map(|bit| 1 << (bit_scan(bit) ^ 56));
+        game.hash = game.compute_hash();
+        game
+    }
+
+    // The position with files swapped, a for h. Castling rights are
+    // dropped since the kings no longer stand where castling starts.
+    pub fn flip_horizontal(&self) -> Game {
+        let mut game = self.remapped(|square| square ^ 7, false);
+        game.en_passant = self.en_passant.map(|bit| 1 << (bit_scan(bit) ^ 7));
+        game.hash = game.compute_hash();
+        game
+    }
+
+    // Same side to move and clocks, pieces moved by `map`; no castling rights
+    fn remapped(&self, map: fn(usize) -> usize, swap_colors: bool) -> Game {
         let mut game = Game {
-            pieces: vec![],
-            squares: vec![],
-            active_color: Color::White,
-            castling_rights: CastlingRights::ALL,
-            en_passant: None,
-            halfmove_clock: 0,
-            fullmove_number: 1};
+            position: Position { active_color: self.position.active_color, ..Position::empty() },
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            chess960: self.chess960,
+            tt: self.tt.clone(),
+            ..Game::empty()
+        };
+        for piece in self.all_pieces() {
+            let color = if swap_colors { piece.color.opposite() } else { piece.color };
+            game.set_piece(1 << map(bit_scan(piece.position)), color, piece.piece_type);
+        }
+        game.start_fen = game.to_FEN();
+        game
+    }
 
-        let (position, rest) = split_on(fen, ' ');
+    pub fn piece_at(&self, square: PiecePosition) -> Option<&Piece> {
+        if square.count_ones() != 1 {
+            return None;
+        }
 
-        let mut deque_squares = VecDeque::new();
-        let mut piece_index = 0;
-        let mut piece_position = 64;
-        
-        for row in position.splitn(8, |ch| ch == '/') {
-            piece_position -= 8;
-            let (pieces, squares) = parse_row(&row, piece_index, piece_position);
-            
-            for p in pieces {
-                game.pieces.push(p);
-                piece_index += 1;
+        match self.squares.get(bit_scan(square)) {
+            Some(Occupant::Occupied(idx)) => self.pieces.get(*idx),
+            _ => None,
+        }
+    }
+
+    pub fn piece_at_str(&self, pos: &str) -> Option<&Piece> {
+        match position_to_bit(pos) {
+            Ok(bit) => self.piece_at(bit),
+            Err(_) => None,
+        }
+    }
+
+    // ASCII grid with rank labels down the left and files along the bottom
+    pub fn to_pretty(&self) -> String {
+        let border = "  +---+---+---+---+---+---+---+---+\n";
+        let mut board = String::from(border);
+        for rank in (0..8).rev() {
+            board.push_str(&format!("{} |", rank + 1));
+            for file in 0..8 {
+                let ch = match self.squares[rank * 8 + file] {
+                    Occupant::Empty => ' ',
+                    Occupant::Occupied(idx) => self.pieces[idx].fen_char(),
+                };
+                board.push_str(&format!(" {} |", ch));
             }
-            for s in squares {
-                deque_squares.push_front(s);
+            board.push('\n');
+            board.push_str(border);
+        }
+        board.push_str("    a   b   c   d   e   f   g   h\n");
+        board
+    }
+
+    // Figurine board seen from White, rank 8 at the top
+    pub fn to_unicode(&self) -> String {
+        self.to_unicode_for(Color::White)
+    }
+
+    // Figurine board with `perspective`'s pieces at the bottom
+    pub fn to_unicode_for(&self, perspective: Color) -> String {
+        let (ranks, files): (Vec<usize>, Vec<usize>) = match perspective {
+            Color::White => ((0..8).rev().collect(), (0..8).collect()),
+            Color::Black => ((0..8).collect(), (0..8).rev().collect()),
+        };
+
+        let mut board = String::new();
+        for &rank in &ranks {
+            board.push_str(&(rank + 1).to_string());
+            for &file in &files {
+                board.push(' ');
+                board.push(match self.squares[rank * 8 + file] {
+                    Occupant::Empty => '·',
+                    Occupant::Occupied(idx) => self.pieces[idx].unicode_char(),
+                });
             }
+            board.push('\n');
+        }
+
+        board.push(' ');
+        for &file in &files {
+            board.push(' ');
+            board.push(COL_MAP[file]);
         }
+        board.push('\n');
+        board
+    }
 
-        game.squares = Vec::from(deque_squares);
 
+    // For FENs known to be valid; panics on any other. Input from outside
+    // the program belongs in try_read_FEN.
+    #[allow(non_snake_case)]
+    pub fn read_FEN(fen: &str) -> Game {
+        match Game::try_read_FEN(fen) {
+            Ok(game) => game,
+            Err(err) => panic!("{}", err),
+        }
+    }
 
-        let (color_to_move, rest) = split_on(rest, ' ');
-        game.active_color = match color_to_move {
-            "w" => Color::White,
-            "b" => Color::Black,
-            _ => panic!("Unknown color designator: '{}'", color_to_move),
+    // The square must be behind a pawn of the side that just moved, on the
+    // third or sixth rank, with it and the pawn's start square empty. A pawn
+    // able to capture isn't needed: make_move sets the square after every
+    // double push, and FENs must hash the same as the moves leading to them.
+    fn en_passant_is_possible(&self, target: PiecePosition) -> bool {
+        let (rank, pawn, start) = match self.position.active_color {
+            Color::White => (5, target >> 8, target << 8),
+            Color::Black => (2, target << 8, target >> 8),
         };
+        let mover = self.position.active_color.opposite();
+        rank_of(target) == rank
+            && self.position.pieces_of(mover, PieceType::Pawn) & pawn != 0
+            && self.position.occupancy() & (target | start) == 0
+    }
+
+    #[allow(non_snake_case)]
+    pub fn try_read_FEN(fen: &str) -> Result<Game, FenError> {
+        // Fields may be separated by any run of spaces or tabs
+        let mut fields = fen.split_whitespace();
+        let board = fields.next().unwrap_or("");
+
+        let rows: Vec<&str> = board.split('/').collect();
+        if rows.len() != 8 {
+            return Err(FenError::Board(board.to_string()));
+        }
+
+        let mut position = Position::empty();
+        for (row, rank) in rows.into_iter().zip((0..8).rev()) {
+            let (pieces, _) = parse_row(row, 0, rank * 8)?;
+            for p in pieces {
+                position.toggle(p.position, p.color, p.piece_type);
+            }
+        }
 
+        // Move generation assumes every pawn has a square in front of it
+        let stranded = position.piece_bitboards[PieceType::Pawn as usize] & (RANK_MASKS[0] | RANK_MASKS[7]);
+        if let Some(square) = Square::from_bit(stranded) {
+            return Err(FenError::PawnOnBackRank(square));
+        }
+
+        let color_to_move = fields.next().unwrap_or("");
+        position.active_color = color_to_move.parse()
+            .map_err(|_| FenError::ActiveColor(color_to_move.to_string()))?;
+
+        let mut game = Game::from_position(position);
+        let opponent = position.active_color.opposite();
+        if game.is_in_check(opponent) {
+            return Err(FenError::OpponentInCheck(opponent));
+        }
 
-        let (castling_rights, rest) = split_on(rest, ' ');
-        let mut castling = CastlingRights::NONE;
+        let castling_rights = fields.next().unwrap_or("");
+        game.castling_rights = CastlingRights::NONE;
         for ch in castling_rights.chars() {
-            match ch {
-                'K' => castling |= CastlingRights::WHITEKINGSIDE,
-                'Q' => castling |= CastlingRights::WHITEQUEENSIDE,
-                'k' => castling |= CastlingRights::BLACKKINGSIDE,
-                'q' => castling |= CastlingRights::BLACKQUEENSIDE,
-                '-' => (),
-                other => panic!("Invalid character in castling rights: '{}'", other),
+            if ch != '-' {
+                game.add_castling_right(ch)?;
             }
         }
-        game.castling_rights = castling;
 
-        let (en_passant, rest) = split_on(rest, ' ');
+        // The en passant field and the two clocks are often left out; they
+        // default to "-", 0 and 1
+        let en_passant = fields.next().unwrap_or("");
         match en_passant {
-            "-" => game.en_passant = None,
+            "-" | "" => game.en_passant = None,
             s => match position_to_bit(s) {
-                Err(msg) => panic!("{}", msg),
+                Err(err) => return Err(FenError::EnPassant(err)),
                 Ok(bit) => game.en_passant = Some(bit),
             }
         };
+        if let Some(target) = game.en_passant {
+            if !game.en_passant_is_possible(target) {
+                return Err(FenError::ImpossibleEnPassant(Square::from_bit(target).unwrap()));
+            }
+        }
 
 
-        let (halfmove_clock, rest) = split_on(rest, ' ');
+        let halfmove_clock = fields.next().unwrap_or("");
         match halfmove_clock.parse() {
             Ok(number) => game.halfmove_clock = number,
-            Err(_) => panic!("Invalid halfmove: {}", halfmove_clock),
+            Err(_) if halfmove_clock.is_empty() => game.halfmove_clock = 0,
+            Err(_) => return Err(FenError::HalfmoveClock(halfmove_clock.to_string())),
         }
 
-        let (fullmove_number, rest) = split_on(rest, ' ');
+        let fullmove_number = fields.next().unwrap_or("");
         match fullmove_number.parse() {
             Ok(number) => game.fullmove_number = number,
-            Err(_) => panic!("Invalid halfmove: {}", fullmove_number),
+            Err(_) if fullmove_number.is_empty() => game.fullmove_number = 1,
+            Err(_) => return Err(FenError::FullmoveNumber(fullmove_number.to_string())),
         }
 
-        game
+        game.hash = game.compute_hash();
+        game.start_fen = game.to_FEN();
+        Ok(game)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn to_FEN(&self) -> String {
+        let mut fen = String::new();
+
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.squares[rank * 8 + file] {
+                    Occupant::Empty => empty += 1,
+                    Occupant::Occupied(idx) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(self.pieces[idx].fen_char());
+                    }
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.position.active_color {
+            Color::White => " w ",
+            Color::Black => " b ",
+        });
+
+        if self.castling_rights.is_empty() {
+            fen.push('-');
+        } else {
+            for (i, (right, ch)) in CASTLING_SIDES.iter().zip(['K', 'Q', 'k', 'q']).enumerate() {
+                if !self.castling_rights.contains(*right) {
+                    continue;
+                }
+                if self.chess960 {
+                    let file = COL_MAP[self.castling_rooks[i] % 8];
+                    fen.push(if i < 2 { file.to_ascii_uppercase() } else { file });
+                } else {
+                    fen.push(ch);
+                }
+            }
+        }
+
+        match self.en_passant {
+            None => fen.push_str(" -"),
+            Some(bit) => {
+                fen.push(' ');
+                fen.push_str(&index_to_position(bit_scan(bit)));
+            }
+        }
+
+        fen.push_str(&format!(" {} {}", self.halfmove_clock, self.fullmove_number));
+
+        fen
+    }
+
+    // 64-bit FNV-1a of the board and side to move fields of the FEN, e.g.
+    // "8/8/8/8/8/8/8/K6k w". Unlike the Zobrist hash it doesn't depend on
+    // generated keys, so the value for a position never changes and can be
+    // written into tests.
+    pub fn board_fingerprint(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+        let fen = self.to_FEN();
+        let fields: Vec<&str> = fen.split(' ').take(2).collect();
+        fields.join(" ").bytes()
+            .fold(FNV_OFFSET, |hash, byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+    }
+
+    // Reads one castling character: KQkq (X-FEN, the outermost rook on that
+    // side) or a rook file in Shredder-FEN, e.g. "HAha". Anything but h- and
+    // a-file rooks with the king on the e-file makes the game Chess960.
+    fn add_castling_right(&mut self, ch: char) -> Result<(), FenError> {
+        let color = if ch.is_ascii_uppercase() { Color::White } else { Color::Black };
+        let back_rank = match color {
+            Color::White => 0,
+            Color::Black => 56,
+        };
+        let rooks = self.pieces_of(color, PieceType::Rook);
+        let king = self.king_square(color).filter(|king| king / 8 == back_rank / 8);
+
+        let (kingside, rook) = match (ch.to_ascii_lowercase(), king) {
+            ('k', Some(king)) => (true, (king + 1..back_rank + 8).rev().find(|sq| rooks & (1 << sq) != 0)),
+            ('q', Some(king)) => (false, (back_rank..king).find(|sq| rooks & (1 << sq) != 0)),
+            ('k', None) => (true, None),
+            ('q', None) => (false, None),
+            ('a'..='h', Some(king)) => {
+                let file = ch.to_ascii_lowercase() as usize - 'a' as usize;
+                self.chess960 = true;
+                (back_rank + file > king, Some(back_rank + file))
+            }
+            _ => return Err(FenError::Castling(ch)),
+        };
+
+        let side = color as usize * 2 + if kingside { 0 } else { 1 };
+        let rook = rook.unwrap_or(CLASSICAL_CASTLING_ROOKS[side]);
+        if rook != CLASSICAL_CASTLING_ROOKS[side] || king.is_some_and(|king| king % 8 != 4) {
+            self.chess960 = true;
+        }
+        self.castling_rooks[side] = rook;
+        self.castling_rights |= CASTLING_SIDES[side];
+        Ok(())
     }
 }
 
-fn parse_row(row: &str, mut piece_index: usize, mut piece_position: usize) -> (Vec<Piece>, VecDeque<Square>) {
+// The board as FEN letters, eighth rank first
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut board = "".to_owned();
+        let mut temp = "".to_owned();
+
+        for (i, square) in self.squares.iter().enumerate() {
+            match square {
+                Occupant::Empty => temp.push_str(". "),
+                Occupant::Occupied(idx) => temp.push_str(&format!("{} ", self.pieces[*idx].fen_char())),
+            }
+
+            if (i + 1) % 8 == 0 {
+                temp.push('\n');
+                board.insert_str(0, &temp);
+                temp.clear();
+            }
+        }
+        board.insert_str(0, &temp);
+
+        write!(f, "{}", board)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FenError {
+    Board(String),
+    ActiveColor(String),
+    Castling(char),
+    EnPassant(PositionError),
+    HalfmoveClock(String),
+    FullmoveNumber(String),
+    // The side that just moved left its king in check
+    OpponentInCheck(Color),
+    PawnOnBackRank(Square),
+    // No pawn can just have passed over the en passant square
+    ImpossibleEnPassant(Square),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::Board(board) => write!(f, "Invalid board: '{}'", board),
+            FenError::ActiveColor(color) => write!(f, "Unknown color designator: '{}'", color),
+            FenError::Castling(ch) => write!(f, "Invalid character in castling rights: '{}'", ch),
+            FenError::EnPassant(err) => write!(f, "Invalid en passant square: {}", err),
+            FenError::HalfmoveClock(clock) => write!(f, "Invalid halfmove: {}", clock),
+            FenError::FullmoveNumber(number) => write!(f, "Invalid fullmove: {}", number),
+            FenError::OpponentInCheck(color) => write!(f, "{:?} is in check but not to move", color),
+            FenError::PawnOnBackRank(square) => write!(f, "Pawn on the first or last rank: {}", square),
+            FenError::ImpossibleEnPassant(square) => {
+                write!(f, "No pawn can have passed over the en passant square: {}", square)
+            }
+        }
+    }
+}
+
+fn parse_row(row: &str, mut piece_index: usize, mut piece_position: usize) -> Result<(Vec<Piece>, VecDeque<Occupant>), FenError> {
     let mut pieces = Vec::new();
     let mut squares = VecDeque::new();
+    let row_end = piece_position + 8;
 
     let mut color;
 
 
     macro_rules! add_piece {
-        ($piece_type:ident) => {
+        ($piece_type:expr) => {
             {
-                let piece = Piece {color: color,
-                               position: (1 as u64) << piece_position,
-                               piece_type: PieceType::$piece_type};
-                let square = Square::Occupied(piece_index);
+                if piece_position >= row_end {
+                    return Err(FenError::Board(row.to_string()));
+                }
+                let piece = Piece {color,
+                               position: 1u64 << piece_position,
+                               piece_type: $piece_type};
+                let square = Occupant::Occupied(piece_index);
                 pieces.push(piece);
                 squares.push_front(square);
                 piece_position += 1;
@@ -272,26 +1118,187 @@ fn parse_row(row: &str, mut piece_index: usize, mut piece_position: usize) -> (V
     for ch in row.chars() {
         let is_upper = ch.is_ascii_uppercase();
         color = if is_upper {Color::White} else {Color::Black};
-        match ch.to_ascii_lowercase() {
-            'r' => add_piece!(Rook),
-            'n' => add_piece!(Knight),
-            'b' => add_piece!(Bishop),
-            'q' => add_piece!(Queen),
-            'k' => add_piece!(King),
-            'p' => add_piece!(Pawn),
-            num => {
-                match num.to_digit(10) {
-                    None => panic!("Invalid input: {}", num),
-                    Some(number) => for i in 0..number {
-                        squares.push_front(Square::Empty);
-                        piece_position += 1;
+        match PieceType::from_char(ch) {
+            Some(pt) => add_piece!(pt),
+            None => {
+                match ch.to_digit(10) {
+                    Some(number) if number >= 1 && piece_position + number as usize <= row_end => {
+                        for _ in 0..number {
+                            squares.push_front(Occupant::Empty);
+                            piece_position += 1;
+                        }
                     }
+                    _ => return Err(FenError::Board(row.to_string())),
                 }
             }
         }
     }
 
-    (pieces, squares)
+    if piece_position != row_end {
+        return Err(FenError::Board(row.to_string()));
+    }
+
+    Ok((pieces, squares))
+}
+
+// CastlingRights is stored as its raw bits and Game as a FEN string, so the
+// serialized form does not depend on how the board is laid out internally.
+#[cfg(feature = "serde")]
+mod serialization {
+    use super::*;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    impl Serialize for CastlingRights {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u8(self.bits())
+        }
+    }
+
+    impl<'de> Deserialize<'de> for CastlingRights {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let bits = u8::deserialize(deserializer)?;
+            CastlingRights::from_bits(bits)
+                .ok_or_else(|| D::Error::custom(format!("invalid castling rights: {:#b}", bits)))
+        }
+    }
+
+    // A game is stored as it was set up plus the moves played since, in
+    // coordinate notation, so the history needed for repetitions survives.
+    // The current position is kept too, so a start that doesn't lead to it
+    // is caught instead of silently replayed into another game.
+    #[derive(Serialize, Deserialize)]
+    struct SerializedGame {
+        start_fen: String,
+        moves: Vec<String>,
+        fen: String,
+        resigned: Option<Color>,
+    }
+
+    impl Serialize for Game {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            SerializedGame {
+                start_fen: self.start_fen.clone(),
+                moves: self.move_history.iter().map(|mv| mv.to_string()).collect(),
+                fen: self.to_FEN(),
+                resigned: self.resigned,
+            }.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Game {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let serialized = SerializedGame::deserialize(deserializer)?;
+            let mut game = Game::try_read_FEN(&serialized.start_fen).map_err(D::Error::custom)?;
+            for mv in &serialized.moves {
+                game.make_move_uci(mv).map_err(D::Error::custom)?;
+            }
+            if game.to_FEN() != serialized.fen {
+                return Err(D::Error::custom(format!("moves from {} reach {}, not {}",
+                                                    serialized.start_fen, game.to_FEN(), serialized.fen)));
+            }
+            game.resigned = serialized.resigned;
+            Ok(game)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn game_round_trips_through_json() {
+            let fen = "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b Kq - 1 2";
+            let json = serde_json::to_string(&Game::read_FEN(fen)).unwrap();
+            assert_eq!(json, format!("{{\"start_fen\":\"{0}\",\"moves\":[],\"fen\":\"{0}\",\"resigned\":null}}", fen));
+
+            let game: Game = serde_json::from_str(&json).unwrap();
+            assert_eq!(game.to_FEN(), fen);
+
+            let mut played = Game::initialize();
+            for mv in ["g1f3", "g8f6", "f3g1", "f6g8", "e2e4"] {
+                played.make_move_uci(mv).unwrap();
+            }
+            let json = serde_json::to_string(&played).unwrap();
+            assert!(json.contains("\"moves\":[\"g1f3\",\"g8f6\",\"f3g1\",\"f6g8\",\"e2e4\"]"), "{}", json);
+
+            let game: Game = serde_json::from_str(&json).unwrap();
+            assert_eq!(game.to_FEN(), played.to_FEN());
+            assert_eq!(game.start_fen, played.start_fen);
+            assert_eq!(game.move_history, played.move_history);
+            assert_eq!(game.history, played.history);
+        }
+
+        #[test]
+        fn edited_games_round_trip_through_json() {
+            let mut game = Game::initialize();
+            for mv in ["e2e4", "e7e5"] {
+                game.make_move_uci(mv).unwrap();
+            }
+            game.remove_piece(position_to_bit("h8").unwrap());
+            game.set_piece(position_to_bit("d4").unwrap(), Color::White, PieceType::Knight);
+            let fen = "rnbqkbn1/pppp1ppp/8/4p3/3NP3/8/PPPP1PPP/RNBQKBNR w KQq e6 0 2";
+            assert_eq!(game.to_FEN(), fen);
+
+            let json = serde_json::to_string(&game).unwrap();
+            let restored: Game = serde_json::from_str(&json).unwrap();
+            assert_eq!(restored.to_FEN(), fen);
+            assert!(restored.move_history.is_empty());
+
+            let json = serde_json::to_string(&Game::empty()).unwrap();
+            assert_eq!(serde_json::from_str::<Game>(&json).unwrap().to_FEN(), Game::empty().to_FEN());
+        }
+
+        #[test]
+        fn resignations_survive_a_round_trip() {
+            let mut game = Game::initialize();
+            game.make_move_uci("e2e4").unwrap();
+            game.resign(Color::Black);
+
+            let restored: Game = serde_json::from_str(&serde_json::to_string(&game).unwrap()).unwrap();
+            assert_eq!(restored.resigned, Some(Color::Black));
+            assert_eq!(restored.status(), game.status());
+        }
+
+        #[test]
+        fn a_stale_start_fails_to_deserialize() {
+            // The moves are legal from the start but lead elsewhere
+            let mut game = Game::initialize();
+            game.make_move_uci("e2e4").unwrap();
+            game.start_fen = "rnbqkbnr/1ppppppp/p7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string();
+            let json = serde_json::to_string(&game).unwrap();
+            let error = serde_json::from_str::<Game>(&json).err().unwrap();
+            assert!(error.to_string().contains("reach rnbqkbnr/1ppppppp/p7/8/4P3"), "{}", error);
+        }
+
+        #[test]
+        fn castling_rights_serialize_as_bits() {
+            let rights = CastlingRights::WHITEKINGSIDE | CastlingRights::BLACKQUEENSIDE;
+            assert_eq!(serde_json::to_string(&rights).unwrap(), "9");
+            assert_eq!(serde_json::from_str::<CastlingRights>("9").unwrap(), rights);
+            assert!(serde_json::from_str::<CastlingRights>("16").is_err());
+        }
+
+        #[test]
+        fn squares_serialize_as_their_index() {
+            let e4: Square = "e4".parse().unwrap();
+            assert_eq!(serde_json::to_string(&e4).unwrap(), "28");
+            assert_eq!(serde_json::from_str::<Square>("28").unwrap(), e4);
+            assert!(serde_json::from_str::<Square>("63").is_ok());
+            assert!(serde_json::from_str::<Square>("64").is_err());
+            assert!(serde_json::from_str::<Square>("255").is_err());
+        }
+
+        #[test]
+        fn invalid_games_fail_to_deserialize() {
+            let start = Game::initialize().start_fen;
+            let bad_fen = format!("{{\"start_fen\":\"not a fen\",\"moves\":[],\"fen\":\"{}\",\"resigned\":null}}", start);
+            assert!(serde_json::from_str::<Game>(&bad_fen).is_err());
+            let illegal = format!("{{\"start_fen\":\"{0}\",\"moves\":[\"e2e4\",\"e2e4\"],\"fen\":\"{0}\",\"resigned\":null}}",
+                                  start);
+            assert!(serde_json::from_str::<Game>(&illegal).is_err());
+        }
+    }
 }
 
 #[cfg(test)]
@@ -299,12 +1306,9 @@ mod tests {
     use super::*;
 
     fn get_initial_position() -> Game {
-        let mut game = Game { pieces: vec![], squares: vec![],
-                              active_color: Color::White,
+        let mut game = Game { squares: vec![],
                               castling_rights: CastlingRights::ALL,
-                              en_passant: None,
-                              halfmove_clock: 0,
-                              fullmove_number: 1
+                              ..Game::empty()
         };
         let mut piece_index = 0;
 
@@ -332,7 +1336,7 @@ mod tests {
                                        PieceType::Pawn, &mut piece_index);
         }
 
-        for i in 16..48 {
+        for _ in 16..48 {
             game.push_empty_square();
         }
 
@@ -343,7 +1347,7 @@ mod tests {
         }        
 
         let offset = 56;
-        game.push_piece_and_square(0 + offset, color,
+        game.push_piece_and_square(offset, color,
                                    PieceType::Rook, &mut piece_index);
         game.push_piece_and_square(1 + offset, color,
                                    PieceType::Knight, &mut piece_index);
@@ -369,15 +1373,15 @@ mod tests {
     fn read_initial_position() {
         let game = Game::initialize();
         let default = get_initial_position();
-        assert_eq!(game.active_color, Color::White);
+        assert_eq!(game.position.active_color, Color::White);
         assert_eq!(game.castling_rights, CastlingRights::ALL);
         assert_eq!(game.en_passant, None);
         assert_eq!(game.halfmove_clock, 0);
         assert_eq!(game.fullmove_number, 1);
         for i in 0..64 {
             match (game.squares[i], default.squares[i]) {
-                (Square::Empty, Square::Empty) => (),
-                (Square::Occupied(idx1), Square::Occupied(idx2)) => assert_eq!(game.pieces[idx1], default.pieces[idx2]),
+                (Occupant::Empty, Occupant::Empty) => (),
+                (Occupant::Occupied(idx1), Occupant::Occupied(idx2)) => assert_eq!(game.pieces[idx1], default.pieces[idx2]),
                  _ => panic!("Wrong square at index {}", i),
             }
         }
@@ -386,7 +1390,7 @@ mod tests {
     #[test]
     fn read_fen_black_active() {
         let game = Game::read_FEN("rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b - - 1 2");
-        assert_eq!(game.active_color, Color::Black);
+        assert_eq!(game.position.active_color, Color::Black);
     }   
 
     #[test]
@@ -397,14 +1401,51 @@ mod tests {
 
     #[test]
     fn read_fen_en_passant_allowed() {
-        let en_passant_square = "g7";
-        let game = Game::read_FEN(&format!("rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq {} 1 2", en_passant_square));
+        let en_passant_square = "c6";
+        let game = Game::read_FEN(&format!("rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq {} 0 2", en_passant_square));
         assert_eq!(game.en_passant, Some(position_to_bit(en_passant_square).unwrap()));
     }
 
+    #[test]
+    fn ply_and_side_sign_follow_the_moves() {
+        let mut game = Game::initialize();
+        assert_eq!((game.ply(), game.side_sign()), (0, 1));
+        game.make_move(&Move::new(12, 28));
+        assert_eq!((game.ply(), game.side_sign()), (1, -1));
+        game.make_move(&Move::new(52, 36));
+        assert_eq!((game.ply(), game.side_sign()), (2, 1));
+
+        let later = Game::read_FEN("4k3/8/8/8/8/8/8/4K3 b - - 0 40");
+        assert_eq!(later.ply(), 79);
+    }
+
+    #[test]
+    fn read_fen_rejects_impossible_en_passant() {
+        // Kept even though no white pawn can take on e6
+        let uncapturable = Game::try_read_FEN("rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 2").unwrap();
+        assert_eq!(uncapturable.en_passant, position_to_bit("e6").ok());
+        let capturable = Game::try_read_FEN("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3").unwrap();
+        assert!(capturable.legal_moves().contains(&Move::new(36, 43)));
+
+        let impossible = |fen: &str, square: &str| {
+            assert_eq!(Game::try_read_FEN(fen).err(),
+                       Some(FenError::ImpossibleEnPassant(square.parse().unwrap())), "{}", fen);
+        };
+        // The pushed pawn's square given instead of the one behind it
+        impossible("rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq e5 0 2", "e5");
+        // Wrong rank for the side to move
+        impossible("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e6 0 1", "e6");
+        // No pawn in front of the square, or the square or start occupied
+        impossible("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq e3 0 1", "e3");
+        impossible("rnbqkbnr/pppppppp/8/8/4P3/4N3/PPPP1PPP/RNBQKB1R b KQkq e3 0 1", "e3");
+        impossible("rnbqkbnr/pppppppp/8/8/4P3/8/PPPPPPPP/RNBQKBNR b KQkq e3 0 1", "e3");
+        assert_eq!(FenError::ImpossibleEnPassant("e5".parse().unwrap()).to_string(),
+                   "No pawn can have passed over the en passant square: e5");
+    }
+
     #[test]
     fn read_fen_moveclocks() {
-        let game = Game::read_FEN("rnbqkbnr/pp1ppppp/7P/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b - g7 1 2");
+        let game = Game::read_FEN("rnbqkbnr/pp1ppppp/7P/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b - - 1 2");
         assert_eq!(game.halfmove_clock, 1);
         assert_eq!(game.fullmove_number, 2);
     }
@@ -415,15 +1456,422 @@ mod tests {
         let right_chars = ["K", "Q", "k", "q"];
         for i in 0..(2^4) {
             let bitflag_rights = CastlingRights::from_bits(i).unwrap();
-            for j in 0..4 {
+            for (j, right) in right_chars.iter().enumerate() {
                 if (i >> j) & 1 != 0 {
-                    rights.push_str(right_chars[j]);
+                    rights.push_str(right);
                 }
             }
+            if rights.is_empty() {
+                rights.push('-');
+            }
             let fen = format!("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w {} - 0 1", rights);
             let game = Game::read_FEN(&fen);
             assert_eq!(game.castling_rights, bitflag_rights, "FEN: {}\n\n i: {}", fen, i);
             rights.clear();
         }
     }
+
+    #[test]
+    fn to_fen_round_trips() {
+        let fens = ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b - - 1 2",
+                    "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b Qk e3 0 3",
+                    "8/8/8/4k3/8/8/8/4K2R w K - 12 40"];
+        for fen in fens {
+            assert_eq!(Game::read_FEN(fen).to_FEN(), fen);
+        }
+    }
+
+    #[test]
+    fn piece_types_and_colors_convert_to_and_from_text() {
+        for pt in PIECE_TYPES {
+            for color in [Color::White, Color::Black] {
+                let ch = pt.to_char(color);
+                assert_eq!(ch.is_ascii_uppercase(), color == Color::White);
+                assert_eq!(ch.to_string().parse::<PieceType>(), Ok(pt));
+            }
+        }
+        assert_eq!(PieceType::Knight.to_char(Color::White), 'N');
+        assert_eq!("q".parse::<PieceType>(), Ok(PieceType::Queen));
+        assert!("x".parse::<PieceType>().is_err());
+        assert!("qq".parse::<PieceType>().is_err());
+        assert!("".parse::<PieceType>().is_err());
+
+        assert_eq!("w".parse::<Color>(), Ok(Color::White));
+        assert_eq!("b".parse::<Color>(), Ok(Color::Black));
+        assert_eq!("W".parse::<Color>(), Err("Invalid color: 'W'".to_string()));
+    }
+
+    #[test]
+    fn board_fingerprint_is_pinned() {
+        let start = Game::initialize();
+        assert_eq!(start.board_fingerprint(), 0xe00f_8c8e_a6e6_9231);
+        // Castling, en passant and the clocks are left out
+        let bare = Game::read_FEN("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 7 30");
+        assert_eq!(bare.board_fingerprint(), start.board_fingerprint());
+        let black = Game::read_FEN("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1");
+        assert_ne!(black.board_fingerprint(), start.board_fingerprint());
+    }
+
+    #[test]
+    fn read_chess960_castling_rights() {
+        let game = Game::read_FEN("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9");
+        assert!(game.chess960);
+        assert_eq!(game.castling_rooks, [7, 5, 63, 61]);
+        assert_eq!(game.to_FEN(), "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9");
+
+        // X-FEN letters name the outermost rook on that side of the king
+        let game = Game::read_FEN("rk2r3/8/8/8/8/8/8/RK2R3 w KQkq - 0 1");
+        assert!(game.chess960);
+        assert_eq!(game.castling_rooks, [4, 0, 60, 56]);
+        assert_eq!(game.to_FEN(), "rk2r3/8/8/8/8/8/8/RK2R3 w EAea - 0 1");
+
+        let game = Game::initialize();
+        assert!(!game.chess960);
+        assert_eq!(game.castling_rooks, CLASSICAL_CASTLING_ROOKS);
+        assert_eq!(Game::try_read_FEN("8/8/8/8/8/8/8/R6R w A - 0 1").err(), Some(FenError::Castling('A')));
+    }
+
+    #[test]
+    fn to_string_renders_the_initial_position() {
+        let board = Game::initialize().to_string();
+        let lines: Vec<&str> = board.lines().collect();
+        assert_eq!(lines, ["r n b q k b n r ",
+                           "p p p p p p p p ",
+                           ". . . . . . . . ",
+                           ". . . . . . . . ",
+                           ". . . . . . . . ",
+                           ". . . . . . . . ",
+                           "P P P P P P P P ",
+                           "R N B Q K B N R "]);
+    }
+
+    #[test]
+    fn to_pretty_draws_a_labeled_grid() {
+        let game = Game::read_FEN("4k3/8/8/8/8/8/3P4/R3K3 w Q - 0 1");
+        let pretty = game.to_pretty();
+        let lines: Vec<&str> = pretty.lines().collect();
+        assert_eq!(lines.len(), 18);
+        assert_eq!(lines[0], "  +---+---+---+---+---+---+---+---+");
+        assert_eq!(lines[1], "8 |   |   |   |   | k |   |   |   |");
+        assert_eq!(lines[13], "2 |   |   |   | P |   |   |   |   |");
+        assert_eq!(lines[15], "1 | R |   |   |   | K |   |   |   |");
+        assert_eq!(lines[16], lines[0]);
+        assert_eq!(lines[17], "    a   b   c   d   e   f   g   h");
+    }
+
+    #[test]
+    fn to_unicode_draws_figurines() {
+        let game = Game::read_FEN("4k3/8/8/8/8/8/3P4/R3K3 w Q - 0 1");
+        assert_eq!(game.to_unicode(), "8 · · · · ♚ · · ·
+7 · · · · · · · ·
+6 · · · · · · · ·
+5 · · · · · · · ·
+4 · · · · · · · ·
+3 · · · · · · · ·
+2 · · · ♙ · · · ·
+1 ♖ · · · ♔ · · ·
+  a b c d e f g h
+");
+        assert_eq!(game.to_unicode_for(Color::Black), "1 · · · ♔ · · · ♖
+2 · · · · ♙ · · ·
+3 · · · · · · · ·
+4 · · · · · · · ·
+5 · · · · · · · ·
+6 · · · · · · · ·
+7 · · · · · · · ·
+8 · · · ♚ · · · ·
+  h g f e d c b a
+");
+    }
+
+    #[test]
+    fn read_fen_without_trailing_fields() {
+        let four = Game::read_FEN("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3");
+        assert_eq!(four.to_FEN(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+
+        let five = Game::read_FEN("4k3/8/8/8/8/8/8/4K3 w - - 12");
+        assert_eq!((five.halfmove_clock, five.fullmove_number), (12, 1));
+
+        let three = Game::read_FEN("4k3/8/8/8/8/8/8/4K3 w -");
+        assert_eq!(three.en_passant, None);
+        assert_eq!(three.to_FEN(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn read_fen_tolerates_irregular_whitespace() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 3 17";
+        for spaced in ["  r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R  b  KQkq - 3 17 ",
+                       "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R\tb\tKQkq\t-\t3\t17",
+                       "\nr3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq -   3 \t 17\r\n"] {
+            assert_eq!(Game::try_read_FEN(spaced).map(|game| game.to_FEN()), Ok(fen.to_string()), "{:?}", spaced);
+        }
+
+        let short = Game::read_FEN(" 4k3/8/8/8/8/8/8/4K3   w  - ");
+        assert_eq!(short.to_FEN(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn malformed_fens_never_panic() {
+        // Random edits of valid FENs. Whatever still parses must be safe to
+        // generate moves for, play, evaluate and print.
+        let seeds = ["r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                     "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                     "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                     "PPPPPPPP/8/8/8/8/8/8/pppppppp w - - 0 1",
+                     "kkkkkkkk/8/8/8/8/8/8/KKKKKKKK w KQkq - 0 1"];
+        let alphabet: Vec<char> = "rnbqkpRNBQKP0123456789/ -wKQkqAHaeh\té".chars().collect();
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut random = |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize % n
+        };
+
+        for round in 0..3000 {
+            let mut chars: Vec<char> = seeds[round % seeds.len()].chars().collect();
+            for _ in 0..1 + random(3) {
+                let ch = alphabet[random(alphabet.len())];
+                match random(3) {
+                    0 => chars.insert(random(chars.len() + 1), ch),
+                    1 if !chars.is_empty() => { chars.remove(random(chars.len())); }
+                    _ if !chars.is_empty() => {
+                        let index = random(chars.len());
+                        chars[index] = ch;
+                    }
+                    _ => (),
+                }
+            }
+            let fen: String = chars.into_iter().collect();
+            for field in fen.split_whitespace() {
+                let _ = position_to_bit(field);
+            }
+
+            if let Ok(mut game) = Game::try_read_FEN(&fen) {
+                let moves = game.legal_moves();
+                game.to_FEN();
+                game.evaluate();
+                game.status();
+                for mv in &moves {
+                    game.move_to_san(mv);
+                    let undo = game.make_move(mv);
+                    game.legal_moves();
+                    game.unmake_move(mv, undo);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn try_read_fen_reports_errors() {
+        assert!(matches!(Game::try_read_FEN("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1"),
+                         Err(FenError::Board(_))));
+        assert!(matches!(Game::try_read_FEN("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+                         Err(FenError::Board(_))));
+        assert!(matches!(Game::try_read_FEN("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1"),
+                         Err(FenError::ActiveColor(_))));
+        assert_eq!(Game::try_read_FEN("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1").err(),
+                   Some(FenError::Castling('x')));
+        assert_eq!(Game::try_read_FEN("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq j3 0 1").err(),
+                   Some(FenError::EnPassant(PositionError::BadFile('j'))));
+        assert!(matches!(Game::try_read_FEN("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1"),
+                         Err(FenError::HalfmoveClock(_))));
+        assert_eq!(Game::try_read_FEN("rnbqkbPr/pppppppp/8/8/8/8/PPPPPPP1/RNBQKBNR b KQkq - 0 1").err(),
+                   Some(FenError::PawnOnBackRank(Square::from_index(62))));
+        assert_eq!(Game::try_read_FEN("4k3/8/8/8/8/8/8/p3K3 w - - 0 1").err(),
+                   Some(FenError::PawnOnBackRank(Square::from_index(0))));
+    }
+
+    #[test]
+    fn fen_with_the_side_not_to_move_in_check_is_rejected() {
+        // Black to move while the rook on e2 attacks the white king
+        assert_eq!(Game::try_read_FEN("4k3/8/8/8/8/8/4r3/4K3 b - - 0 1").err(),
+                   Some(FenError::OpponentInCheck(Color::White)));
+        assert!(Game::try_read_FEN("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1").is_ok());
+    }
+
+    #[test]
+    fn piece_iterators_filter_by_color() {
+        let game = Game::read_FEN("4k3/4p3/8/8/8/8/3PP3/4K3 w - - 0 1");
+        assert_eq!(game.all_pieces().count(), 5);
+        assert_eq!(game.pieces_iter(Color::White).count(), 3);
+        assert!(game.pieces_iter(Color::Black).all(|piece| piece.color == Color::Black));
+        assert_eq!(game.pieces_iter(Color::Black).filter(|piece| piece.piece_type == PieceType::Pawn).count(), 1);
+    }
+
+    #[test]
+    fn material_balance_and_piece_counts() {
+        let game = Game::initialize();
+        assert_eq!(game.material_balance(), 0);
+        assert_eq!(game.count_pieces(Color::White, PieceType::Pawn), 8);
+        assert_eq!(game.count_pieces(Color::Black, PieceType::Pawn), 8);
+        assert_eq!(game.count_pieces(Color::Black, PieceType::Queen), 1);
+
+        let game = Game::read_FEN("4k3/8/8/8/8/8/3n4/R3K3 w - - 0 1");
+        assert_eq!(game.material_balance(), 500 - 320);
+        assert_eq!(game.count_pieces(Color::White, PieceType::Knight), 0);
+    }
+
+    #[test]
+    fn mirror_vertical_swaps_ranks_and_colors() {
+        let game = Game::read_FEN("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b Kq e3 0 1");
+        let mirrored = game.mirror_vertical();
+        assert_eq!(mirrored.to_FEN(), "rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w Qk e6 0 1");
+        assert_eq!(mirrored.hash, mirrored.compute_hash());
+        assert_eq!(mirrored.mirror_vertical().to_FEN(), game.to_FEN());
+    }
+
+    #[test]
+    fn flip_horizontal_swaps_files() {
+        let game = Game::read_FEN("4k3/8/8/3pP3/8/8/8/R3K3 w Q d6 0 1");
+        let flipped = game.flip_horizontal();
+        assert_eq!(flipped.to_FEN(), "3k4/8/8/3Pp3/8/8/8/3K3R w - e6 0 1");
+        assert_eq!(flipped.hash, flipped.compute_hash());
+    }
+
+    #[test]
+    fn piece_at_finds_pieces() {
+        let game = Game::initialize();
+        let king = game.piece_at_str("e1").unwrap();
+        assert_eq!(king.piece_type, PieceType::King);
+        assert_eq!(king.color, Color::White);
+        assert_eq!(king.position, position_to_bit("e1").unwrap());
+
+        let knight = game.piece_at(position_to_bit("g8").unwrap()).unwrap();
+        assert_eq!(knight.piece_type, PieceType::Knight);
+        assert_eq!(knight.color, Color::Black);
+    }
+
+    #[test]
+    fn piece_at_empty_or_invalid_square() {
+        let game = Game::initialize();
+        assert_eq!(game.piece_at_str("e4"), None);
+        assert_eq!(game.piece_at_str("z9"), None);
+        assert_eq!(game.piece_at_str(""), None);
+        assert_eq!(game.piece_at(0), None);
+        assert_eq!(game.piece_at(0b11), None);
+    }
+
+    #[test]
+    fn square_coordinates() {
+        let e4 = Square::from_file_rank(4, 3);
+        assert_eq!((e4.index(), e4.file(), e4.rank()), (28, 4, 3));
+        assert_eq!(e4.to_string(), "e4");
+        assert_eq!("e4".parse(), Ok(e4));
+        assert_eq!(Square::from_bit(e4.to_bit()), Some(e4));
+        assert_eq!(Square::from_bit(0), None);
+        assert_eq!("h8".parse::<Square>().map(Square::index), Ok(63));
+        assert!("i1".parse::<Square>().is_err());
+        assert!("a9".parse::<Square>().is_err());
+        assert!("a10".parse::<Square>().is_err());
+
+        for index in 0..64 {
+            let square = Square::from_index(index);
+            assert_eq!(Square::from_file_rank(square.file(), square.rank()), square);
+            assert_eq!(position_to_bit(&index_to_position(index)), Ok(square.to_bit()));
+            assert_eq!(bit_to_position(square.to_bit()), Ok(square.to_string()));
+        }
+    }
+
+    #[test]
+    fn position_errors_say_what_is_wrong() {
+        assert_eq!(position_to_bit("e44"), Err(PositionError::WrongLength(3)));
+        assert_eq!(position_to_bit("i1"), Err(PositionError::BadFile('i')));
+        assert_eq!(position_to_bit("a9"), Err(PositionError::BadRank('9')));
+        assert_eq!(bit_to_position(0), Err(PositionError::NoSquare));
+        assert_eq!(PositionError::BadFile('i').to_string(), "Invalid column character: i");
+        assert_eq!(FenError::EnPassant(PositionError::BadRank('0')).to_string(),
+                   "Invalid en passant square: Invalid row character: 0");
+    }
+
+    #[test]
+    fn position_holds_placement_and_side_to_move() {
+        let game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(game.position.piece_on(4), Some((Color::White, PieceType::King)));
+        assert_eq!(game.position.piece_on(52), Some((Color::Black, PieceType::Queen)));
+        assert_eq!(game.position.piece_on(27), None);
+        assert_eq!(game.position.occupancy(), game.occupancy());
+
+        // Clocks and rights are not part of it
+        let other = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 7 30");
+        assert_eq!(game.position, other.position);
+
+        let rebuilt = Game::from_position(game.position);
+        assert_eq!(rebuilt.position, game.position);
+        assert_eq!(rebuilt.to_FEN(), "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1");
+        assert_eq!(rebuilt.psqt, game.psqt);
+    }
+
+    #[test]
+    fn set_piece_builds_position() {
+        let mut game = Game::empty();
+        let at = |pos| position_to_bit(pos).unwrap();
+
+        game.set_piece(at("g1"), Color::White, PieceType::King);
+        game.set_piece(at("f2"), Color::White, PieceType::Pawn);
+        game.set_piece(at("g2"), Color::White, PieceType::Pawn);
+        game.set_piece(at("d1"), Color::White, PieceType::Rook);
+        game.set_piece(at("c8"), Color::Black, PieceType::King);
+        game.set_piece(at("b7"), Color::Black, PieceType::Pawn);
+        game.set_piece(at("e5"), Color::Black, PieceType::Knight);
+        game.position.active_color = Color::Black;
+
+        assert_eq!(game.to_FEN(), "2k5/1p6/8/4n3/8/8/5PP1/3R2K1 b - - 0 1");
+    }
+
+    #[test]
+    fn set_piece_refuses_pawns_on_the_back_ranks() {
+        let mut game = Game::read_FEN("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        let at = |pos| position_to_bit(pos).unwrap();
+
+        game.set_piece(at("a1"), Color::White, PieceType::Pawn);
+        game.set_piece(at("h8"), Color::Black, PieceType::Pawn);
+        game.set_piece(at("e8"), Color::White, PieceType::Pawn);
+        assert_eq!(game.to_FEN(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(game.legal_moves().len(), 5);
+    }
+
+    #[test]
+    fn set_piece_overwrites_and_remove_piece_keeps_indices() {
+        let mut game = Game::initialize();
+        let at = |pos| position_to_bit(pos).unwrap();
+
+        game.set_piece(at("d1"), Color::Black, PieceType::Knight);
+        game.remove_piece(at("a8"));
+        game.remove_piece(at("e2"));
+        game.remove_piece(at("e4"));
+        assert_eq!(game.to_FEN(), "1nbqkbnr/pppppppp/8/8/8/8/PPPP1PPP/RNBnKBNR w KQk - 0 1");
+        assert_eq!(game.pieces.len(), 30);
+
+        for (i, square) in game.squares.iter().enumerate() {
+            if let Occupant::Occupied(idx) = square {
+                assert_eq!(game.pieces[*idx].position, 1 << i);
+            }
+        }
+    }
+
+    #[test]
+    fn edits_drop_rights_the_board_no_longer_allows() {
+        let at = |pos| position_to_bit(pos).unwrap();
+        let mut game = Game::read_FEN("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        // Putting the same rook back changes nothing
+        game.set_piece(at("h1"), Color::White, PieceType::Rook);
+        assert_eq!(game.to_FEN(), "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        game.set_piece(at("h1"), Color::White, PieceType::Queen);
+        game.remove_piece(at("e8"));
+        assert_eq!(game.to_FEN(), "r6r/8/8/8/8/8/8/R3K2Q w Q - 0 1");
+        assert_eq!(game.hash, game.compute_hash());
+
+        let mut game = Game::read_FEN("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        game.set_piece(at("a3"), Color::White, PieceType::Knight);
+        assert_eq!(game.en_passant, Some(at("e3")));
+        game.remove_piece(at("e4"));
+        assert_eq!(game.en_passant, None);
+        assert_eq!(game.hash, game.compute_hash());
+
+        let mut game = Game::read_FEN("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        game.set_piece(at("e2"), Color::White, PieceType::Bishop);
+        assert_eq!(game.en_passant, None);
+    }
 }
@@ -0,0 +1,26 @@
+pub mod attacks;
+pub mod bench;
+pub mod builder;
+pub mod book;
+pub mod clock;
+pub mod epd;
+pub mod eval;
+#[cfg(feature = "capi")]
+pub mod ffi;
+pub mod game;
+pub mod mate;
+pub mod movegen;
+pub mod opening;
+pub mod pgn;
+pub mod san;
+pub mod see;
+pub mod status;
+pub mod search;
+#[cfg(feature = "syzygy")]
+pub mod syzygy;
+pub mod tt;
+pub mod uci;
+pub mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod zobrist;
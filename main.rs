@@ -1,12 +1,10 @@
-mod game;
-mod utils;
-use game::*;
+use chess_engine::game::*;
 
 fn main() {
     let fen_str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
     let game = Game::read_FEN(fen_str);
    // let (first_row, rest) = split_on(fen_str, '/');
     //println!("First: {}, Second: {}", first_row, rest);
-    println!("{}", game.to_string());
-    println!("{:?} {:?} {}", game.active_color, game.en_passant, game.fullmove_number);
+    println!("{}", game);
+    println!("{:?} {:?} {}", game.position.active_color, game.en_passant, game.fullmove_number);
 }
@@ -0,0 +1,102 @@
+use crate::game::*;
+use crate::movegen::*;
+
+impl Game {
+    // Shortest forced mate for the side to move in at most `max_moves` of
+    // its moves: the mating line, with the defence that holds out longest
+    pub fn find_mate(&mut self, max_moves: u8) -> Option<Vec<Move>> {
+        (1..=max_moves).find_map(|moves| self.mate_line(moves))
+    }
+
+    // A move after which every defence is mated within `moves - 1` more
+    // moves; checks are tried first since most mating lines are made of them
+    fn mate_line(&mut self, moves: u8) -> Option<Vec<Move>> {
+        if moves == 0 {
+            return None;
+        }
+
+        let mut candidates = self.legal_moves();
+        candidates.sort_by_cached_key(|mv| {
+            let mut after = self.clone();
+            after.make_move(mv);
+            !after.is_in_check(after.position.active_color)
+        });
+
+        for mv in candidates {
+            let undo = self.make_move(&mv);
+            let line = self.longest_defence(moves - 1);
+            self.unmake_move(&mv, undo);
+
+            if let Some(mut line) = line {
+                line.insert(0, mv);
+                return Some(line);
+            }
+        }
+        None
+    }
+
+    // With the defender to move: the line that delays mate longest if every
+    // reply is mated within `moves`, empty if already checkmated
+    fn longest_defence(&mut self, moves: u8) -> Option<Vec<Move>> {
+        let replies = self.legal_moves();
+        if replies.is_empty() {
+            return if self.is_in_check(self.position.active_color) { Some(Vec::new()) } else { None };
+        }
+
+        let mut longest: Option<Vec<Move>> = None;
+        for reply in replies {
+            let undo = self.make_move(&reply);
+            let line = (1..=moves).find_map(|n| self.mate_line(n));
+            self.unmake_move(&reply, undo);
+
+            let mut line = line?;
+            if longest.as_ref().is_none_or(|longest| line.len() + 1 > longest.len()) {
+                line.insert(0, reply);
+                longest = Some(line);
+            }
+        }
+        longest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_checkmate(game: &Game) -> bool {
+        game.is_in_check(game.position.active_color) && !game.has_legal_move()
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let mut game = Game::read_FEN("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(game.find_mate(3), Some(vec![Move::new(0, 56)]));
+    }
+
+    #[test]
+    fn finds_mate_in_two() {
+        // 1. Nf6+ gxf6 2. Bxf7#
+        let fen = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+        let mut game = Game::read_FEN(fen);
+        let line = game.find_mate(2).unwrap();
+        assert_eq!(line.len(), 3);
+        assert_eq!(game.to_FEN(), fen);
+
+        let mut san = Vec::new();
+        for mv in &line {
+            assert!(game.legal_moves().contains(mv));
+            san.push(game.move_to_san(mv));
+            game.make_move(mv);
+        }
+        assert_eq!(san, ["Nf6+", "gxf6", "Bxf7#"]);
+        assert!(is_checkmate(&game));
+    }
+
+    #[test]
+    fn no_mate_within_the_limit() {
+        assert_eq!(Game::initialize().find_mate(2), None);
+        // Mate in two exists, but not in one
+        let mut game = Game::read_FEN("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1");
+        assert_eq!(game.find_mate(1), None);
+    }
+}
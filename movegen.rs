@@ -0,0 +1,1784 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::attacks::*;
+use crate::game::*;
+use crate::utils::iter_bits;
+use crate::zobrist::*;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Move {
+    pub from: usize,
+    pub to: usize,
+    pub promotion: Option<PieceType>,
+}
+
+impl Move {
+    pub fn new(from: usize, to: usize) -> Move {
+        Move { from, to, promotion: None }
+    }
+}
+
+// Coordinate notation, e.g. "e2e4" or "e7e8q"
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", index_to_position(self.from), index_to_position(self.to))?;
+        match self.promotion {
+            Some(PieceType::Queen) => write!(f, "q"),
+            Some(PieceType::Rook) => write!(f, "r"),
+            Some(PieceType::Bishop) => write!(f, "b"),
+            Some(PieceType::Knight) => write!(f, "n"),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Coordinate notation back to a move; says nothing about legality
+impl FromStr for Move {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Move, String> {
+        if !text.is_ascii() || (text.len() != 4 && text.len() != 5) {
+            return Err(format!("Invalid move: '{}'", text));
+        }
+        let from = text[0..2].parse::<Square>().map_err(|_| format!("Invalid move: '{}'", text))?;
+        let to = text[2..4].parse::<Square>().map_err(|_| format!("Invalid move: '{}'", text))?;
+        let promotion = match &text[4..] {
+            "" => None,
+            letter => match letter.parse() {
+                Ok(pt) if PROMOTIONS.contains(&pt) && letter.chars().all(|ch| ch.is_ascii_lowercase()) => Some(pt),
+                _ => return Err(format!("Invalid move: '{}'", text)),
+            },
+        };
+        Ok(Move { from: from.index(), to: to.index(), promotion })
+    }
+}
+
+// Everything make_move overwrites that unmake_move cannot recompute
+#[derive(Debug, Clone, Copy)]
+pub struct UndoInfo {
+    captured: Option<(Piece, usize)>,
+    castling_rights: CastlingRights,
+    en_passant: Option<PiecePosition>,
+    halfmove_clock: usize,
+    hash: u64,
+    castled: bool,
+    repetition_floor: usize,
+}
+
+// Leaf counts of a perft run by kind of move, as published for the
+// standard test positions. Checkmates are also counted as checks.
+#[derive(Debug, PartialEq, Eq, Default, Clone, Copy)]
+pub struct PerftStats {
+    pub nodes: u64,
+    pub captures: u64,
+    pub en_passant: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    pub checks: u64,
+    pub checkmates: u64,
+}
+
+const PROMOTIONS: [PieceType; 4] = [PieceType::Queen, PieceType::Rook,
+                                    PieceType::Bishop, PieceType::Knight];
+
+// Index into CASTLING_SIDES
+fn castling_side(color: Color, kingside: bool) -> usize {
+    color as usize * 2 + if kingside { 0 } else { 1 }
+}
+
+// Squares strictly between two squares on the same rank, plus `to`
+fn rank_path(from: usize, to: usize) -> u64 {
+    let (low, high) = if from < to { (from + 1, to) } else { (to, from - 1) };
+    if low > high {
+        0
+    } else {
+        (u64::MAX >> (63 - high)) & (u64::MAX << low)
+    }
+}
+
+// The whole line through two aligned squares, without the squares themselves
+fn line_through(a: usize, b: usize) -> u64 {
+    if rook_attacks(a, 0) & (1 << b) != 0 {
+        rook_attacks(a, 0) & rook_attacks(b, 0)
+    } else if bishop_attacks(a, 0) & (1 << b) != 0 {
+        bishop_attacks(a, 0) & bishop_attacks(b, 0)
+    } else {
+        0
+    }
+}
+
+fn push_pawn_moves(moves: &mut Vec<Move>, from: usize, to: usize) {
+    if matches!(Square::from_index(to).rank(), 0 | 7) {
+        for pt in PROMOTIONS {
+            moves.push(Move { from, to, promotion: Some(pt) });
+        }
+    } else {
+        moves.push(Move::new(from, to));
+    }
+}
+
+fn push_moves(moves: &mut Vec<Move>, from: usize, targets: u64) {
+    moves.extend(iter_bits(targets).map(|to| Move::new(from, to)));
+}
+
+// Why a move can or can't be played, so a rejected move can be explained
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MoveLegality {
+    Legal,
+    OffBoard,
+    NoPieceAtSource,
+    WrongColor,
+    OwnPieceOnTarget,
+    // The piece doesn't move that way, even on an empty board
+    NotAPieceMove,
+    BlockedPath,
+    // A pawn reaching the last rank without a promotion, a promotion
+    // anywhere else, or one to a king or pawn
+    BadPromotion,
+    NoCastlingRights,
+    CastleOutOfCheck,
+    CastleThroughCheck,
+    LeavesKingInCheck,
+}
+
+// Generation order, so the cheapest pieces' moves come first
+const GENERATION_ORDER: [PieceType; 6] = [PieceType::Pawn, PieceType::Knight, PieceType::Bishop,
+                                          PieceType::Rook, PieceType::Queen, PieceType::King];
+
+impl Game {
+    pub fn is_square_attacked(&self, square: usize, by: Color) -> bool {
+        self.is_attacked_with(square, by, self.occupancy(), 0)
+    }
+
+    // Attack test against a hypothetical occupancy, ignoring attackers of
+    // `by` on the `removed` squares (pieces that would have been captured).
+    fn is_attacked_with(&self, square: usize, by: Color, occupancy: u64, removed: u64) -> bool {
+        let them = self.position.color_bitboards[by as usize] & !removed;
+        let of = |pt: PieceType| self.position.piece_bitboards[pt as usize] & them;
+
+        pawn_attacks(by.opposite(), square) & of(PieceType::Pawn) != 0
+            || knight_attacks(square) & of(PieceType::Knight) != 0
+            || king_attacks(square) & of(PieceType::King) != 0
+            || bishop_attacks(square, occupancy) & (of(PieceType::Bishop) | of(PieceType::Queen)) != 0
+            || rook_attacks(square, occupancy) & (of(PieceType::Rook) | of(PieceType::Queen)) != 0
+    }
+
+    // Pieces of both colors attacking `square`, with sliders seen through
+    // the given occupancy
+    pub fn attackers_to(&self, square: usize, occupancy: u64) -> u64 {
+        let of = |pt: PieceType| self.position.piece_bitboards[pt as usize];
+        let white = self.position.color_bitboards[Color::White as usize];
+        let black = self.position.color_bitboards[Color::Black as usize];
+        let diagonal = of(PieceType::Bishop) | of(PieceType::Queen);
+        let straight = of(PieceType::Rook) | of(PieceType::Queen);
+
+        (pawn_attacks(Color::Black, square) & of(PieceType::Pawn) & white)
+            | (pawn_attacks(Color::White, square) & of(PieceType::Pawn) & black)
+            | (knight_attacks(square) & of(PieceType::Knight))
+            | (king_attacks(square) & of(PieceType::King))
+            | (bishop_attacks(square, occupancy) & diagonal)
+            | (rook_attacks(square, occupancy) & straight)
+    }
+
+    // Squares the piece on `square` attacks, sliders stopping at the first
+    // piece in each direction and pawns taking only their capture squares.
+    // 0 for an empty square or anything but a single bit.
+    pub fn attacks_from(&self, square: PiecePosition) -> u64 {
+        if square.count_ones() != 1 {
+            return 0;
+        }
+        let index = square.trailing_zeros() as usize;
+        let occupancy = self.occupancy();
+        match self.position.piece_on(index) {
+            Some((color, PieceType::Pawn)) => pawn_attacks(color, index),
+            Some((_, PieceType::Knight)) => knight_attacks(index),
+            Some((_, PieceType::Bishop)) => bishop_attacks(index, occupancy),
+            Some((_, PieceType::Rook)) => rook_attacks(index, occupancy),
+            Some((_, PieceType::Queen)) => queen_attacks(index, occupancy),
+            Some((_, PieceType::King)) => king_attacks(index),
+            None => 0,
+        }
+    }
+
+    // Every square attacked by `color`, own pieces included. Pawns count
+    // only their capture squares, never their pushes.
+    pub fn attack_map(&self, color: Color) -> u64 {
+        iter_bits(self.position.color_bitboards[color as usize])
+            .fold(0, |map, square| map | self.attacks_from(1 << square))
+    }
+
+    // Enemy pieces giving check to the king of `color`
+    pub fn checkers(&self, color: Color) -> u64 {
+        match self.king_square(color) {
+            Some(king) => self.attackers_to(king, self.occupancy()) & self.position.color_bitboards[color.opposite() as usize],
+            None => 0,
+        }
+    }
+
+    // The checkers of the side to move one square each: none, one, or two
+    // in double check, when only the king can move
+    pub fn checking_pieces(&self) -> Vec<PiecePosition> {
+        iter_bits(self.checkers(self.position.active_color)).map(|square| 1 << square).collect()
+    }
+
+    // Pieces of `color` that are the only piece between their king and an
+    // enemy slider, so they may only move along that line
+    pub fn pinned_pieces(&self, color: Color) -> u64 {
+        let king = match self.king_square(color) {
+            Some(king) => king,
+            None => return 0,
+        };
+        let them = color.opposite();
+        let queens = self.pieces_of(them, PieceType::Queen);
+        let snipers = (rook_attacks(king, 0) & (self.pieces_of(them, PieceType::Rook) | queens))
+            | (bishop_attacks(king, 0) & (self.pieces_of(them, PieceType::Bishop) | queens));
+
+        let mut pinned = 0;
+        let mut remaining = snipers;
+        while remaining != 0 {
+            let sniper = remaining.trailing_zeros() as usize;
+            remaining &= remaining - 1;
+            let blockers = between(king, sniper) & self.occupancy();
+            if blockers.count_ones() == 1 {
+                pinned |= blockers & self.position.color_bitboards[color as usize];
+            }
+        }
+        pinned
+    }
+
+    pub fn is_in_check(&self, color: Color) -> bool {
+        match self.king_square(color) {
+            Some(king) => self.is_square_attacked(king, color.opposite()),
+            None => false,
+        }
+    }
+
+    pub(crate) fn is_en_passant(&self, mv: &Move) -> bool {
+        self.en_passant == Some(1 << mv.to)
+            && Square::from_index(mv.from).file() != Square::from_index(mv.to).file()
+            && self.position.piece_bitboards[PieceType::Pawn as usize] & (1 << mv.from) != 0
+    }
+
+    // Square of the pawn removed by an en passant capture to `to`
+    pub(crate) fn en_passant_victim(&self, to: usize) -> usize {
+        match self.position.active_color {
+            Color::White => to - 8,
+            Color::Black => to + 8,
+        }
+    }
+
+    // Pseudo-legal moves for the side to move; with `captures_only` just
+    // captures and promotions are generated.
+    fn generate_pseudo_legal(&self, moves: &mut Vec<Move>, captures_only: bool) {
+        for pt in GENERATION_ORDER {
+            self.generate_pseudo_legal_for(pt, moves, captures_only);
+        }
+    }
+
+    // Pseudo-legal moves of the side to move's pieces of one type; castling
+    // counts as a king move
+    fn generate_pseudo_legal_for(&self, pt: PieceType, moves: &mut Vec<Move>, captures_only: bool) {
+        let us = self.position.active_color;
+        let own = self.position.color_bitboards[us as usize];
+        let enemy = self.position.color_bitboards[us.opposite() as usize];
+        let occupancy = own | enemy;
+        let targets = if captures_only { enemy } else { !own };
+
+        let mut pieces = self.pieces_of(us, pt);
+        while pieces != 0 {
+            let from = pieces.trailing_zeros() as usize;
+            pieces &= pieces - 1;
+            let attacks = match pt {
+                PieceType::Pawn => {
+                    self.generate_pawn_moves(moves, from, occupancy, enemy, captures_only);
+                    continue;
+                }
+                PieceType::Knight => knight_attacks(from),
+                PieceType::Bishop => bishop_attacks(from, occupancy),
+                PieceType::Rook => rook_attacks(from, occupancy),
+                PieceType::Queen => queen_attacks(from, occupancy),
+                PieceType::King => king_attacks(from),
+            };
+            push_moves(moves, from, attacks & targets);
+        }
+
+        if pt == PieceType::King && !captures_only {
+            self.generate_castling(moves, occupancy);
+        }
+    }
+
+    fn generate_pawn_moves(&self, moves: &mut Vec<Move>, from: usize, occupancy: u64,
+                           enemy: u64, captures_only: bool) {
+        let us = self.position.active_color;
+        let (forward, start_rank, last_rank): (isize, u8, u8) = match us {
+            Color::White => (8, 1, 7),
+            Color::Black => (-8, 6, 0),
+        };
+
+        let one = (from as isize + forward) as usize;
+        if occupancy & (1 << one) == 0 && (!captures_only || Square::from_index(one).rank() == last_rank) {
+            push_pawn_moves(moves, from, one);
+
+            let two = (one as isize + forward) as usize;
+            if !captures_only && Square::from_index(from).rank() == start_rank && occupancy & (1 << two) == 0 {
+                moves.push(Move::new(from, two));
+            }
+        }
+
+        let attacks = pawn_attacks(us, from);
+        let mut captures = attacks & enemy;
+        while captures != 0 {
+            let to = captures.trailing_zeros() as usize;
+            captures &= captures - 1;
+            push_pawn_moves(moves, from, to);
+        }
+
+        if let Some(target) = self.en_passant {
+            if attacks & target != 0 {
+                moves.push(Move::new(from, target.trailing_zeros() as usize));
+            }
+        }
+    }
+
+    // Destinations of king and rook: g and f files for kingside castling,
+    // c and d for queenside, whatever the start files were
+    fn castling_targets(king: usize, kingside: bool) -> (usize, usize) {
+        let back_rank = king / 8 * 8;
+        if kingside {
+            (back_rank + 6, back_rank + 5)
+        } else {
+            (back_rank + 2, back_rank + 3)
+        }
+    }
+
+    // Castling is a king move two squares towards the rook, or in Chess960
+    // the king moving onto its own rook
+    pub fn is_castling(&self, mv: &Move) -> bool {
+        let own = self.position.color_bitboards[self.position.active_color as usize];
+        let from_bit = 1 << mv.from;
+        if own & self.position.piece_bitboards[PieceType::King as usize] & from_bit == 0 {
+            return false;
+        }
+        own & self.position.piece_bitboards[PieceType::Rook as usize] & (1 << mv.to) != 0
+            || (Square::from_index(mv.from).rank() == Square::from_index(mv.to).rank()
+                && mv.from.abs_diff(mv.to) == 2)
+    }
+
+    // King from, king to, rook from and rook to of a castling move made by `us`
+    fn castling_squares(&self, mv: &Move, us: Color) -> (usize, usize, usize, usize) {
+        let kingside = mv.to > mv.from;
+        let rook = self.castling_rooks[castling_side(us, kingside)];
+        let (king_to, rook_to) = Game::castling_targets(mv.from, kingside);
+        (mv.from, king_to, rook, rook_to)
+    }
+
+    // Every square king and rook cross must be empty apart from the two
+    // of them, and the king may not castle out of, through or into check.
+    // Only the king's own path is tested for attacks: f1 and g1, or d1 and
+    // c1, but never b1.
+    fn generate_castling(&self, moves: &mut Vec<Move>, occupancy: u64) {
+        let us = self.position.active_color;
+        let them = us.opposite();
+        let king = match self.king_square(us) {
+            Some(king) => king,
+            None => return,
+        };
+        let back_rank = match us {
+            Color::White => 0,
+            Color::Black => 7,
+        };
+
+        if king / 8 != back_rank || (!self.chess960 && king % 8 != 4)
+            || self.is_square_attacked(king, them) {
+            return;
+        }
+
+        let rooks = self.pieces_of(us, PieceType::Rook);
+        for kingside in [true, false] {
+            let side = castling_side(us, kingside);
+            let rook = self.castling_rooks[side];
+            if !self.castling_rights.contains(CASTLING_SIDES[side]) || rooks & (1 << rook) == 0 {
+                continue;
+            }
+
+            let (king_to, rook_to) = Game::castling_targets(king, kingside);
+            let others = occupancy & !(1 << king) & !(1 << rook);
+            if (rank_path(king, king_to) | rank_path(rook, rook_to)) & others != 0 {
+                continue;
+            }
+
+            let mut path = rank_path(king, king_to);
+            let mut safe = true;
+            while path != 0 {
+                let square = path.trailing_zeros() as usize;
+                path &= path - 1;
+                safe &= !self.is_attacked_with(square, them, others, 0);
+            }
+
+            if safe {
+                moves.push(Move::new(king, if self.chess960 { rook } else { king_to }));
+            }
+        }
+    }
+
+    // Whether a pseudo-legal move leaves the mover's king safe. The move is
+    // simulated on the occupancy bitboards rather than played.
+    pub fn is_legal(&self, mv: &Move) -> bool {
+        let us = self.position.active_color;
+        if self.is_castling(mv) {
+            let (king, king_to, rook, rook_to) = self.castling_squares(mv, us);
+            let occupancy = (self.occupancy() & !(1 << king) & !(1 << rook)) | (1 << king_to) | (1 << rook_to);
+            return !self.is_attacked_with(king_to, us.opposite(), occupancy, 0);
+        }
+
+        let from_bit: u64 = 1 << mv.from;
+        let to_bit: u64 = 1 << mv.to;
+        let mut removed = to_bit;
+        let mut occupancy = (self.occupancy() & !from_bit) | to_bit;
+
+        if self.is_en_passant(mv) {
+            let victim = 1 << self.en_passant_victim(mv.to);
+            removed |= victim;
+            occupancy &= !victim;
+        }
+
+        let king = if self.position.piece_bitboards[PieceType::King as usize] & from_bit != 0 {
+            mv.to
+        } else {
+            match self.king_square(us) {
+                Some(king) => king,
+                None => return true,
+            }
+        };
+
+        !self.is_attacked_with(king, us.opposite(), occupancy, removed)
+    }
+
+    // Whether the move is legal, and if not the first rule it breaks
+    pub fn move_legality(&self, mv: &Move) -> MoveLegality {
+        if mv.from >= 64 || mv.to >= 64 {
+            return MoveLegality::OffBoard;
+        }
+        let us = self.position.active_color;
+        let pt = match self.position.piece_on(mv.from) {
+            Some((color, _)) if color != us => return MoveLegality::WrongColor,
+            Some((_, pt)) => pt,
+            None => return MoveLegality::NoPieceAtSource,
+        };
+        if self.is_castling(mv) {
+            return self.castling_legality(mv);
+        }
+
+        let own = self.position.color_bitboards[us as usize];
+        let enemy = self.position.color_bitboards[us.opposite() as usize];
+        let occupancy = own | enemy;
+        if own & (1 << mv.to) != 0 {
+            return MoveLegality::OwnPieceOnTarget;
+        }
+
+        // The piece's moves here, and where it could go on an empty board
+        let mut moves = Vec::new();
+        let unobstructed = match pt {
+            PieceType::Pawn => {
+                self.generate_pawn_moves(&mut moves, mv.from, occupancy, enemy, false);
+                let (forward, start_rank) = match us {
+                    Color::White => (8, 1),
+                    Color::Black => (-8, 6),
+                };
+                let one = (mv.from as isize + forward) as usize;
+                let two = (one as isize + forward) as usize;
+                let double = if Square::from_index(mv.from).rank() == start_rank { 1 << two } else { 0 };
+                (1 << one) | double
+            }
+            PieceType::Knight => knight_attacks(mv.from),
+            PieceType::Bishop => bishop_attacks(mv.from, 0),
+            PieceType::Rook => rook_attacks(mv.from, 0),
+            PieceType::Queen => queen_attacks(mv.from, 0),
+            PieceType::King => king_attacks(mv.from),
+        };
+        if pt != PieceType::Pawn {
+            let attacks = match pt {
+                PieceType::Bishop => bishop_attacks(mv.from, occupancy),
+                PieceType::Rook => rook_attacks(mv.from, occupancy),
+                PieceType::Queen => queen_attacks(mv.from, occupancy),
+                _ => unobstructed,
+            };
+            push_moves(&mut moves, mv.from, attacks & !own);
+        }
+
+        if !moves.iter().any(|known| known.to == mv.to) {
+            return if unobstructed & (1 << mv.to) != 0 {
+                MoveLegality::BlockedPath
+            } else {
+                MoveLegality::NotAPieceMove
+            };
+        }
+        if !moves.contains(mv) {
+            return MoveLegality::BadPromotion;
+        }
+        if self.is_legal(mv) {
+            MoveLegality::Legal
+        } else {
+            MoveLegality::LeavesKingInCheck
+        }
+    }
+
+    // The checks of generate_castling, one at a time
+    fn castling_legality(&self, mv: &Move) -> MoveLegality {
+        let us = self.position.active_color;
+        let them = us.opposite();
+        let side = castling_side(us, mv.to > mv.from);
+        let (king, king_to, rook, rook_to) = self.castling_squares(mv, us);
+        if !self.castling_rights.contains(CASTLING_SIDES[side])
+            || self.pieces_of(us, PieceType::Rook) & (1 << rook) == 0 {
+            return MoveLegality::NoCastlingRights;
+        }
+        if self.is_square_attacked(king, them) {
+            return MoveLegality::CastleOutOfCheck;
+        }
+
+        let occupancy = self.occupancy();
+        let others = occupancy & !(1 << king) & !(1 << rook);
+        if (rank_path(king, king_to) | rank_path(rook, rook_to)) & others != 0 {
+            return MoveLegality::BlockedPath;
+        }
+        let mut path = rank_path(king, king_to);
+        while path != 0 {
+            let square = path.trailing_zeros() as usize;
+            path &= path - 1;
+            if self.is_attacked_with(square, them, others, 0) {
+                return MoveLegality::CastleThroughCheck;
+            }
+        }
+
+        // Anything still missing is a king that isn't castling from home, or
+        // a Chess960 castle not written as the king taking its rook
+        let mut moves = Vec::new();
+        self.generate_castling(&mut moves, occupancy);
+        if moves.contains(mv) {
+            MoveLegality::Legal
+        } else {
+            MoveLegality::NotAPieceMove
+        }
+    }
+
+    // King square of the side to move, the squares a non-king move must
+    // reach to answer a check (all of them out of check, none in double
+    // check) and the pinned pieces. None without a king.
+    fn legality_masks(&self) -> Option<(usize, u64, u64)> {
+        let us = self.position.active_color;
+        let king = self.king_square(us)?;
+        let checkers = self.checkers(us);
+        let evasions = match checkers.count_ones() {
+            0 => u64::MAX,
+            1 => checkers | between(king, checkers.trailing_zeros() as usize),
+            _ => 0,
+        };
+        Some((king, evasions, self.pinned_pieces(us)))
+    }
+
+    // Legal destinations of the knights, bishops, rooks and queens, or of
+    // just the first piece with any when `first_only` is set. These pieces
+    // have no special moves, so the attack sets give the moves directly.
+    fn piece_move_count(&self, (king, evasions, pinned): (usize, u64, u64), first_only: bool) -> usize {
+        let us = self.position.active_color;
+        let own = self.position.color_bitboards[us as usize];
+        let occupancy = self.occupancy();
+        let mut count = 0;
+        for pt in [PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen] {
+            let mut pieces = self.pieces_of(us, pt);
+            while pieces != 0 {
+                let from = pieces.trailing_zeros() as usize;
+                pieces &= pieces - 1;
+                let mut targets = match pt {
+                    PieceType::Knight => knight_attacks(from),
+                    PieceType::Bishop => bishop_attacks(from, occupancy),
+                    PieceType::Rook => rook_attacks(from, occupancy),
+                    _ => queen_attacks(from, occupancy),
+                } & !own & evasions;
+                if pinned & (1 << from) != 0 {
+                    targets &= line_through(king, from);
+                }
+                count += targets.count_ones() as usize;
+                if first_only && count > 0 {
+                    return count;
+                }
+            }
+        }
+        count
+    }
+
+    // Number of legal moves. Pawn and king moves, with their promotions,
+    // en passant and castling, are generated; the other pieces are counted
+    // from their attack sets
+    pub fn legal_move_count(&self) -> usize {
+        let masks = match self.legality_masks() {
+            Some(masks) => masks,
+            None => return self.legal_moves().len(),
+        };
+        let mut moves = Vec::new();
+        self.generate_piece_moves_into(PieceType::Pawn, &mut moves);
+        self.generate_piece_moves_into(PieceType::King, &mut moves);
+        moves.len() + self.piece_move_count(masks, false)
+    }
+
+    // Stops at the first legal move found, which is all checkmate and
+    // stalemate detection needs
+    pub fn has_legal_move(&self) -> bool {
+        let masks = match self.legality_masks() {
+            Some(masks) => masks,
+            None => return !self.legal_moves().is_empty(),
+        };
+        let mut moves = Vec::new();
+        self.generate_piece_moves_into(PieceType::King, &mut moves);
+        if !moves.is_empty() || self.piece_move_count(masks, true) > 0 {
+            return true;
+        }
+        self.generate_piece_moves_into(PieceType::Pawn, &mut moves);
+        !moves.is_empty()
+    }
+
+    // Drops the illegal moves among those appended to `out` from `start` on.
+    // Only king moves and en passant need the full attack test: any other
+    // move is legal when it captures or blocks the single checker (if any)
+    // and, for a pinned piece, stays on the line of the pin. In double
+    // check only the king can move.
+    fn retain_legal(&self, out: &mut Vec<Move>, start: usize) {
+        let (king, evasions, pinned) = match self.legality_masks() {
+            Some(masks) => masks,
+            None => return,
+        };
+
+        let mut kept = start;
+        for i in start..out.len() {
+            let mv = &out[i];
+            let legal = if mv.from == king || self.is_en_passant(mv) {
+                self.is_legal(mv)
+            } else {
+                evasions & (1 << mv.to) != 0
+                    && (pinned & (1 << mv.from) == 0 || line_through(king, mv.from) & (1 << mv.to) != 0)
+            };
+            if legal {
+                out.swap(kept, i);
+                kept += 1;
+            }
+        }
+        out.truncate(kept);
+    }
+
+    // Appends the legal moves to a buffer owned by the caller, so searches
+    // can clear and reuse one buffer per ply instead of allocating per node
+    pub fn generate_moves_into(&self, out: &mut Vec<Move>) {
+        let start = out.len();
+        self.generate_pseudo_legal(out, false);
+        self.retain_legal(out, start);
+    }
+
+    // Captures, en passant and promotions only, for quiescence search
+    pub fn generate_captures_into(&self, out: &mut Vec<Move>) {
+        let start = out.len();
+        self.generate_pseudo_legal(out, true);
+        self.retain_legal(out, start);
+    }
+
+    // Legal moves of the pieces of one type
+    pub fn generate_piece_moves_into(&self, pt: PieceType, out: &mut Vec<Move>) {
+        let start = out.len();
+        self.generate_pseudo_legal_for(pt, out, false);
+        self.retain_legal(out, start);
+    }
+
+    // Squares the piece on `from` can legally move to, for highlighting
+    // under a click. A promotion square appears once whatever the piece;
+    // castling shows the king's move as written, onto the rook in Chess960.
+    // 0 unless `from` is one square holding a piece of the side to move.
+    pub fn legal_destinations(&self, from: PiecePosition) -> u64 {
+        if from.count_ones() != 1 {
+            return 0;
+        }
+        let index = from.trailing_zeros() as usize;
+        let pt = match self.position.piece_on(index) {
+            Some((color, pt)) if color == self.position.active_color => pt,
+            _ => return 0,
+        };
+        let mut moves = Vec::new();
+        self.generate_piece_moves_into(pt, &mut moves);
+        moves.iter()
+            .filter(|mv| mv.from == index)
+            .fold(0, |destinations, mv| destinations | 1 << mv.to)
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(64);
+        self.generate_moves_into(&mut moves);
+        moves
+    }
+
+    pub fn legal_captures(&self) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(16);
+        self.generate_captures_into(&mut moves);
+        moves
+    }
+
+    // Legal moves that give check, found by playing each one on a copy
+    pub fn legal_checks(&self) -> Vec<Move> {
+        let mut after = self.clone();
+        self.legal_moves().into_iter().filter(|mv| {
+            let undo = after.make_move(mv);
+            let check = after.is_in_check(after.position.active_color);
+            after.unmake_move(mv, undo);
+            check
+        }).collect()
+    }
+
+    // Uniformly chosen legal move, None once the game is over
+    #[cfg(feature = "rand")]
+    pub fn random_move(&self, rng: &mut impl rand::Rng) -> Option<Move> {
+        let moves = self.legal_moves();
+        if moves.is_empty() {
+            None
+        } else {
+            Some(moves[rng.gen_range(0..moves.len())])
+        }
+    }
+
+    pub fn is_capture(&self, mv: &Move) -> bool {
+        self.position.color_bitboards[self.position.active_color.opposite() as usize] & (1 << mv.to) != 0
+            || self.is_en_passant(mv)
+    }
+
+    // Rights that disappear when `moving` plays `mv`: both of its color's
+    // rights for a king move, and the right of any rook moving or captured
+    // on its home square
+    fn castling_rights_lost(&self, moving: &Piece, mv: &Move) -> CastlingRights {
+        let mut lost = CastlingRights::NONE;
+        if moving.piece_type == PieceType::King {
+            lost |= CASTLING_SIDES[castling_side(moving.color, true)]
+                | CASTLING_SIDES[castling_side(moving.color, false)];
+        }
+        for (side, rook) in self.castling_rooks.iter().enumerate() {
+            if *rook == mv.from || *rook == mv.to {
+                lost |= CASTLING_SIDES[side];
+            }
+        }
+        lost
+    }
+
+    // Plays a legal (or at least pseudo-legal) move for the side to move
+    pub fn make_move(&mut self, mv: &Move) -> UndoInfo {
+        let us = self.position.active_color;
+        let moving = match self.squares[mv.from] {
+            Occupant::Occupied(idx) => self.pieces[idx],
+            Occupant::Empty => panic!("No piece to move on {}", index_to_position(mv.from)),
+        };
+
+        let castled = self.is_castling(mv);
+        let capture_square = if self.is_en_passant(mv) {
+            self.en_passant_victim(mv.to)
+        } else {
+            mv.to
+        };
+
+        let hash = self.hash;
+        let undo = UndoInfo {
+            captured: if castled { None } else { self.take_piece(capture_square) },
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash,
+            castled,
+            repetition_floor: self.repetition_floor,
+        };
+
+        self.history.push(hash);
+        self.move_history.push(*mv);
+        self.hash ^= castling_key(self.castling_rights) ^ black_to_move_key();
+        if let Some(target) = self.en_passant {
+            self.hash ^= en_passant_key(target);
+        }
+
+        if castled {
+            let (king, king_to, rook, rook_to) = self.castling_squares(mv, us);
+            self.castle_pieces(king, king_to, rook, rook_to);
+        } else {
+            self.relocate_piece(mv.from, mv.to);
+            if let Some(pt) = mv.promotion {
+                self.change_piece_type(mv.to, pt);
+            }
+        }
+
+        self.castling_rights.remove(self.castling_rights_lost(&moving, mv));
+
+        self.en_passant = if moving.piece_type == PieceType::Pawn && mv.from.abs_diff(mv.to) == 16 {
+            Some(1 << ((mv.from + mv.to) / 2))
+        } else {
+            None
+        };
+
+        self.hash ^= castling_key(self.castling_rights);
+        if let Some(target) = self.en_passant {
+            self.hash ^= en_passant_key(target);
+        }
+
+        if moving.piece_type == PieceType::Pawn || undo.captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+
+        if us == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.position.active_color = us.opposite();
+
+        undo
+    }
+
+    pub fn unmake_move(&mut self, mv: &Move, undo: UndoInfo) {
+        let us = self.position.active_color.opposite();
+        self.position.active_color = us;
+        if us == Color::Black {
+            self.fullmove_number -= 1;
+        }
+
+        if undo.castled {
+            let (king, king_to, rook, rook_to) = self.castling_squares(mv, us);
+            self.castle_pieces(king_to, king, rook_to, rook);
+        } else {
+            if mv.promotion.is_some() {
+                self.change_piece_type(mv.to, PieceType::Pawn);
+            }
+            self.relocate_piece(mv.to, mv.from);
+        }
+
+        if let Some((piece, idx)) = undo.captured {
+            self.restore_piece(piece, idx);
+        }
+
+        self.castling_rights = undo.castling_rights;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+        self.history.pop();
+        self.move_history.pop();
+    }
+
+    // A coordinate move such as "e2e4" or "e7e8q", if legal here
+    pub fn uci_to_move(&self, text: &str) -> Result<Move, String> {
+        let mv = text.parse::<Move>()?;
+        if self.legal_moves().contains(&mv) {
+            Ok(mv)
+        } else {
+            Err(format!("Illegal move: '{}'", text))
+        }
+    }
+
+    // Plays a coordinate move, failing with "Invalid move" for text that
+    // isn't one and "Illegal move" for one not legal here
+    pub fn make_move_uci(&mut self, text: &str) -> Result<(), String> {
+        let mv = self.uci_to_move(text)?;
+        self.make_move(&mv);
+        Ok(())
+    }
+
+    // Plays the moves in order. At the first illegal one it stops, leaving
+    // the moves before it played.
+    pub fn apply_moves(&mut self, moves: &[Move]) -> Result<(), String> {
+        for (index, mv) in moves.iter().enumerate() {
+            if !self.legal_moves().contains(mv) {
+                return Err(format!("Illegal move {} at index {}", mv, index));
+            }
+            self.make_move(mv);
+        }
+        Ok(())
+    }
+
+    // Passes the turn without moving a piece; used by null-move pruning
+    pub fn make_null_move(&mut self) -> UndoInfo {
+        let undo = UndoInfo {
+            captured: None,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+            castled: false,
+            repetition_floor: self.repetition_floor,
+        };
+
+        // Left out of the history, which only holds positions of the game
+        self.repetition_floor = self.history.len();
+        self.hash ^= black_to_move_key();
+        if let Some(target) = self.en_passant.take() {
+            self.hash ^= en_passant_key(target);
+        }
+        self.halfmove_clock += 1;
+        if self.position.active_color == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.position.active_color = self.position.active_color.opposite();
+
+        undo
+    }
+
+    pub fn unmake_null_move(&mut self, undo: UndoInfo) {
+        self.position.active_color = self.position.active_color.opposite();
+        if self.position.active_color == Color::Black {
+            self.fullmove_number -= 1;
+        }
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+        self.repetition_floor = undo.repetition_floor;
+    }
+
+    pub fn perft(&mut self, depth: usize) -> u64 {
+        // Room for the pseudo-legal moves of any position without growing
+        let mut buffers: Vec<Vec<Move>> = (0..depth).map(|_| Vec::with_capacity(256)).collect();
+        self.perft_with(depth, &mut buffers)
+    }
+
+    // One move buffer per remaining depth, reused by every node at that depth
+    fn perft_with(&mut self, depth: usize, buffers: &mut [Vec<Move>]) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        let (moves, rest) = buffers.split_last_mut().unwrap();
+        moves.clear();
+        self.generate_moves_into(moves);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+
+        let mut nodes = 0;
+        for mv in moves.iter() {
+            let undo = self.make_move(mv);
+            nodes += self.perft_with(depth - 1, rest);
+            self.unmake_move(mv, undo);
+        }
+        nodes
+    }
+
+    // perft that also sorts the moves of the last ply by kind
+    pub fn perft_stats(&mut self, depth: usize) -> PerftStats {
+        let mut stats = PerftStats::default();
+        if depth == 0 {
+            stats.nodes = 1;
+        } else {
+            self.perft_stats_into(depth, &mut stats);
+        }
+        stats
+    }
+
+    fn perft_stats_into(&mut self, depth: usize, stats: &mut PerftStats) {
+        for mv in self.legal_moves() {
+            if depth == 1 {
+                stats.nodes += 1;
+                if self.is_capture(&mv) {
+                    stats.captures += 1;
+                }
+                if self.is_en_passant(&mv) {
+                    stats.en_passant += 1;
+                }
+                if self.is_castling(&mv) {
+                    stats.castles += 1;
+                }
+                if mv.promotion.is_some() {
+                    stats.promotions += 1;
+                }
+            }
+
+            let undo = self.make_move(&mv);
+            if depth == 1 {
+                if self.is_in_check(self.position.active_color) {
+                    stats.checks += 1;
+                    if !self.has_legal_move() {
+                        stats.checkmates += 1;
+                    }
+                }
+            } else {
+                self.perft_stats_into(depth - 1, stats);
+            }
+            self.unmake_move(&mv, undo);
+        }
+    }
+
+    // perft with the root moves shared out over the rayon thread pool, each
+    // counted on its own copy of the game
+    #[cfg(feature = "rayon")]
+    pub fn perft_parallel(&self, depth: usize) -> u64 {
+        use rayon::prelude::*;
+
+        if depth <= 1 {
+            return self.clone().perft(depth);
+        }
+
+        self.legal_moves().par_iter()
+            .map(|mv| {
+                let mut game = self.clone();
+                game.make_move(mv);
+                game.perft(depth - 1)
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    // Counts allocations per thread so tests running in parallel don't mix
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(|count| count.get())
+    }
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    fn assert_perft(fen: &str, expected: &[u64]) {
+        let mut game = Game::read_FEN(fen);
+        for (depth, nodes) in expected.iter().enumerate() {
+            assert_eq!(game.perft(depth + 1), *nodes, "perft({}) of {}", depth + 1, fen);
+        }
+        assert_eq!(game.to_FEN(), fen);
+    }
+
+    #[test]
+    fn perft_initial_position() {
+        assert_perft("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                     &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn perft_stats_match_the_published_counts() {
+        let mut game = Game::initialize();
+        assert_eq!(game.perft_stats(3), PerftStats { nodes: 8902, captures: 34, checks: 12, ..Default::default() });
+        assert_eq!(game.perft_stats(4), PerftStats { nodes: 197281, captures: 1576, checks: 469, checkmates: 8,
+                                                     ..Default::default() });
+
+        let mut game = Game::read_FEN(KIWIPETE);
+        assert_eq!(game.perft_stats(3), PerftStats { nodes: 97862, captures: 17102, en_passant: 45, castles: 3162,
+                                                     promotions: 0, checks: 993, checkmates: 1 });
+        let mut game = Game::read_FEN("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+        assert_eq!(game.perft_stats(3), PerftStats { nodes: 2812, captures: 209, en_passant: 2, checks: 267,
+                                                     ..Default::default() });
+        assert_eq!(game.perft_stats(0).nodes, 1);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        assert_perft(KIWIPETE, &[48, 2039, 97862]);
+    }
+
+    #[test]
+    fn perft_endgame_with_en_passant_pins() {
+        assert_perft("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238]);
+    }
+
+    #[test]
+    fn perft_promotions_and_castling() {
+        assert_perft("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                     &[6, 264, 9467]);
+        assert_perft("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                     &[44, 1486, 62379]);
+    }
+
+    #[test]
+    fn perft_classical_positions_in_shredder_fen() {
+        assert_perft("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1", &[20, 400, 8902]);
+        assert_perft("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w HAha - 0 1",
+                     &[48, 2039, 97862]);
+    }
+
+    #[test]
+    fn perft_chess960() {
+        assert_perft("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                     &[21, 528, 12189]);
+        assert_perft("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                     &[21, 807, 18002]);
+        assert_perft("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                     &[20, 479, 10471]);
+        assert_perft("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+                     &[22, 593, 13440]);
+        assert_perft("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+                     &[28, 1120, 31058]);
+    }
+
+    #[test]
+    fn chess960_castling_moves_king_and_rook_to_standard_files() {
+        // King on b1 between rooks on a1 and h1
+        let fen = "4k3/8/8/8/8/8/8/RK5R w HA - 0 1";
+        let mut game = Game::read_FEN(fen);
+        let long = Move::new(1, 0);
+        let short = Move::new(1, 7);
+        assert!(game.legal_moves().contains(&long));
+        assert!(game.legal_moves().contains(&short));
+
+        let undo = game.make_move(&long);
+        assert_eq!(game.to_FEN(), "4k3/8/8/8/8/8/8/2KR3R b - - 1 1");
+        assert_eq!(game.hash, game.compute_hash());
+        game.unmake_move(&long, undo);
+        assert_eq!(game.to_FEN(), fen);
+
+        let undo = game.make_move(&short);
+        assert_eq!(game.to_FEN(), "4k3/8/8/8/8/8/8/R4RK1 b - - 1 1");
+        game.unmake_move(&short, undo);
+        assert_eq!(game.to_FEN(), fen);
+    }
+
+    #[test]
+    fn chess960_castling_checks_the_square_the_rook_uncovers() {
+        // After O-O-O the rook leaves b1 and the a1 rook would attack the king on c1
+        let game = Game::read_FEN("4k3/8/8/8/8/8/8/rR1K4 w B - 0 1");
+        assert!(!game.legal_moves().contains(&Move::new(3, 1)));
+        let game = Game::read_FEN("4k3/8/8/8/8/8/8/1R1K4 w B - 0 1");
+        assert!(game.legal_moves().contains(&Move::new(3, 1)));
+    }
+
+    fn can_castle(fen: &str, to: usize) -> bool {
+        Game::read_FEN(fen).legal_moves().contains(&Move::new(4, to))
+    }
+
+    #[test]
+    fn kingside_castling_needs_a_safe_king_and_f1() {
+        assert!(can_castle("4k3/8/8/8/8/8/8/4K2R w K - 0 1", 6));
+        // The bishop on h3 only hits f1, the knight on e2 only g1 and the
+        // rook on e7 gives check
+        assert!(!can_castle("4k3/8/8/8/8/7b/8/4K2R w K - 0 1", 6));
+        assert!(!can_castle("4k3/8/8/8/8/8/4n3/4K2R w K - 0 1", 6));
+        assert!(!can_castle("4k3/4r3/8/8/8/8/8/4K2R w K - 0 1", 6));
+    }
+
+    #[test]
+    fn queenside_castling_ignores_attacks_on_b1() {
+        // The king crosses d1 and c1 only, so a rook on the b-file is harmless
+        assert!(can_castle("1r2k3/8/8/8/8/8/8/R3K3 w Q - 0 1", 2));
+        // b1 must still be empty for the rook
+        assert!(!can_castle("4k3/8/8/8/8/8/8/RN2K3 w Q - 0 1", 2));
+        assert!(!can_castle("2r1k3/8/8/8/8/8/8/R3K3 w Q - 0 1", 2));
+        assert!(!can_castle("3rk3/8/8/8/8/8/8/R3K3 w Q - 0 1", 2));
+    }
+
+    #[test]
+    fn capturing_a_rook_on_its_home_square_removes_the_right() {
+        // ...Bxh1 takes the kingside rook before it ever moved
+        let mut game = Game::read_FEN("r3k2r/8/8/8/8/8/6b1/R3K2R b KQkq - 0 1");
+        let undo = game.make_move(&Move::new(14, 7));
+        assert_eq!(game.castling_rights, CastlingRights::WHITEQUEENSIDE
+                   | CastlingRights::BLACKKINGSIDE | CastlingRights::BLACKQUEENSIDE);
+        assert_eq!(game.hash, game.compute_hash());
+        assert!(!game.legal_moves().contains(&Move::new(4, 6)));
+        assert!(game.legal_moves().contains(&Move::new(4, 2)));
+
+        game.unmake_move(&Move::new(14, 7), undo);
+        assert_eq!(game.castling_rights, CastlingRights::ALL);
+    }
+
+    #[test]
+    fn king_and_rook_moves_remove_castling_rights() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let black = CastlingRights::BLACKKINGSIDE | CastlingRights::BLACKQUEENSIDE;
+        for (mv, rights) in [(Move::new(4, 12), black),
+                             (Move::new(4, 6), black),
+                             (Move::new(0, 1), black | CastlingRights::WHITEKINGSIDE),
+                             (Move::new(7, 15), black | CastlingRights::WHITEQUEENSIDE),
+                             (Move::new(0, 56), CastlingRights::WHITEKINGSIDE | CastlingRights::BLACKKINGSIDE)] {
+            let mut game = Game::read_FEN(fen);
+            game.make_move(&mv);
+            assert_eq!(game.castling_rights, rights, "after {}", mv);
+        }
+    }
+
+    #[test]
+    fn en_passant_that_exposes_the_king_on_its_rank_is_illegal() {
+        // dxe6 would take both pawns off the fifth rank, opening it to the rook
+        let capture = Move::new(35, 44);
+        for fen in ["8/8/8/K2Pp2r/8/8/8/8 w - e6 0 1", "7k/8/8/K2Pp2r/8/8/8/8 w - e6 0 1"] {
+            let game = Game::read_FEN(fen);
+            assert!(game.is_en_passant(&capture));
+            assert!(!game.is_legal(&capture), "{}", fen);
+            assert!(!game.legal_moves().contains(&capture), "{}", fen);
+            assert!(game.legal_moves().contains(&Move::new(35, 43)));
+        }
+
+        // Without the rook the same capture is fine
+        let game = Game::read_FEN("7k/8/8/K2Pp3/8/8/8/8 w - e6 0 1");
+        assert!(game.legal_moves().contains(&capture));
+    }
+
+    #[test]
+    fn en_passant_target_is_set_only_by_double_pushes() {
+        let mut game = Game::initialize();
+        game.make_move(&Move::new(12, 28));
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        assert_eq!(game.to_FEN(), fen);
+        assert_eq!(game.en_passant, Game::read_FEN(fen).en_passant);
+        assert_eq!(game.hash, Game::read_FEN(fen).hash);
+
+        // A single push clears the target, a double push sets the skipped square
+        game.make_move(&Move::new(50, 42));
+        assert_eq!(game.en_passant, None);
+        game.make_move(&Move::new(28, 36));
+        game.make_move(&Move::new(51, 35));
+        let fen = "rnbqkbnr/pp2pppp/2p5/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3";
+        assert_eq!(game.to_FEN(), fen);
+        assert_eq!(game.en_passant, Game::read_FEN(fen).en_passant);
+
+        game.make_move(&Move::new(6, 21));
+        assert_eq!(game.en_passant, None);
+    }
+
+    // Plays the moves, then takes en passant from `from` to `to` and checks
+    // that the pawn behind the target, not on it, is the one removed
+    fn check_en_passant_capture(moves: &[&str], from: &str, to: &str, after: &str) {
+        let mut game = Game::from_moves(moves).unwrap();
+        let before = game.to_FEN();
+        let capture: Move = format!("{}{}", from, to).parse().unwrap();
+        assert!(game.legal_moves().contains(&capture), "{} in {}", capture, before);
+        assert!(game.is_en_passant(&capture));
+
+        let undo = game.make_move(&capture);
+        assert_eq!(game.to_FEN(), after);
+        assert_eq!(game.hash, game.compute_hash());
+        game.unmake_move(&capture, undo);
+        assert_eq!(game.to_FEN(), before);
+    }
+
+    #[test]
+    fn en_passant_captures_for_both_colors() {
+        check_en_passant_capture(&["e4", "a6", "e5", "d5"], "e5", "d6",
+                                 "rnbqkbnr/1pp1pppp/p2P4/8/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3");
+        check_en_passant_capture(&["a3", "d5", "a4", "d4", "e4"], "d4", "e3",
+                                 "rnbqkbnr/ppp1pppp/8/8/P7/4p3/1PPP1PPP/RNBQKBNR w KQkq - 0 4");
+        // On the edge files there is only one way to take
+        check_en_passant_capture(&["b4", "h6", "b5", "a5"], "b5", "a6",
+                                 "rnbqkbnr/1pppppp1/P6p/8/8/8/P1PPPPPP/RNBQKBNR b KQkq - 0 3");
+        check_en_passant_capture(&["a3", "g5", "a4", "g4", "h4"], "g4", "h3",
+                                 "rnbqkbnr/pppppp1p/8/8/P7/7p/1PPPPPP1/RNBQKBNR w KQkq - 0 4");
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn perft_parallel_matches_perft() {
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", KIWIPETE] {
+            let mut game = Game::read_FEN(fen);
+            for depth in 1..=4 {
+                assert_eq!(game.perft_parallel(depth), game.perft(depth), "depth {} of {}", depth, fen);
+            }
+        }
+    }
+
+    // Minutes even in release builds: cargo test --release --features rayon -- --ignored
+    #[cfg(feature = "rayon")]
+    #[test]
+    #[ignore]
+    fn perft_parallel_deep() {
+        let game = Game::initialize();
+        assert_eq!(game.perft_parallel(5), 4865609);
+        assert_eq!(game.perft_parallel(6), 119060324);
+        let game = Game::read_FEN(KIWIPETE);
+        assert_eq!(game.perft_parallel(5), 193690690);
+        assert_eq!(game.perft_parallel(6), 8031647685);
+    }
+
+    // The allocating generator, node by node, as perft used to run
+    fn perft_allocating(game: &mut Game, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let mut nodes = 0;
+        for mv in game.legal_moves() {
+            let undo = game.make_move(&mv);
+            nodes += perft_allocating(game, depth - 1);
+            game.unmake_move(&mv, undo);
+        }
+        nodes
+    }
+
+    #[test]
+    fn perft_reuses_move_buffers() {
+        let mut game = Game::read_FEN(KIWIPETE);
+        // Builds the slider tables if this thread gets there first, and grows
+        // the history stack to its full depth
+        game.perft(4);
+
+        let count = |game: &mut Game, depth: usize, perft: fn(&mut Game, usize) -> u64| {
+            let before = allocations();
+            perft(game, depth);
+            allocations() - before
+        };
+
+        // One buffer per depth, however many nodes
+        let three = count(&mut game, 3, Game::perft);
+        let four = count(&mut game, 4, Game::perft);
+        assert_eq!(four, three + 1);
+
+        let allocating = count(&mut game, 3, perft_allocating);
+        assert!(allocating > 100 * three, "{} and {} allocations", three, allocating);
+    }
+
+    #[test]
+    fn generate_into_appends_to_the_buffer() {
+        let game = Game::read_FEN(KIWIPETE);
+        let mut buffer = vec![Move::new(0, 0)];
+        game.generate_moves_into(&mut buffer);
+        assert_eq!(buffer[0], Move::new(0, 0));
+        assert_eq!(buffer[1..], game.legal_moves()[..]);
+
+        let mut by_piece = Vec::new();
+        for pt in PIECE_TYPES {
+            game.generate_piece_moves_into(pt, &mut by_piece);
+        }
+        assert_eq!(by_piece.len(), 48);
+
+        let mut kings = Vec::new();
+        game.generate_piece_moves_into(PieceType::King, &mut kings);
+        assert_eq!(kings.len(), 4);
+
+        buffer.clear();
+        game.generate_captures_into(&mut buffer);
+        assert_eq!(buffer, game.legal_captures());
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn random_games_stay_consistent() {
+        use crate::status::GameStatus;
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2024);
+        let mut game = Game::initialize();
+        for _ in 0..200 {
+            let status = game.status();
+            let mv = game.random_move(&mut rng);
+            assert_eq!(mv.is_none(), matches!(status, GameStatus::Checkmate(_) | GameStatus::Stalemate));
+            if matches!(status, GameStatus::Checkmate(_)) {
+                assert!(game.is_in_check(game.position.active_color));
+            }
+
+            let mv = match mv {
+                Some(mv) => mv,
+                None => break,
+            };
+            game.make_move(&mv);
+            assert!(!game.is_in_check(game.position.active_color.opposite()), "{} left the king in check", mv);
+            assert_eq!(game.hash, game.compute_hash());
+            assert_eq!(Game::read_FEN(&game.to_FEN()).to_FEN(), game.to_FEN());
+        }
+    }
+
+    #[test]
+    fn legal_destinations_of_a_square() {
+        let game = Game::initialize();
+        // g1 reaches f3 and h3, e2 both pushes
+        assert_eq!(game.legal_destinations(1 << 6), 1 << 21 | 1 << 23);
+        assert_eq!(game.legal_destinations(1 << 12), 1 << 20 | 1 << 28);
+        assert_eq!(game.legal_destinations(1 << 52), 0);
+        assert_eq!(game.legal_destinations(1 << 30), 0);
+        assert_eq!(game.legal_destinations(0), 0);
+        assert_eq!(game.legal_destinations(1 << 6 | 1 << 1), 0);
+
+        // The b7 pawn promotes on b8 and a8 with four pieces each; the
+        // pinned c2 bishop stays on the pin
+        let game = Game::read_FEN("n3k3/1P6/8/8/q7/8/2B5/3K4 w - - 0 1");
+        assert_eq!(game.legal_destinations(1 << 49), 1 << 56 | 1 << 57);
+        assert_eq!(game.legal_destinations(1 << 10), 1 << 17 | 1 << 24);
+
+        // Every legal move is covered, and nothing else
+        let game = Game::read_FEN(KIWIPETE);
+        let total: u32 = iter_bits(game.position.color_bitboards[Color::White as usize])
+            .map(|square| game.legal_destinations(1 << square).count_ones())
+            .sum();
+        assert_eq!(total as usize, game.legal_moves().len());
+    }
+
+    #[test]
+    fn make_move_uci_plays_or_explains() {
+        let mut game = Game::initialize();
+        assert_eq!(game.make_move_uci("e2e4"), Ok(()));
+        assert_eq!(game.to_FEN(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+
+        let fen = game.to_FEN();
+        assert_eq!(game.make_move_uci("e2e4"), Err("Illegal move: 'e2e4'".to_string()));
+        assert_eq!(game.make_move_uci("e2"), Err("Invalid move: 'e2'".to_string()));
+        assert_eq!(game.to_FEN(), fen);
+    }
+
+    #[test]
+    fn apply_moves_stops_at_the_first_illegal_move() {
+        let mut game = Game::initialize();
+        assert_eq!(game.apply_moves(&[Move::new(12, 28), Move::new(52, 36)]), Ok(()));
+        assert_eq!(game.to_FEN(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2");
+
+        let mut game = Game::initialize();
+        let result = game.apply_moves(&[Move::new(12, 28), Move::new(52, 36), Move::new(28, 36)]);
+        assert_eq!(result, Err("Illegal move e4e5 at index 2".to_string()));
+        assert_eq!(game.to_FEN(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2");
+    }
+
+    #[test]
+    fn make_and_unmake_restore_the_position() {
+        let mut game = Game::read_FEN(KIWIPETE);
+        for mv in game.legal_moves() {
+            let undo = game.make_move(&mv);
+            assert_ne!(game.to_FEN(), KIWIPETE);
+            game.unmake_move(&mv, undo);
+            assert_eq!(game.to_FEN(), KIWIPETE, "after {}", mv);
+
+            for (i, square) in game.squares.iter().enumerate() {
+                if let Occupant::Occupied(idx) = square {
+                    assert_eq!(game.pieces[*idx].position, 1 << i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn promotions_replace_the_pawn_and_unmake_restores_it() {
+        let fen = "3r4/4Pk2/8/8/8/8/8/4K3 w - - 0 1";
+        let mut game = Game::read_FEN(fen);
+        for (pt, letter) in [(PieceType::Queen, 'Q'), (PieceType::Rook, 'R'),
+                             (PieceType::Bishop, 'B'), (PieceType::Knight, 'N')] {
+            let mv = Move { from: 52, to: 60, promotion: Some(pt) };
+            let undo = game.make_move(&mv);
+            assert_eq!(game.to_FEN(), format!("3r{}3/5k2/8/8/8/8/8/4K3 b - - 0 1", letter));
+            assert_eq!(game.pieces_of(Color::White, pt), 1 << 60);
+            assert_eq!(game.pieces_of(Color::White, PieceType::Pawn), 0);
+            game.unmake_move(&mv, undo);
+            assert_eq!(game.to_FEN(), fen);
+        }
+
+        // exd8=N+ captures the rook and checks the king on f7
+        let mv = Move { from: 52, to: 59, promotion: Some(PieceType::Knight) };
+        let undo = game.make_move(&mv);
+        assert_eq!(game.to_FEN(), "3N4/5k2/8/8/8/8/8/4K3 b - - 0 1");
+        assert!(game.is_in_check(Color::Black));
+        assert_eq!(game.hash, game.compute_hash());
+        game.unmake_move(&mv, undo);
+        assert_eq!(game.to_FEN(), fen);
+        assert_eq!(game.pieces_of(Color::Black, PieceType::Rook), 1 << 59);
+        assert_eq!(game.hash, Game::read_FEN(fen).hash);
+    }
+
+    #[test]
+    fn legal_captures_are_the_capturing_legal_moves() {
+        let game = Game::read_FEN(KIWIPETE);
+        let mut expected: Vec<Move> = game.legal_moves().into_iter()
+            .filter(|mv| game.is_capture(mv) || mv.promotion.is_some())
+            .collect();
+        let mut captures = game.legal_captures();
+        expected.sort_by_key(|mv| (mv.from, mv.to));
+        captures.sort_by_key(|mv| (mv.from, mv.to));
+        assert_eq!(captures, expected);
+        assert_eq!(captures.len(), 8);
+    }
+
+    #[test]
+    fn null_move_passes_the_turn() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        let mut game = Game::read_FEN(fen);
+        let undo = game.make_null_move();
+        assert_eq!(game.to_FEN(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2");
+        assert_eq!(game.hash, game.compute_hash());
+
+        game.unmake_null_move(undo);
+        assert_eq!(game.to_FEN(), fen);
+        assert_eq!(game.hash, game.compute_hash());
+    }
+
+    #[test]
+    fn attacks_from_looks_up_the_piece() {
+        let lone = Game::read_FEN("k7/8/8/8/3Q4/8/8/7K w - - 0 1");
+        let d4 = position_to_bit("d4").unwrap();
+        assert_eq!(lone.attacks_from(d4).count_ones(), 27);
+
+        // Blocked by its own pawns on d5 and e4, the queen still sees them
+        let blocked = Game::read_FEN("k7/8/8/3P4/3QP3/8/8/7K w - - 0 1");
+        let attacks = blocked.attacks_from(d4);
+        assert_eq!(attacks.count_ones(), 21);
+        assert_ne!(attacks & position_to_bit("d5").unwrap(), 0);
+        assert_eq!(attacks & position_to_bit("d6").unwrap(), 0);
+
+        assert_eq!(blocked.attacks_from(position_to_bit("d5").unwrap()),
+                   position_to_bit("c6").unwrap() | position_to_bit("e6").unwrap());
+        assert_eq!(blocked.attacks_from(position_to_bit("h1").unwrap()).count_ones(), 3);
+        assert_eq!(blocked.attacks_from(position_to_bit("e5").unwrap()), 0);
+        assert_eq!(blocked.attacks_from(0), 0);
+    }
+
+    #[test]
+    fn move_legality_names_the_broken_rule() {
+        let cases = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e2e4", MoveLegality::Legal),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e3e4", MoveLegality::NoPieceAtSource),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e7e5", MoveLegality::WrongColor),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "d1d2", MoveLegality::OwnPieceOnTarget),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "g1g3", MoveLegality::NotAPieceMove),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e2e5", MoveLegality::NotAPieceMove),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e2d3", MoveLegality::NotAPieceMove),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "f1c4", MoveLegality::BlockedPath),
+            ("rnbqkbnr/pppp1ppp/8/8/8/4p3/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e2e4", MoveLegality::BlockedPath),
+            ("8/4P3/8/8/8/8/8/k1K5 w - - 0 1", "e7e8", MoveLegality::BadPromotion),
+            ("8/8/4P3/8/8/8/8/k1K5 w - - 0 1", "e6e7q", MoveLegality::BadPromotion),
+            ("8/4P3/8/8/8/8/8/k1K5 w - - 0 1", "e7e8q", MoveLegality::Legal),
+            ("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1", "e2d3", MoveLegality::LeavesKingInCheck),
+            ("4k3/8/8/8/8/8/8/R3K2R w Q - 0 1", "e1g1", MoveLegality::NoCastlingRights),
+            ("4k3/4r3/8/8/8/8/8/R3K2R w KQ - 0 1", "e1g1", MoveLegality::CastleOutOfCheck),
+            ("4k3/5r2/8/8/8/8/8/R3K2R w KQ - 0 1", "e1g1", MoveLegality::CastleThroughCheck),
+            ("4k3/6r1/8/8/8/8/8/R3K2R w KQ - 0 1", "e1g1", MoveLegality::CastleThroughCheck),
+            ("4k3/1r6/8/8/8/8/8/R3K2R w KQ - 0 1", "e1c1", MoveLegality::Legal),
+            ("4k3/8/8/8/8/8/8/RN2K2R w KQ - 0 1", "e1c1", MoveLegality::BlockedPath),
+        ];
+        for (fen, text, expected) in cases {
+            let game = Game::read_FEN(fen);
+            let mv = text.parse::<Move>().unwrap();
+            assert_eq!(game.move_legality(&mv), expected, "{} in {}", text, fen);
+            assert_eq!(game.legal_moves().contains(&mv), expected == MoveLegality::Legal, "{} in {}", text, fen);
+        }
+        assert_eq!(Game::initialize().move_legality(&Move::new(12, 64)), MoveLegality::OffBoard);
+    }
+
+    #[test]
+    fn move_legality_agrees_with_the_generator() {
+        let game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let legal = game.legal_moves();
+        for from in 0..64 {
+            for to in 0..64 {
+                for promotion in [None, Some(PieceType::Queen)] {
+                    let mv = Move { from, to, promotion };
+                    assert_eq!(game.move_legality(&mv) == MoveLegality::Legal, legal.contains(&mv), "{}", mv);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn check_detection() {
+        let game = Game::read_FEN("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert!(game.is_in_check(Color::White));
+        assert!(!game.is_in_check(Color::Black));
+        assert!(game.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn checkers_and_pinned_pieces() {
+        // Bishop on b5 pins the d7 pawn, the rook on e1 checks through e2..e7
+        let game = Game::read_FEN("4k3/3p4/8/1B6/8/8/8/4R1K1 b - - 0 1");
+        assert_eq!(game.checkers(Color::Black), 1 << 4);
+        assert_eq!(game.pinned_pieces(Color::Black), 1 << 51);
+        assert_eq!(game.checkers(Color::White), 0);
+        assert_eq!(game.pinned_pieces(Color::White), 0);
+
+        // Two pieces in the way: no pin
+        let game = Game::read_FEN("4k3/3p4/2p5/1B6/8/8/8/6K1 b - - 0 1");
+        assert_eq!(game.pinned_pieces(Color::Black), 0);
+    }
+
+    #[test]
+    fn checking_pieces_in_single_and_double_check() {
+        assert!(Game::initialize().checking_pieces().is_empty());
+        let game = Game::read_FEN("4k3/3p4/8/1B6/8/8/8/4R1K1 b - - 0 1");
+        assert_eq!(game.checking_pieces(), [1 << 4]);
+
+        // Knight on d6 and rook on e1, after a discovered check
+        let game = Game::read_FEN("4k3/8/3N4/8/8/8/8/K3R3 b - - 0 1");
+        assert_eq!(game.checking_pieces(), [1 << 4, 1 << 43]);
+        assert!(game.legal_moves().iter().all(|mv| mv.from == 60));
+    }
+
+    #[test]
+    fn legal_checks_include_discovered_checks() {
+        // Every knight move uncovers the rook on e1; the rook and king
+        // moves give no check
+        let game = Game::read_FEN("4k3/8/8/8/4N3/8/8/K3R3 w - - 0 1");
+        let mut checks = game.legal_checks();
+        checks.sort_by_key(|mv| mv.to);
+        let knight_moves: Vec<Move> = [11, 13, 18, 22, 34, 38, 43, 45].iter().map(|&to| Move::new(28, to)).collect();
+        assert_eq!(checks, knight_moves);
+
+        // Direct checks, a checking castle and a checking promotion
+        let game = Game::read_FEN("5k2/1P6/8/8/8/8/8/4K2R w K - 0 1");
+        let mut checks: Vec<String> = game.legal_checks().iter().map(|mv| mv.to_string()).collect();
+        checks.sort();
+        assert_eq!(checks, ["b7b8q", "b7b8r", "e1g1", "h1f1", "h1h8"]);
+        assert!(Game::initialize().legal_checks().is_empty());
+    }
+
+    #[test]
+    fn attack_map_unions_piece_attacks() {
+        // Ranks 2 and 3 in full plus b1 through g1
+        let game = Game::initialize();
+        assert_eq!(game.attack_map(Color::White).count_ones(), 22);
+        assert_eq!(game.attack_map(Color::White), 0x7E | 0xFFFF00);
+
+        // A lone pawn attacks diagonally and not the square ahead
+        let game = Game::read_FEN("4k3/8/8/8/3P4/8/8/4K3 w - - 0 1");
+        assert_eq!(game.attack_map(Color::White) & (1 << 35 | 1 << 36 | 1 << 34), 1 << 34 | 1 << 36);
+
+        let game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        for color in [Color::White, Color::Black] {
+            let map = game.attack_map(color);
+            for square in 0..64 {
+                assert_eq!(map & (1 << square) != 0, game.is_square_attacked(square, color), "{}", square);
+            }
+        }
+    }
+
+    // The legal moves found by making each pseudo-legal move and looking
+    // for a check, as the generator used to do
+    fn legal_by_make_unmake(game: &mut Game) -> Vec<Move> {
+        let mut pseudo = Vec::new();
+        game.generate_pseudo_legal(&mut pseudo, false);
+        let us = game.position.active_color;
+        pseudo.into_iter().filter(|mv| {
+            let undo = game.make_move(mv);
+            let legal = !game.is_in_check(us);
+            game.unmake_move(mv, undo);
+            legal
+        }).collect()
+    }
+
+    fn compare_generators(game: &mut Game, depth: usize) {
+        let mut fast = game.legal_moves();
+        let mut reference = legal_by_make_unmake(game);
+        let key = |mv: &Move| (mv.from, mv.to, mv.promotion.map(|pt| pt as usize));
+        fast.sort_by_key(key);
+        reference.sort_by_key(key);
+        assert_eq!(fast, reference, "{}", game.to_FEN());
+
+        if depth > 1 {
+            for mv in fast {
+                let undo = game.make_move(&mv);
+                compare_generators(game, depth - 1);
+                game.unmake_move(&mv, undo);
+            }
+        }
+    }
+
+    fn compare_counts(game: &mut Game, depth: usize) {
+        let moves = game.legal_moves();
+        assert_eq!(game.legal_move_count(), moves.len(), "{}", game.to_FEN());
+        assert_eq!(game.has_legal_move(), !moves.is_empty(), "{}", game.to_FEN());
+        if depth > 1 {
+            for mv in moves {
+                let undo = game.make_move(&mv);
+                compare_counts(game, depth - 1);
+                game.unmake_move(&mv, undo);
+            }
+        }
+    }
+
+    // Plays `games` random games of up to `plies` moves from a few starting
+    // positions, checking at every step that the pin-aware generator, the
+    // make/unmake filter and the move counter agree and that the FEN reads
+    // back the same. Failures name the FEN; the same seed replays them.
+    fn fuzz_movegen(seed: u64, games: usize, plies: usize) {
+        let starts = ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                      KIWIPETE,
+                      "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                      "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                      "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"];
+        let mut state = seed.max(1);
+        for index in 0..games {
+            let mut game = Game::read_FEN(starts[index % starts.len()]);
+            for _ in 0..plies {
+                let fen = game.to_FEN();
+                let mut fast = game.legal_moves();
+                let mut slow = legal_by_make_unmake(&mut game);
+                let key = |mv: &Move| (mv.from, mv.to, mv.promotion.map(|pt| pt as usize));
+                fast.sort_by_key(key);
+                slow.sort_by_key(key);
+                assert_eq!(fast, slow, "generators disagree in {} (seed {})", fen, seed);
+                assert_eq!(game.legal_move_count(), fast.len(), "move count is off in {} (seed {})", fen, seed);
+                assert_eq!(game.has_legal_move(), !fast.is_empty(), "{} (seed {})", fen, seed);
+                assert_eq!(Game::read_FEN(&fen).to_FEN(), fen, "FEN doesn't round-trip (seed {})", seed);
+                if fast.is_empty() {
+                    break;
+                }
+
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                game.make_move(&fast[state as usize % fast.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn generators_agree_in_random_games() {
+        for seed in [1, 0x9E37_79B9_7F4A_7C15, 2024] {
+            fuzz_movegen(seed, 10, 150);
+        }
+    }
+
+    #[test]
+    fn legal_move_count_matches_the_generator() {
+        for fen in [KIWIPETE,
+                    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                    "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"] {
+            compare_counts(&mut Game::read_FEN(fen), 3);
+        }
+        assert_eq!(Game::initialize().legal_move_count(), 20);
+
+        // Checkmate, stalemate, and a position whose only moves are a
+        // pinned bishop's along the pin
+        assert!(!Game::read_FEN("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").has_legal_move());
+        assert!(!Game::read_FEN("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").has_legal_move());
+        let pinned = Game::read_FEN("k7/1b6/8/8/8/8/8/KQ6 b - - 0 1");
+        assert_eq!(pinned.legal_move_count(), pinned.legal_moves().len());
+    }
+
+    #[test]
+    fn pin_aware_generator_matches_make_unmake_filter() {
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    KIWIPETE,
+                    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                    "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"] {
+            compare_generators(&mut Game::read_FEN(fen), 3);
+        }
+    }
+
+    #[test]
+    fn coordinate_moves_parse_and_check_legality() {
+        assert_eq!("e2e4".parse::<Move>(), Ok(Move::new(12, 28)));
+        assert_eq!("e7e8n".parse::<Move>(), Ok(Move { from: 52, to: 60, promotion: Some(PieceType::Knight) }));
+        for text in ["e2e", "e2e4k", "i2e4", "e2e9", "e2e4qq", "Nf3"] {
+            assert!(text.parse::<Move>().is_err(), "{}", text);
+        }
+
+        let game = Game::initialize();
+        assert_eq!(game.uci_to_move("g1f3"), Ok(Move::new(6, 21)));
+        assert_eq!(game.uci_to_move("e2e5"), Err("Illegal move: 'e2e5'".to_string()));
+        assert_eq!(game.uci_to_move("e2"), Err("Invalid move: 'e2'".to_string()));
+    }
+
+    #[test]
+    fn move_display_uses_coordinates() {
+        assert_eq!(Move::new(12, 28).to_string(), "e2e4");
+        assert_eq!(Move { from: 52, to: 60, promotion: Some(PieceType::Knight) }.to_string(), "e7e8n");
+    }
+}
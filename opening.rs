@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use crate::game::*;
+use crate::movegen::Move;
+use crate::pgn::*;
+
+// How often a move was played from a position and how those games ended
+#[derive(Debug, PartialEq, Eq, Default, Clone, Copy)]
+pub struct MoveStats {
+    pub games: u32,
+    pub white_wins: u32,
+    pub draws: u32,
+    pub black_wins: u32,
+}
+
+impl MoveStats {
+    fn record(&mut self, result: &str) {
+        self.games += 1;
+        match result {
+            "1-0" => self.white_wins += 1,
+            "0-1" => self.black_wins += 1,
+            "1/2-1/2" => self.draws += 1,
+            _ => (),
+        }
+    }
+}
+
+// The moves played from each position of a collection of games, keyed by
+// Zobrist hash so that transpositions share their statistics
+#[derive(Debug, Default, Clone)]
+pub struct OpeningTree {
+    positions: HashMap<u64, Vec<(Move, MoveStats)>>,
+    pub games: usize,
+}
+
+impl OpeningTree {
+    pub fn new() -> OpeningTree {
+        OpeningTree::default()
+    }
+
+    // Adds the mainline of a game; variations are analysis, not games played
+    pub fn add_game(&mut self, parsed: &ParsedGame) {
+        let mut game = parsed.start();
+        for mv in parsed.mainline() {
+            let moves = self.positions.entry(game.hash).or_default();
+            let index = match moves.iter().position(|(known, _)| *known == mv) {
+                Some(index) => index,
+                None => {
+                    moves.push((mv, MoveStats::default()));
+                    moves.len() - 1
+                }
+            };
+            moves[index].1.record(&parsed.result);
+            game.make_move(&mv);
+        }
+        self.games += 1;
+    }
+
+    // The moves played from the position, most frequent first
+    pub fn suggest(&self, game: &Game) -> Vec<(Move, MoveStats)> {
+        let mut moves = self.positions.get(&game.hash).cloned().unwrap_or_default();
+        moves.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.games));
+        moves
+    }
+}
+
+// Builds a tree from a file of games. Games that fail to parse are left out.
+pub fn build_opening_tree(pgns: &str) -> OpeningTree {
+    let mut tree = OpeningTree::new();
+    for text in split_games(pgns) {
+        if let Ok(parsed) = parse_pgn(&text) {
+            tree.add_game(&parsed);
+        }
+    }
+    tree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(sans: &[&str]) -> Game {
+        let mut game = Game::initialize();
+        for san in sans {
+            let mv = game.san_to_move(san).unwrap();
+            game.make_move(&mv);
+        }
+        game
+    }
+
+    #[test]
+    fn transpositions_share_their_statistics() {
+        let pgns = "[Result \"1-0\"]\n\n1. d4 Nf6 2. c4 e6 3. Nc3 Bb4 1-0\n\n\
+                    [Result \"1/2-1/2\"]\n\n1. c4 e6 2. d4 Nf6 3. Nf3 d5 1/2-1/2\n\n\
+                    [Result \"0-1\"]\n\n1. c4 Nf6 2. d4 e6 3. Nf3 b6 0-1\n\n\
+                    [Result \"1-0\"]\n\n1. d4 Nf6 2. c4 e6 3. Nc3 d5 1-0\n\n\
+                    [Result \"*\"]\n\n1. e4 Ke7 2. Ke3 *\n\n\
+                    [Result \"1-0\"]\n\n1. d4 d5 1-0\n";
+        let tree = build_opening_tree(pgns);
+        // The one with the illegal move is left out
+        assert_eq!(tree.games, 5);
+
+        let start = Game::initialize();
+        let first: Vec<(String, u32)> = tree.suggest(&start).iter()
+            .map(|(mv, stats)| (start.move_to_san(mv), stats.games)).collect();
+        assert_eq!(first, [("d4".to_string(), 3), ("c4".to_string(), 2)]);
+
+        // Every move order but the last reaches this position
+        let position = play(&["d4", "Nf6", "c4", "e6"]);
+        let third: Vec<(String, MoveStats)> = tree.suggest(&position).iter()
+            .map(|(mv, stats)| (position.move_to_san(mv), *stats)).collect();
+        assert_eq!(third, [
+            ("Nc3".to_string(), MoveStats { games: 2, white_wins: 2, draws: 0, black_wins: 0 }),
+            ("Nf3".to_string(), MoveStats { games: 2, white_wins: 0, draws: 1, black_wins: 1 }),
+        ]);
+        assert!(tree.suggest(&play(&["a4"])).is_empty());
+    }
+}
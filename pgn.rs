@@ -0,0 +1,453 @@
+use std::fmt;
+use crate::game::*;
+use crate::movegen::Move;
+
+// A move of the game tree. The first child continues the line the move
+// belongs to; any further children are variations replacing that move.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnNode {
+    pub mv: Move,
+    pub comment: Option<String>,
+    // Numeric annotation glyphs: $1 or "!" is 1, $2 or "?" is 2, ...
+    pub nags: Vec<u8>,
+    pub children: Vec<PgnNode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedGame {
+    pub tags: Vec<(String, String)>,
+    // The first move of the game followed by its alternatives
+    pub moves: Vec<PgnNode>,
+    pub result: String,
+}
+
+impl ParsedGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    // The moves of the game without its variations
+    pub fn mainline(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let mut node = self.moves.first();
+        while let Some(current) = node {
+            moves.push(current.mv);
+            node = current.children.first();
+        }
+        moves
+    }
+
+    // The game after the first `ply` moves of the mainline. Parsing already
+    // checked every move, so they are played without looking at SAN or
+    // the legal moves again.
+    pub fn position_after(&self, ply: usize) -> Result<Game, String> {
+        let mut game = self.start();
+        let mut node = self.moves.first();
+        for played in 0..ply {
+            match node {
+                Some(current) => {
+                    game.make_move(&current.mv);
+                    node = current.children.first();
+                }
+                None => return Err(format!("Ply {} is past the end of the game after {} plies", ply, played)),
+            }
+        }
+        Ok(game)
+    }
+
+    // The position the moves start from: the FEN tag's if the game was set
+    // up, the initial position otherwise. Parsing already checked the FEN.
+    pub fn start(&self) -> Game {
+        start_fen(&self.tags)
+            .and_then(|fen| Game::try_read_FEN(fen).ok())
+            .unwrap_or_else(Game::initialize)
+    }
+}
+
+// The FEN tag of a game that doesn't start from the initial position. A
+// FEN without SetUp counts too, as plenty of files leave SetUp out.
+fn start_fen(tags: &[(String, String)]) -> Option<&str> {
+    let tag = |name: &str| tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str());
+    match tag("SetUp") {
+        Some("0") => None,
+        _ => tag("FEN"),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PgnError {
+    Tag(String),
+    Fen(FenError),
+    Comment,
+    Annotation(String),
+    Variation,
+    Move(String),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Tag(tag) => write!(f, "Invalid tag pair: '[{}]'", tag),
+            PgnError::Fen(err) => write!(f, "Invalid FEN tag: {}", err),
+            PgnError::Comment => write!(f, "Unterminated comment"),
+            PgnError::Annotation(nag) => write!(f, "Invalid annotation: '{}'", nag),
+            PgnError::Variation => write!(f, "Unbalanced or misplaced variation"),
+            PgnError::Move(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Open,
+    Close,
+    Nag(u8),
+    San(String),
+    Result(String),
+}
+
+fn parse_tag(pair: &str) -> Result<Token, PgnError> {
+    let pair = pair.trim();
+    let end = pair.find(char::is_whitespace).ok_or_else(|| PgnError::Tag(pair.to_string()))?;
+    let value = pair[end..].trim();
+    if end == 0 || value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return Err(PgnError::Tag(pair.to_string()));
+    }
+    Ok(Token::Tag(pair[..end].to_string(), value[1..value.len() - 1].to_string()))
+}
+
+fn suffix_nag(suffix: &str) -> Result<u8, PgnError> {
+    match suffix {
+        "!" => Ok(1),
+        "?" => Ok(2),
+        "!!" => Ok(3),
+        "??" => Ok(4),
+        "!?" => Ok(5),
+        "?!" => Ok(6),
+        _ => Err(PgnError::Annotation(suffix.to_string())),
+    }
+}
+
+// A word of movetext: a result, a move number, or a move with an optional
+// "!"/"?" suffix, which becomes its NAG
+fn push_word(word: &str, tokens: &mut Vec<Token>) -> Result<(), PgnError> {
+    if matches!(word, "1-0" | "0-1" | "1/2-1/2" | "*") {
+        tokens.push(Token::Result(word.to_string()));
+        return Ok(());
+    }
+
+    let mut san = word;
+    if !san.starts_with("0-0") {
+        san = san.trim_start_matches(|ch: char| ch.is_ascii_digit()).trim_start_matches('.');
+    }
+    if san.is_empty() {
+        return Ok(());
+    }
+
+    let text = san.trim_end_matches(['!', '?']);
+    tokens.push(Token::San(text.to_string()));
+    if text.len() < san.len() {
+        tokens.push(Token::Nag(suffix_nag(&san[text.len()..])?));
+    }
+    Ok(())
+}
+
+fn tokenize(pgn: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = pgn.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(ch) => comment.push(ch),
+                        None => return Err(PgnError::Comment),
+                    }
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|ch| *ch != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '[' => {
+                let mut pair = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(ch) => pair.push(ch),
+                        None => return Err(PgnError::Tag(pair)),
+                    }
+                }
+                tokens.push(parse_tag(&pair)?);
+            }
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => {
+                let mut digits = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(digit);
+                }
+                let nag = digits.parse().map_err(|_| PgnError::Annotation(format!("${}", digits)))?;
+                tokens.push(Token::Nag(nag));
+            }
+            ch if ch.is_whitespace() => (),
+            ch => {
+                let mut word = ch.to_string();
+                while let Some(ch) = chars.next_if(|ch| !ch.is_whitespace() && !"{};[()$".contains(*ch)) {
+                    word.push(ch);
+                }
+                push_word(&word, &mut tokens)?;
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn move_number(game: &Game) -> String {
+    match game.position.active_color {
+        Color::White => format!("{}.", game.fullmove_number),
+        Color::Black => format!("{}...", game.fullmove_number),
+    }
+}
+
+fn append_comment(comment: &mut Option<String>, text: &str) {
+    match comment {
+        Some(existing) => {
+            existing.push(' ');
+            existing.push_str(text);
+        }
+        None => *comment = Some(text.to_string()),
+    }
+}
+
+// Parses the moves of a line played from `start` until the ")" closing it,
+// a result or the end of the tokens. Returns the first move of the line
+// followed by the variations replacing it, each with its continuation as
+// children.
+fn parse_line(tokens: &[Token], index: &mut usize, start: &Game) -> Result<Vec<PgnNode>, PgnError> {
+    let mut line: Vec<PgnNode> = Vec::new();
+    let mut alternatives: Vec<Vec<PgnNode>> = Vec::new();
+    let mut before = start.clone();
+    let mut game = start.clone();
+    let mut leading_comment = None;
+
+    while let Some(token) = tokens.get(*index) {
+        match token {
+            Token::Close | Token::Result(_) => break,
+            Token::San(san) => {
+                let mv = game.san_to_move(san)
+                    .map_err(|err| PgnError::Move(format!("{} at move {}", err, move_number(&game))))?;
+                before = game.clone();
+                game.make_move(&mv);
+                line.push(PgnNode { mv, comment: leading_comment.take(), nags: Vec::new(), children: Vec::new() });
+                alternatives.push(Vec::new());
+            }
+            Token::Nag(nag) => match line.last_mut() {
+                Some(node) => node.nags.push(*nag),
+                None => return Err(PgnError::Annotation(format!("${}", nag))),
+            },
+            Token::Comment(text) => match line.last_mut() {
+                Some(node) => append_comment(&mut node.comment, text),
+                None => append_comment(&mut leading_comment, text),
+            },
+            Token::Open => {
+                let replaced = alternatives.last_mut().ok_or(PgnError::Variation)?;
+                *index += 1;
+                replaced.extend(parse_line(tokens, index, &before)?);
+                if tokens.get(*index) != Some(&Token::Close) {
+                    return Err(PgnError::Variation);
+                }
+            }
+            Token::Tag(name, value) => return Err(PgnError::Tag(format!("{} \"{}\"", name, value))),
+        }
+        *index += 1;
+    }
+
+    let mut next = Vec::new();
+    while let (Some(mut node), Some(replacements)) = (line.pop(), alternatives.pop()) {
+        node.children = next;
+        next = vec![node];
+        next.extend(replacements);
+    }
+    Ok(next)
+}
+
+// Parses a single game: its tag pairs, then movetext with `{...}` and `;`
+// comments, NAGs and recursive variations in parentheses. Every move is
+// resolved against its position, so the tree holds only legal moves.
+pub fn parse_pgn(pgn: &str) -> Result<ParsedGame, PgnError> {
+    let tokens = tokenize(pgn)?;
+
+    let mut tags = Vec::new();
+    let mut index = 0;
+    while let Some(Token::Tag(name, value)) = tokens.get(index) {
+        tags.push((name.clone(), value.clone()));
+        index += 1;
+    }
+
+    let start = match start_fen(&tags) {
+        Some(fen) => Game::try_read_FEN(fen).map_err(PgnError::Fen)?,
+        None => Game::initialize(),
+    };
+    let moves = parse_line(&tokens, &mut index, &start)?;
+    // Movetext cut short of its result falls back on the Result tag
+    let result = match tokens.get(index) {
+        Some(Token::Result(result)) => result.clone(),
+        Some(_) => return Err(PgnError::Variation),
+        None => tags.iter().find(|(tag, _)| tag == "Result")
+            .map_or("*".to_string(), |(_, value)| value.clone()),
+    };
+
+    Ok(ParsedGame { tags, moves, result })
+}
+
+// Splits a file of several games into the text of each: a tag pair after
+// movetext starts the next game.
+pub fn split_games(pgns: &str) -> Vec<String> {
+    let mut games = Vec::new();
+    let mut current = String::new();
+    let mut in_movetext = false;
+    for line in pgns.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && in_movetext {
+            games.push(std::mem::take(&mut current));
+            in_movetext = false;
+        } else if !trimmed.is_empty() && !trimmed.starts_with('[') {
+            in_movetext = true;
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.trim().is_empty() {
+        games.push(current);
+    }
+    games
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san(game: &Game, mv: &Move) -> String {
+        game.move_to_san(mv)
+    }
+
+    #[test]
+    fn parse_pgn_reads_tags_mainline_and_result() {
+        let pgn = "[Event \"Casual\"]\n[White \"Anderssen\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1-0";
+        let parsed = parse_pgn(pgn).unwrap();
+        assert_eq!(parsed.tag("White"), Some("Anderssen"));
+        assert_eq!(parsed.tag("Black"), None);
+        assert_eq!(parsed.result, "1-0");
+
+        let mut game = Game::initialize();
+        for mv in parsed.mainline() {
+            game.make_move(&mv);
+        }
+        assert_eq!(game.to_FEN(), "r1bqkbnr/1ppp1ppp/p1n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 0 4");
+    }
+
+    #[test]
+    fn parse_pgn_builds_a_tree_of_nested_variations() {
+        let pgn = "1. e4 {King's pawn} e5 (1... c5 $1 2. Nf3 (2. c3 d5) 2... d6) (1... e6) 2. Nf3! *";
+        let parsed = parse_pgn(pgn).unwrap();
+        let start = Game::initialize();
+
+        assert_eq!(parsed.moves.len(), 1);
+        let e4 = &parsed.moves[0];
+        assert_eq!(san(&start, &e4.mv), "e4");
+        assert_eq!(e4.comment.as_deref(), Some("King's pawn"));
+
+        let mut after_e4 = start.clone();
+        after_e4.make_move(&e4.mv);
+        let replies: Vec<String> = e4.children.iter().map(|node| san(&after_e4, &node.mv)).collect();
+        assert_eq!(replies, ["e5", "c5", "e6"]);
+
+        let e5 = &e4.children[0];
+        assert_eq!(e5.children.len(), 1);
+        assert_eq!(e5.children[0].nags, [1]);
+
+        let c5 = &e4.children[1];
+        assert_eq!(c5.nags, [1]);
+        let mut after_c5 = after_e4.clone();
+        after_c5.make_move(&c5.mv);
+        let seconds: Vec<String> = c5.children.iter().map(|node| san(&after_c5, &node.mv)).collect();
+        assert_eq!(seconds, ["Nf3", "c3"]);
+        assert_eq!(c5.children[0].children.len(), 1);
+        assert_eq!(c5.children[1].children.len(), 1);
+        assert!(c5.children[1].children[0].children.is_empty());
+
+        assert!(e4.children[2].children.is_empty());
+        assert_eq!(parsed.mainline().len(), 3);
+        assert_eq!(parsed.result, "*");
+    }
+
+    #[test]
+    fn parse_pgn_starts_from_the_fen_tag() {
+        let pgn = "[SetUp \"1\"]\n[FEN \"6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 30\"]\n\
+                   [Result \"1-0\"]\n\n30... h6 31. Ra8+ Kh7";
+        let parsed = parse_pgn(pgn).unwrap();
+        assert_eq!(parsed.result, "1-0");
+
+        let mut game = parsed.start();
+        for mv in parsed.mainline() {
+            game.make_move(&mv);
+        }
+        assert_eq!(game.to_FEN(), "R7/5ppk/7p/8/8/8/5PPP/6K1 w - - 2 32");
+
+        // Numbered from the FEN's move, not from the start of a game
+        let illegal = "[FEN \"6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 30\"]\n30... h6 31. Ra8+ Kh8";
+        assert_eq!(parse_pgn(illegal).err(),
+                   Some(PgnError::Move("Illegal move: 'Kh8' at move 31...".to_string())));
+        // SetUp "0" plays from the initial position whatever the FEN says
+        assert_eq!(parse_pgn("[SetUp \"0\"]\n[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n1. e4").unwrap().mainline().len(), 1);
+        assert!(matches!(parse_pgn("[SetUp \"1\"]\n[FEN \"8/8/8\"]\n1. e4"), Err(PgnError::Fen(_))));
+    }
+
+    #[test]
+    fn position_after_plays_the_mainline_up_to_a_ply() {
+        let parsed = parse_pgn("1. e4 e5 (1... c5 2. Nf3) 2. Nf3 Nc6 3. Bb5 a6 *").unwrap();
+        assert_eq!(parsed.position_after(0).unwrap().to_FEN(), Game::initialize().to_FEN());
+        assert_eq!(parsed.position_after(4).unwrap().to_FEN(),
+                   "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        assert_eq!(parsed.position_after(6).unwrap().move_history.len(), 6);
+        assert_eq!(parsed.position_after(7).err(),
+                   Some("Ply 7 is past the end of the game after 6 plies".to_string()));
+
+        let from_fen = parse_pgn("[FEN \"6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 30\"]\n30. Ra8#").unwrap();
+        assert_eq!(from_fen.position_after(1).unwrap().to_FEN(), "R5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 1 30");
+    }
+
+    #[test]
+    fn split_games_starts_a_game_at_each_tag_block() {
+        let pgns = "[Event \"One\"]\n[Result \"1-0\"]\n\n1. e4 e5\n2. Qh5 1-0\n\n\
+                    [Event \"Two\"]\n\n1. d4 *\n1. c4 *\n";
+        let games = split_games(pgns);
+        assert_eq!(games.len(), 2);
+        assert!(games[0].ends_with("2. Qh5 1-0\n\n"));
+        assert_eq!(parse_pgn(&games[0]).unwrap().tag("Event"), Some("One"));
+        // Movetext without tags between runs on into one game
+        assert_eq!(games[1], "[Event \"Two\"]\n\n1. d4 *\n1. c4 *\n");
+        assert!(split_games("\n\n").is_empty());
+    }
+
+    #[test]
+    fn parse_pgn_reports_bad_movetext() {
+        assert_eq!(parse_pgn("1. e4 e5 2. Ke3").err(),
+                   Some(PgnError::Move("Illegal move: 'Ke3' at move 2.".to_string())));
+        assert_eq!(parse_pgn("1. e4 e5 2. Nf3 Qe4").err(),
+                   Some(PgnError::Move("Illegal move: 'Qe4' at move 2...".to_string())));
+        assert_eq!(parse_pgn("1. e4 (1. d4 e5").err(), Some(PgnError::Variation));
+        assert_eq!(parse_pgn("1. e4 e5) 2. Nf3").err(), Some(PgnError::Variation));
+        assert_eq!(parse_pgn("(1. d4) 1. e4").err(), Some(PgnError::Variation));
+        assert_eq!(parse_pgn("1. e4 {unfinished").err(), Some(PgnError::Comment));
+        assert!(matches!(parse_pgn("[Event Casual]\n1. e4"), Err(PgnError::Tag(_))));
+    }
+}
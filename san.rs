@@ -0,0 +1,437 @@
+use crate::game::*;
+use crate::movegen::*;
+
+fn piece_letter(pt: PieceType) -> &'static str {
+    match pt {
+        PieceType::Pawn => "",
+        PieceType::Knight => "N",
+        PieceType::Bishop => "B",
+        PieceType::Rook => "R",
+        PieceType::Queen => "Q",
+        PieceType::King => "K",
+    }
+}
+
+fn piece_from_letter(letter: char) -> Option<PieceType> {
+    match letter {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+// Four or five lowercase characters: two squares and maybe a promotion.
+// SAN never looks like this since its destination square comes last.
+fn is_coordinate_move(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    (bytes.len() == 4 || bytes.len() == 5)
+        && bytes.iter().all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit())
+        && bytes[1].is_ascii_digit() && bytes[3].is_ascii_digit()
+}
+
+impl Game {
+    fn piece_type_on(&self, square: usize) -> Option<PieceType> {
+        self.piece_at(1 << square).map(|piece| piece.piece_type)
+    }
+
+    // Standard algebraic notation of a legal move, e.g. "Nbd7", "exd6", "e8=Q+"
+    pub fn move_to_san(&self, mv: &Move) -> String {
+        let mut san = String::new();
+
+        if self.is_castling(mv) {
+            san.push_str(if mv.to > mv.from { "O-O" } else { "O-O-O" });
+        } else {
+            let pt = match self.piece_type_on(mv.from) {
+                Some(pt) => pt,
+                None => return mv.to_string(),
+            };
+            let capture = self.is_capture(mv);
+
+            san.push_str(piece_letter(pt));
+            if pt == PieceType::Pawn {
+                if capture {
+                    san.push(COL_MAP[Square::from_index(mv.from).file() as usize]);
+                }
+            } else {
+                san.push_str(&self.disambiguation(mv, pt));
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&index_to_position(mv.to));
+            if let Some(promotion) = mv.promotion {
+                san.push('=');
+                san.push_str(piece_letter(promotion));
+            }
+        }
+
+        san.push_str(self.check_suffix(mv));
+        san
+    }
+
+    // Long algebraic notation: piece, origin, '-' or 'x', destination and
+    // promotion, as in "Ng1-f3", "Nf3xe5" and "e7-e8Q". Castling is written
+    // as in SAN. Nothing needs disambiguating since the origin is given.
+    pub fn move_to_lan(&self, mv: &Move) -> String {
+        let mut lan = String::new();
+
+        if self.is_castling(mv) {
+            lan.push_str(if mv.to > mv.from { "O-O" } else { "O-O-O" });
+        } else {
+            if let Some(pt) = self.piece_type_on(mv.from) {
+                lan.push_str(piece_letter(pt));
+            }
+            lan.push_str(&index_to_position(mv.from));
+            lan.push(if self.is_capture(mv) { 'x' } else { '-' });
+            lan.push_str(&index_to_position(mv.to));
+            if let Some(promotion) = mv.promotion {
+                lan.push_str(piece_letter(promotion));
+            }
+        }
+
+        lan.push_str(self.check_suffix(mv));
+        lan
+    }
+
+    // "+" or "#" when the move checks or mates
+    fn check_suffix(&self, mv: &Move) -> &'static str {
+        let mut after = self.clone();
+        after.make_move(mv);
+        if !after.is_in_check(after.position.active_color) {
+            ""
+        } else if after.has_legal_move() {
+            "+"
+        } else {
+            "#"
+        }
+    }
+
+    // Origin file, rank or square, as needed to tell the move apart from
+    // other pieces of the same type that can reach the same square
+    fn disambiguation(&self, mv: &Move, pt: PieceType) -> String {
+        let rivals: Vec<Move> = self.legal_moves().into_iter()
+            .filter(|other| other.to == mv.to && other.from != mv.from
+                    && self.piece_type_on(other.from) == Some(pt))
+            .collect();
+
+        let from = Square::from_index(mv.from);
+        let square = |other: &Move| Square::from_index(other.from);
+        if rivals.is_empty() {
+            String::new()
+        } else if rivals.iter().all(|other| square(other).file() != from.file()) {
+            COL_MAP[from.file() as usize].to_string()
+        } else if rivals.iter().all(|other| square(other).rank() != from.rank()) {
+            (from.rank() + 1).to_string()
+        } else {
+            from.to_string()
+        }
+    }
+
+    // SAN of every move played since the game was set up. Each depends on
+    // the position before it, so the game is replayed from the start.
+    pub fn history_san(&self) -> Vec<String> {
+        let mut game = match Game::try_read_FEN(&self.start_fen) {
+            Ok(game) => game,
+            Err(_) => return Vec::new(),
+        };
+        self.move_history.iter().map(|mv| {
+            let san = game.move_to_san(mv);
+            game.make_move(mv);
+            san
+        }).collect()
+    }
+
+    // Plays a SAN move. The error starts "Invalid move" when the text can't
+    // be read and "Illegal move" or "Ambiguous move" when it can but names
+    // no single legal move; the game is unchanged either way.
+    pub fn make_move_san(&mut self, san: &str) -> Result<(), String> {
+        let mv = self.san_to_move(san)?;
+        self.make_move(&mv);
+        Ok(())
+    }
+
+    // Plays a sequence of SAN moves, e.g. PGN movetext split into moves.
+    // At the first bad one it stops with the moves before it played.
+    pub fn replay_san(&mut self, sans: &[&str]) -> Result<(), String> {
+        for (index, san) in sans.iter().enumerate() {
+            let mv = self.san_to_move(san).map_err(|err| format!("{} at index {}", err, index))?;
+            self.make_move(&mv);
+        }
+        Ok(())
+    }
+
+    // The game after playing `moves` from the initial position. Each may
+    // be in coordinate notation ("e2e4", "e7e8q") or SAN ("Nf3", "O-O").
+    pub fn from_moves(moves: &[&str]) -> Result<Game, String> {
+        let mut game = Game::initialize();
+        for (index, text) in moves.iter().enumerate() {
+            let mv = if is_coordinate_move(text) {
+                game.uci_to_move(text)
+            } else {
+                game.san_to_move(text)
+            };
+            let mv = mv.map_err(|err| format!("{} at index {}", err, index))?;
+            game.make_move(&mv);
+        }
+        Ok(game)
+    }
+
+    // Resolves a SAN move against the legal moves of the position. Check,
+    // mate and annotation suffixes are ignored.
+    pub fn san_to_move(&self, san: &str) -> Result<Move, String> {
+        let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        let legal = self.legal_moves();
+
+        if text == "O-O" || text == "0-0" || text == "O-O-O" || text == "0-0-0" {
+            let kingside = text.len() == 3;
+            return legal.into_iter()
+                .find(|mv| self.is_castling(mv) && (mv.to > mv.from) == kingside)
+                .ok_or_else(|| format!("Illegal move: '{}'", san));
+        }
+
+        let mut chars: Vec<char> = text.chars().collect();
+
+        let mut promotion = None;
+        if let Some(&last) = chars.last() {
+            if let Some(pt) = piece_from_letter(last) {
+                promotion = Some(pt);
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+        }
+
+        let pt = match chars.first().and_then(|ch| piece_from_letter(*ch)) {
+            Some(pt) => {
+                chars.remove(0);
+                pt
+            }
+            None => PieceType::Pawn,
+        };
+
+        if chars.len() < 2 {
+            return Err(format!("Invalid move: '{}'", san));
+        }
+        let destination: String = chars[chars.len() - 2..].iter().collect();
+        let to = match position_to_bit(&destination) {
+            Ok(bit) => bit.trailing_zeros() as usize,
+            Err(_) => return Err(format!("Invalid move: '{}'", san)),
+        };
+
+        let mut from_file = None;
+        let mut from_rank = None;
+        for ch in &chars[..chars.len() - 2] {
+            match ch {
+                'a'..='h' => from_file = Some(*ch as u8 - b'a'),
+                '1'..='8' => from_rank = Some(*ch as u8 - b'1'),
+                'x' | '-' => (),
+                _ => return Err(format!("Invalid move: '{}'", san)),
+            }
+        }
+
+        let mut candidates = legal.into_iter().filter(|mv| {
+            mv.to == to
+                && mv.promotion == promotion
+                && self.piece_type_on(mv.from) == Some(pt)
+                && from_file.is_none_or(|file| Square::from_index(mv.from).file() == file)
+                && from_rank.is_none_or(|rank| Square::from_index(mv.from).rank() == rank)
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (None, _) => Err(format!("Illegal move: '{}'", san)),
+            (Some(_), Some(_)) => Err(format!("Ambiguous move: '{}'", san)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(pos: &str) -> usize {
+        position_to_bit(pos).unwrap().trailing_zeros() as usize
+    }
+
+    fn mv(from: &str, to: &str) -> Move {
+        Move::new(square(from), square(to))
+    }
+
+    #[test]
+    fn san_to_move_resolves_common_moves() {
+        let game = Game::initialize();
+        assert_eq!(game.san_to_move("e4"), Ok(mv("e2", "e4")));
+        assert_eq!(game.san_to_move("Nf3"), Ok(mv("g1", "f3")));
+        assert_eq!(game.san_to_move("Nc3!?"), Ok(mv("b1", "c3")));
+        assert!(game.san_to_move("e5").is_err());
+        assert!(game.san_to_move("Qh5").is_err());
+        assert!(game.san_to_move("").is_err());
+        assert!(game.san_to_move("Zz9").is_err());
+    }
+
+    #[test]
+    fn san_to_move_castling_captures_and_promotions() {
+        let game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(game.san_to_move("O-O"), Ok(mv("e1", "g1")));
+        assert_eq!(game.san_to_move("O-O-O"), Ok(mv("e1", "c1")));
+        assert_eq!(game.san_to_move("dxe6"), Ok(mv("d5", "e6")));
+        assert_eq!(game.san_to_move("Qxf6"), Ok(mv("f3", "f6")));
+        assert_eq!(game.san_to_move("Bxa6"), Ok(mv("e2", "a6")));
+
+        let game = Game::read_FEN("8/1P2k3/8/8/8/8/8/4K3 w - - 0 1");
+        let promotion = Move { promotion: Some(PieceType::Knight), ..mv("b7", "b8") };
+        assert_eq!(game.san_to_move("b8=N"), Ok(promotion));
+        assert_eq!(game.san_to_move("b8N"), Ok(promotion));
+        assert!(game.san_to_move("b8").is_err());
+    }
+
+    #[test]
+    fn san_to_move_disambiguation() {
+        let game = Game::read_FEN("4k3/8/8/R7/8/8/4K3/R6R w - - 0 1");
+        assert_eq!(game.san_to_move("Rad1"), Ok(mv("a1", "d1")));
+        assert_eq!(game.san_to_move("Rhd1"), Ok(mv("h1", "d1")));
+        assert_eq!(game.san_to_move("R5a3"), Ok(mv("a5", "a3")));
+        assert_eq!(game.san_to_move("Ra1a3"), Ok(mv("a1", "a3")));
+        assert_eq!(game.san_to_move("Rd1"), Err("Ambiguous move: 'Rd1'".to_string()));
+        assert_eq!(game.san_to_move("Ra3"), Err("Ambiguous move: 'Ra3'".to_string()));
+    }
+
+    #[test]
+    fn move_to_san_formats_moves() {
+        let game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(game.move_to_san(&mv("e1", "g1")), "O-O");
+        assert_eq!(game.move_to_san(&mv("e1", "c1")), "O-O-O");
+        assert_eq!(game.move_to_san(&mv("d5", "e6")), "dxe6");
+        assert_eq!(game.move_to_san(&mv("e5", "f7")), "Nxf7");
+        assert_eq!(game.move_to_san(&mv("c3", "b1")), "Nb1");
+        assert_eq!(game.move_to_san(&mv("g2", "h3")), "gxh3");
+        assert_eq!(game.move_to_san(&mv("e5", "d3")), "Nd3");
+
+        let game = Game::read_FEN("4k3/8/8/R7/8/8/4K3/R6R w - - 0 1");
+        assert_eq!(game.move_to_san(&mv("a1", "d1")), "Rad1");
+        assert_eq!(game.move_to_san(&mv("a5", "a3")), "R5a3");
+    }
+
+    #[test]
+    fn move_to_lan_formats_moves() {
+        let game = Game::initialize();
+        assert_eq!(game.move_to_lan(&mv("g1", "f3")), "Ng1-f3");
+        assert_eq!(game.move_to_lan(&mv("e2", "e4")), "e2-e4");
+
+        let game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(game.move_to_lan(&mv("e1", "g1")), "O-O");
+        assert_eq!(game.move_to_lan(&mv("e1", "c1")), "O-O-O");
+        assert_eq!(game.move_to_lan(&mv("e5", "f7")), "Ne5xf7");
+        assert_eq!(game.move_to_lan(&mv("d5", "e6")), "d5xe6");
+
+        let game = Game::read_FEN("4k3/2P5/8/8/8/8/8/R3K3 w - - 0 1");
+        assert_eq!(game.move_to_lan(&Move { promotion: Some(PieceType::Queen), ..mv("c7", "c8") }), "c7-c8Q+");
+        assert_eq!(game.move_to_lan(&Move { promotion: Some(PieceType::Knight), ..mv("c7", "c8") }), "c7-c8N");
+        let game = Game::read_FEN("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(game.move_to_lan(&mv("a1", "a8")), "Ra1-a8#");
+
+        // Apart from castling, LAN is the coordinate move with decoration
+        let game = Game::read_FEN("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1");
+        for legal in game.legal_moves() {
+            let lan = game.move_to_lan(&legal);
+            let coordinates: String = lan.chars()
+                .filter(|ch| ch.is_ascii_digit() || ch.is_ascii_lowercase() && *ch != 'x')
+                .collect();
+            let promotion = legal.promotion.map_or(String::new(), |pt| piece_letter(pt).to_lowercase());
+            assert_eq!(coordinates + &promotion, legal.to_string(), "{}", lan);
+        }
+    }
+
+    #[test]
+    fn make_move_san_plays_or_explains() {
+        let mut game = Game::initialize();
+        assert_eq!(game.make_move_san("e4"), Ok(()));
+        assert_eq!(game.to_FEN(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+
+        let fen = game.to_FEN();
+        assert_eq!(game.make_move_san("e4"), Err("Illegal move: 'e4'".to_string()));
+        assert_eq!(game.make_move_san("Zz9"), Err("Invalid move: 'Zz9'".to_string()));
+        assert_eq!(game.to_FEN(), fen);
+    }
+
+    #[test]
+    fn from_moves_accepts_either_notation() {
+        let game = Game::from_moves(&["e2e4", "e5", "Nf3", "b8c6", "Bb5", "a6", "O-O"]).unwrap();
+        assert_eq!(game.to_FEN(), "r1bqkbnr/1ppp1ppp/p1n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 1 4");
+        assert_eq!(Game::from_moves(&[]).unwrap().to_FEN(), Game::initialize().to_FEN());
+
+        assert_eq!(Game::from_moves(&["e4", "e7e4"]).err(), Some("Illegal move: 'e7e4' at index 1".to_string()));
+        assert_eq!(Game::from_moves(&["e4", "Zz9"]).err(), Some("Invalid move: 'Zz9' at index 1".to_string()));
+    }
+
+    #[test]
+    fn three_pieces_on_one_square_need_file_rank_or_both() {
+        // File when it is unique, rank when only that is, else the square
+        let game = Game::read_FEN("2K5/8/k7/8/4Q2Q/8/8/7Q w - - 0 1");
+        assert_eq!(game.move_to_san(&mv("e4", "e1")), "Qee1");
+        assert_eq!(game.move_to_san(&mv("h1", "e1")), "Q1e1");
+        assert_eq!(game.move_to_san(&mv("h4", "e1")), "Qh4e1");
+        assert_eq!(game.san_to_move("Qh4e1"), Ok(mv("h4", "e1")));
+
+        let game = Game::read_FEN("2K4k/8/8/2N5/8/2N3N1/8/8 w - - 0 1");
+        assert_eq!(game.move_to_san(&mv("g3", "e4")), "Nge4");
+        assert_eq!(game.move_to_san(&mv("c5", "e4")), "N5e4");
+        assert_eq!(game.move_to_san(&mv("c3", "e4")), "Nc3e4");
+    }
+
+    #[test]
+    fn move_to_san_check_and_mate_suffixes() {
+        let game = Game::read_FEN("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(game.move_to_san(&mv("a1", "a8")), "Ra8#");
+        let game = Game::read_FEN("6k1/5pp1/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(game.move_to_san(&mv("a1", "a8")), "Ra8+");
+    }
+
+    #[test]
+    fn san_round_trips_for_every_legal_move() {
+        let fens = ["r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"];
+        for fen in fens {
+            let game = Game::read_FEN(fen);
+            for legal in game.legal_moves() {
+                let san = game.move_to_san(&legal);
+                assert_eq!(game.san_to_move(&san), Ok(legal), "{} in {}", san, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn history_san_matches_a_published_game() {
+        // Morphy v Duke Karl / Count Isouard, Paris 1858
+        let opera = ["e4", "e5", "Nf3", "d6", "d4", "Bg4", "dxe5", "Bxf3", "Qxf3", "dxe5",
+                     "Bc4", "Nf6", "Qb3", "Qe7", "Nc3", "c6", "Bg5", "b5", "Nxb5", "cxb5",
+                     "Bxb5+", "Nbd7", "O-O-O", "Rd8", "Rxd7", "Rxd7", "Rd1", "Qe6", "Bxd7+", "Nxd7",
+                     "Qb8+", "Nxb8", "Rd8#"];
+        let mut game = Game::initialize();
+        game.replay_san(&opera).unwrap();
+        assert_eq!(game.history_san(), opera);
+
+        // Moves taken back drop out of the history
+        let mut game = Game::read_FEN("4k3/8/8/8/8/8/4K3/R6R w - - 0 1");
+        let undo = game.make_move(&mv("a1", "d1"));
+        assert_eq!(game.history_san(), ["Rad1"]);
+        game.unmake_move(&mv("a1", "d1"), undo);
+        assert!(game.history_san().is_empty());
+    }
+
+    #[test]
+    fn replay_san_plays_a_game() {
+        let mut game = Game::initialize();
+        game.replay_san(&["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O"]).unwrap();
+        assert_eq!(game.to_FEN(), "r1bqkb1r/1ppp1ppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 3 5");
+
+        let mut game = Game::initialize();
+        assert_eq!(game.replay_san(&["e4", "e5", "Ke3"]), Err("Illegal move: 'Ke3' at index 2".to_string()));
+        assert_eq!(game.fullmove_number, 2);
+    }
+}
@@ -0,0 +1,1049 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::game::*;
+use crate::movegen::*;
+use crate::tt::*;
+
+pub const MATE: i32 = 100_000;
+const INFINITY: i32 = 1_000_000;
+// Scores beyond this are mates; no search gets anywhere near 1000 plies deep
+const MATE_BOUND: i32 = MATE - 1000;
+// Depth reduction of the null-move search
+const NULL_MOVE_REDUCTION: u8 = 2;
+pub(crate) const MAX_DEPTH: u8 = 64;
+// Half-width of the first aspiration window, and the depth it starts at
+const ASPIRATION_WINDOW: i32 = 25;
+const ASPIRATION_MIN_DEPTH: u8 = 4;
+// History scores are halved once one of them passes this
+const HISTORY_LIMIT: i32 = 1 << 16;
+// Late move reductions apply from this depth, after this many moves
+const LMR_MIN_DEPTH: u8 = 3;
+const LMR_FULL_DEPTH_MOVES: usize = 4;
+// Checks and single replies are only extended this close to the root
+const MAX_EXTENSION_PLY: i32 = 32;
+// Ahead of any capture, promotion or killer
+const HASH_MOVE_SCORE: i32 = 1_000_000;
+// Nodes between looks at the clock
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+// Mate scores count plies from the root, so in the table they are stored
+// relative to the node instead and converted back when probed.
+fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply
+    } else if score <= -MATE_BOUND {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply
+    } else if score <= -MATE_BOUND {
+        score + ply
+    } else {
+        score
+    }
+}
+
+// Moves until mate for a mate score, negative when the side to move is
+// the one getting mated; None for any other score
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score >= MATE_BOUND {
+        Some((MATE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        Some(-(MATE + score) / 2)
+    } else {
+        None
+    }
+}
+
+// Plies cut from a late move's search: more for later moves and deeper
+// nodes, always leaving at least one ply
+fn late_move_reduction(depth: u8, index: usize) -> u8 {
+    let reduction = 1 + (depth >= 6) as u8 + (index >= 12) as u8;
+    reduction.min(depth - 2)
+}
+
+impl Game {
+    // Fixed-depth alpha-beta search. Returns the best move for the side to
+    // move (None when there is no legal move) and its score in centipawns.
+    pub fn search(&mut self, depth: u8) -> (Option<Move>, i32) {
+        self.tt.new_search();
+        self.clear_heuristics();
+        self.nodes = 0;
+        self.deadline = None;
+        self.stopped = false;
+        self.search_root(depth.max(1), -INFINITY, INFINITY)
+    }
+
+    // Iterative deepening until `limit` has passed, returning the result of
+    // the deepest search that finished
+    pub fn search_timed(&mut self, limit: Duration) -> (Option<Move>, i32) {
+        self.iterative_deepening(MAX_DEPTH, Some(Instant::now() + limit), true)
+    }
+
+    // Iterative deepening until `stop` is set, from another thread such as
+    // the one reading UCI commands. As with search_timed the unfinished
+    // depth is thrown away and the last finished one decides the move.
+    pub fn search_until_stopped(&mut self, stop: &Arc<AtomicBool>) -> (Option<Move>, i32) {
+        let previous = self.stop_signal.replace(Arc::clone(stop));
+        let result = self.iterative_deepening(MAX_DEPTH, None, true);
+        self.stop_signal = previous;
+        result
+    }
+
+    // Lazy SMP: `threads` copies of the game run iterative deepening at
+    // once, sharing one transposition table, so each finds the others'
+    // results there. Helpers on odd threads go a ply deeper to spread the
+    // work. The calling thread's search decides the move; the helpers are
+    // stopped as soon as it finishes.
+    pub fn search_parallel(&self, threads: usize, depth: u8) -> (Option<Move>, i32) {
+        let mut main = self.clone();
+        main.tt = self.tt.share();
+        // Allocates the table before sharing it; every thread then starts
+        // one more search, so they agree on its age
+        main.tt.new_search();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut helpers: Vec<Game> = (1..threads.max(1))
+            .map(|_| {
+                let mut helper = self.clone();
+                helper.tt = main.tt.share();
+                helper.stop_signal = Some(Arc::clone(&stop));
+                helper
+            })
+            .collect();
+
+        let depth = depth.clamp(1, MAX_DEPTH - 1);
+        thread::scope(|scope| {
+            for (index, helper) in helpers.iter_mut().enumerate() {
+                let helper_depth = depth + (index % 2 == 0) as u8;
+                scope.spawn(move || helper.iterative_deepening(helper_depth, None, true));
+            }
+            let result = main.iterative_deepening(depth, None, true);
+            stop.store(true, Ordering::Relaxed);
+            result
+        })
+    }
+
+    fn iterative_deepening(&mut self, max_depth: u8, deadline: Option<Instant>,
+                           aspiration: bool) -> (Option<Move>, i32) {
+        self.tt.new_search();
+        self.clear_heuristics();
+        self.nodes = 0;
+        self.stopped = false;
+
+        let mut result = (None, 0);
+        let mut line = Vec::new();
+        for depth in 1..=max_depth {
+            // The first depth always finishes so there is a move to play
+            self.deadline = if depth > 1 { deadline } else { None };
+            let signal = if depth > 1 { None } else { self.stop_signal.take() };
+            let iteration = match result {
+                (Some(_), score) if aspiration && depth >= ASPIRATION_MIN_DEPTH => self.aspiration_search(depth, score),
+                _ => self.search_root(depth, -INFINITY, INFINITY),
+            };
+            if signal.is_some() {
+                self.stop_signal = signal;
+            }
+
+            if self.stopped {
+                // Keep the line of the last finished depth
+                if let Some(pv) = self.pv.first_mut() {
+                    *pv = line;
+                }
+                break;
+            }
+            result = iteration;
+            line = self.principal_variation();
+            if result.0.is_none() {
+                break;
+            }
+        }
+
+        self.deadline = None;
+        result
+    }
+
+    // Searches a window around the score of the previous depth, widening it
+    // when the score falls outside and dropping it after two misses
+    fn aspiration_search(&mut self, depth: u8, previous: i32) -> (Option<Move>, i32) {
+        let mut window = ASPIRATION_WINDOW;
+        for _ in 0..2 {
+            let (alpha, beta) = (previous - window, previous + window);
+            let result = self.search_root(depth, alpha, beta);
+            if self.stopped || (result.1 > alpha && result.1 < beta) {
+                return result;
+            }
+            window *= 4;
+        }
+        self.search_root(depth, -INFINITY, INFINITY)
+    }
+
+    // Searches the root moves, the best move of the previous depth first.
+    // Scores outside (alpha, beta) are only bounds.
+    fn search_root(&mut self, depth: u8, mut alpha: i32, beta: i32) -> (Option<Move>, i32) {
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+
+        self.clear_pv(0);
+
+        let mut moves = self.legal_moves();
+        #[cfg(feature = "syzygy")]
+        self.filter_tablebase_moves(&mut moves);
+        let hash_move = self.tt.probe(self.hash).and_then(|entry| entry.best_move)
+            .filter(|_| self.hash_move_ordering);
+        self.order_moves(&mut moves, self.killers_at(0), hash_move);
+
+        for mv in moves {
+            let undo = self.make_move(&mv);
+            let score = -self.negamax(depth - 1, 1, -beta, -alpha, true);
+            self.unmake_move(&mv, undo);
+            if self.stopped {
+                break;
+            }
+
+            if best_move.is_none() || score > best {
+                best = score;
+                best_move = Some(mv);
+                self.update_pv(0, mv);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        match best_move {
+            Some(_) if !self.stopped => {
+                let bound = if best >= beta {
+                    Bound::Lower
+                } else if best > original_alpha {
+                    Bound::Exact
+                } else {
+                    Bound::Upper
+                };
+                self.tt.store(self.hash, depth, score_to_tt(best, 0), bound, best_move);
+                (best_move, best)
+            }
+            Some(_) => (best_move, best),
+            None => (None, self.terminal_score(0)),
+        }
+    }
+
+    pub fn set_tt_size(&mut self, mb: usize) {
+        self.tt = TranspositionTable::new(mb);
+    }
+
+    // Expected line of play from the last search, starting with its best move
+    pub fn principal_variation(&self) -> Vec<Move> {
+        self.pv.first().cloned().unwrap_or_default()
+    }
+
+    fn clear_pv(&mut self, ply: i32) {
+        if let Some(line) = self.pv.get_mut(ply as usize) {
+            line.clear();
+        }
+    }
+
+    // `mv` followed by the line found from the next ply
+    fn update_pv(&mut self, ply: i32, mv: Move) {
+        let ply = ply as usize;
+        if self.pv.len() < ply + 2 {
+            self.pv.resize(ply + 2, Vec::new());
+        }
+        let (lines, rest) = self.pv.split_at_mut(ply + 1);
+        let line = &mut lines[ply];
+        line.clear();
+        line.push(mv);
+        line.extend_from_slice(&rest[0]);
+    }
+
+    fn negamax(&mut self, depth: u8, ply: i32, mut alpha: i32, beta: i32, allow_null: bool) -> i32 {
+        self.clear_pv(ply);
+        if depth == 0 {
+            return self.quiesce(alpha, beta);
+        }
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(TIME_CHECK_INTERVAL)
+            && (self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                || self.stop_signal.as_ref().is_some_and(|signal| signal.load(Ordering::Relaxed))) {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
+        if self.is_search_repetition(ply) || self.is_dead_drawn() {
+            return self.draw_score(ply);
+        }
+
+        // Too shallow an entry can't end the search here but its move is
+        // still the best guess
+        let mut hash_move = None;
+        if let Some(entry) = self.tt.probe(self.hash) {
+            if entry.depth >= depth {
+                let score = score_from_tt(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => (),
+                }
+            }
+            hash_move = entry.best_move.filter(|_| self.hash_move_ordering);
+        }
+
+        // Null-move pruning: if passing the turn still fails high in a reduced
+        // search, a real move will too. Passing is never better in check, and
+        // in king and pawn endings zugzwang makes the assumption wrong.
+        let us = self.position.active_color;
+        let in_check = self.is_in_check(us);
+        if allow_null && depth > NULL_MOVE_REDUCTION && beta < MATE_BOUND
+            && self.has_non_pawn_material(us) && !in_check {
+            let undo = self.make_null_move();
+            let score = -self.negamax(depth - 1 - NULL_MOVE_REDUCTION, ply + 1, -beta, -beta + 1, false);
+            self.unmake_null_move(undo);
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+        }
+
+        let mut moves = self.legal_moves();
+        if moves.is_empty() {
+            return self.terminal_score(ply);
+        }
+        if self.halfmove_clock >= 100 {
+            return self.draw_score(ply);
+        }
+        // Forcing positions are searched a ply deeper so lines of checks
+        // don't end just before the mate
+        let depth = if self.search_extensions && ply < MAX_EXTENSION_PLY && (in_check || moves.len() == 1) {
+            depth + 1
+        } else {
+            depth
+        };
+        let killers = self.killers_at(ply);
+        self.order_moves(&mut moves, killers, hash_move);
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        for (index, mv) in moves.iter().enumerate() {
+            // Late quiet moves are rarely best, so they get a reduced null
+            // window search first and the full one only if they beat alpha
+            let reducible = self.late_move_reductions && depth >= LMR_MIN_DEPTH && index >= LMR_FULL_DEPTH_MOVES
+                && !in_check && self.is_quiet(mv) && !killers.contains(&Some(*mv));
+            let undo = self.make_move(mv);
+            let score = if reducible && !self.is_in_check(self.position.active_color) {
+                let reduced = depth - 1 - late_move_reduction(depth, index);
+                let score = -self.negamax(reduced, ply + 1, -alpha - 1, -alpha, true);
+                if score > alpha {
+                    -self.negamax(depth - 1, ply + 1, -beta, -alpha, true)
+                } else {
+                    score
+                }
+            } else {
+                -self.negamax(depth - 1, ply + 1, -beta, -alpha, true)
+            };
+            self.unmake_move(mv, undo);
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                best_move = Some(*mv);
+            }
+            if score > alpha {
+                alpha = score;
+                self.update_pv(ply, *mv);
+            }
+            if alpha >= beta {
+                if self.is_quiet(mv) {
+                    self.record_cutoff(*mv, depth, ply);
+                }
+                break;
+            }
+        }
+
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        let best_move = if bound == Bound::Upper { None } else { best_move };
+        self.tt.store(self.hash, depth, score_to_tt(best, ply), bound, best_move);
+
+        best
+    }
+
+    // Repetitions scored as draws. A position already seen since the root
+    // is a draw at once: whichever side could avoid repeating gains nothing
+    // by waiting, and cutting the line short finds forced repetitions at
+    // lower depths. Positions from the game before the root follow the
+    // rules and need two earlier occurrences.
+    fn is_search_repetition(&self, ply: i32) -> bool {
+        let mut earlier = 0;
+        // The last entry of the history is the position one ply back
+        let since_null = self.history.len().saturating_sub(self.repetition_floor);
+        for (back, hash) in self.history.iter().rev().take(self.halfmove_clock.min(since_null)).enumerate() {
+            if *hash == self.hash {
+                if back < ply as usize {
+                    return true;
+                }
+                earlier += 1;
+                if earlier >= 2 {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // Insufficient material, or no pawns, rooks or queens and at most one
+    // minor piece a side. Those can only be mated with the loser's help, so
+    // searching them chases advantages that aren't there. A side in check
+    // is still searched so that such a mate, if it happens, is scored.
+    fn is_dead_drawn(&self) -> bool {
+        let of = |pt: PieceType| self.position.piece_bitboards[pt as usize];
+        if of(PieceType::Pawn) | of(PieceType::Rook) | of(PieceType::Queen) != 0 {
+            return false;
+        }
+        let minors = of(PieceType::Knight) | of(PieceType::Bishop);
+        let one_each = [Color::White, Color::Black].iter()
+            .all(|color| (minors & self.position.color_bitboards[*color as usize]).count_ones() <= 1);
+        (self.is_insufficient_material() || one_each) && !self.is_in_check(self.position.active_color)
+    }
+
+    // Anything besides king and pawns
+    pub fn has_non_pawn_material(&self, color: Color) -> bool {
+        let pawns_and_king = self.position.piece_bitboards[PieceType::Pawn as usize]
+            | self.position.piece_bitboards[PieceType::King as usize];
+        self.position.color_bitboards[color as usize] & !pawns_and_king != 0
+    }
+
+    // Most-Valuable-Victim / Least-Valuable-Attacker: captures first, the
+    // biggest victim first and among equal victims the cheapest attacker.
+    // Promotions rank by the promoted piece. Then come the ply's killer
+    // moves, captures that lose material in the exchange, and the other
+    // quiet moves by their history score.
+    // The hash move goes first. Only moves in `moves` are reordered, so one
+    // that isn't legal here, from a key collision, is simply never played.
+    fn order_moves(&self, moves: &mut [Move], killers: [Option<Move>; 2], hash_move: Option<Move>) {
+        moves.sort_by_cached_key(|mv| {
+            if hash_move == Some(*mv) {
+                -HASH_MOVE_SCORE
+            } else {
+                -self.move_order_score(mv, killers)
+            }
+        });
+    }
+
+    fn move_order_score(&self, mv: &Move, killers: [Option<Move>; 2]) -> i32 {
+        let mut score = 0;
+
+        if self.is_capture(mv) {
+            let victim = match self.piece_at(1 << mv.to) {
+                Some(piece) => piece.piece_type,
+                None => PieceType::Pawn,
+            };
+            let attacker = match self.piece_at(1 << mv.from) {
+                Some(piece) if piece.piece_type != PieceType::King => piece.piece_type.material_value(),
+                _ => PieceType::Queen.material_value() + 100,
+            };
+            let see = self.see(mv);
+            score += if see < 0 {
+                // Still ahead of quiet moves: -see is at most a queen less a pawn
+                1_000 + see
+            } else {
+                10_000 + 10 * victim.material_value() - attacker
+            };
+        }
+
+        if let Some(pt) = mv.promotion {
+            score += 10_000 + pt.material_value();
+        }
+
+        if self.ordering_heuristics && self.is_quiet(mv) {
+            score += if killers[0] == Some(*mv) {
+                9_000
+            } else if killers[1] == Some(*mv) {
+                8_000
+            } else {
+                // At most 0, behind the losing captures
+                self.history_score(mv) - HISTORY_LIMIT
+            };
+        }
+
+        score
+    }
+
+    fn is_quiet(&self, mv: &Move) -> bool {
+        mv.promotion.is_none() && !self.is_capture(mv)
+    }
+
+    fn clear_heuristics(&mut self) {
+        self.killers.clear();
+        self.history_scores.clear();
+        self.history_scores.resize(64, [0; 64]);
+    }
+
+    fn killers_at(&self, ply: i32) -> [Option<Move>; 2] {
+        self.killers.get(ply as usize).copied().unwrap_or([None; 2])
+    }
+
+    fn history_score(&self, mv: &Move) -> i32 {
+        self.history_scores.get(mv.from).map_or(0, |row| row[mv.to])
+    }
+
+    // A quiet move refuted the opponent's move: remember it as a killer of
+    // the ply and credit its history, more for deeper cutoffs
+    fn record_cutoff(&mut self, mv: Move, depth: u8, ply: i32) {
+        let ply = ply as usize;
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; 2]);
+        }
+        let slots = &mut self.killers[ply];
+        if slots[0] != Some(mv) {
+            slots[1] = slots[0];
+            slots[0] = Some(mv);
+        }
+
+        if self.history_scores.len() < 64 {
+            self.history_scores.resize(64, [0; 64]);
+        }
+        let entry = &mut self.history_scores[mv.from][mv.to];
+        *entry += depth as i32 * depth as i32;
+        if *entry > HISTORY_LIMIT {
+            for row in self.history_scores.iter_mut() {
+                for score in row.iter_mut() {
+                    *score /= 2;
+                }
+            }
+        }
+    }
+
+    // Score of a position without legal moves: mated (preferring the shortest
+    // mate) or stalemate
+    fn terminal_score(&self, ply: i32) -> i32 {
+        if self.is_in_check(self.position.active_color) {
+            -(MATE - ply)
+        } else {
+            self.draw_score(ply)
+        }
+    }
+
+    pub fn set_contempt(&mut self, contempt: i32) {
+        self.contempt = contempt;
+    }
+
+    // A draw from the side to move's point of view. The root side is to
+    // move at even plies and pays the contempt; its opponent gains it.
+    fn draw_score(&self, ply: i32) -> i32 {
+        if ply % 2 == 0 {
+            -self.contempt
+        } else {
+            self.contempt
+        }
+    }
+
+    // Resolves captures and promotions at the leaves so the static evaluation
+    // is only taken in quiet positions. The side to move may always decline to
+    // capture, so the static evaluation is a lower bound (stand pat). Captures
+    // that lose material by static exchange are skipped.
+    pub fn quiesce(&mut self, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        let stand_pat = self.evaluate_incremental();
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        if stand_pat > alpha {
+            alpha = stand_pat;
+        }
+
+        let mut best = stand_pat;
+        let mut captures = self.legal_captures();
+        self.order_moves(&mut captures, [None; 2], None);
+        for mv in captures {
+            if self.see(&mv) < 0 {
+                continue;
+            }
+
+            let undo = self.make_move(&mv);
+            let score = -self.quiesce(-beta, -alpha);
+            self.unmake_move(&mv, undo);
+
+            if score > best {
+                best = score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::position_to_bit;
+
+    fn square(pos: &str) -> usize {
+        position_to_bit(pos).unwrap().trailing_zeros() as usize
+    }
+
+    #[test]
+    fn quiescence_avoids_capturing_a_defended_pawn_with_the_queen() {
+        let mut game = Game::read_FEN("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1");
+        let blunder = Move::new(square("d1"), square("d5"));
+
+        // Looking one ply ahead and stopping there, Qxd5 wins a pawn
+        let naive = game.legal_moves().into_iter().max_by_key(|mv| {
+            let undo = game.make_move(mv);
+            let score = -game.evaluate();
+            game.unmake_move(mv, undo);
+            score
+        });
+        assert_eq!(naive, Some(blunder));
+
+        // Quiescence sees exd5 at the leaf
+        let (best, _) = game.search(1);
+        assert!(best.is_some());
+        assert_ne!(best, Some(blunder));
+    }
+
+    #[test]
+    fn order_moves_tries_pawn_takes_queen_before_queen_takes_pawn() {
+        let game = Game::read_FEN("4k3/8/8/3q4/4P3/8/1p6/Q3K3 w - - 0 1");
+        let pawn_takes_queen = Move::new(square("e4"), square("d5"));
+        let queen_takes_pawn = Move::new(square("a1"), square("b2"));
+
+        let mut moves = game.legal_moves();
+        game.order_moves(&mut moves, [None; 2], None);
+        assert_eq!(moves[0], pawn_takes_queen);
+        assert_eq!(moves[1], queen_takes_pawn);
+    }
+
+    #[test]
+    fn order_moves_puts_quiet_moves_last() {
+        let game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let mut moves = game.legal_moves();
+        game.order_moves(&mut moves, [None; 2], None);
+
+        let captures = moves.iter().take_while(|mv| game.is_capture(mv)).count();
+        assert_eq!(captures, game.legal_captures().len());
+        assert!(moves[captures..].iter().all(|mv| !game.is_capture(mv)));
+    }
+
+    #[test]
+    fn order_moves_puts_losing_captures_after_winning_ones() {
+        // Nxg5 wins a pawn; Nxe5 and Rxe5 lose a piece for one
+        let game = Game::read_FEN("4k3/8/3p4/4p1p1/8/5N2/8/4RK2 w - - 0 1");
+        let mut moves = game.legal_moves();
+        game.order_moves(&mut moves, [None; 2], None);
+        assert_eq!(moves[..3], [Move::new(square("f3"), square("g5")),
+                                Move::new(square("f3"), square("e5")),
+                                Move::new(square("e1"), square("e5"))]);
+        assert!(moves[3..].iter().all(|mv| !game.is_capture(mv)));
+    }
+
+    #[test]
+    fn hash_move_goes_first_only_if_legal() {
+        let game = Game::initialize();
+        let knight = Move::new(square("g1"), square("f3"));
+        let mut moves = game.legal_moves();
+        game.order_moves(&mut moves, [None; 2], Some(knight));
+        assert_eq!(moves[0], knight);
+
+        let mut plain = game.legal_moves();
+        game.order_moves(&mut plain, [None; 2], None);
+        let mut collided = game.legal_moves();
+        game.order_moves(&mut collided, [None; 2], Some(Move::new(square("e4"), square("e5"))));
+        assert_eq!(collided, plain);
+
+        // A root entry from another position with the same key does no harm
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut clean = Game::read_FEN(fen);
+        let mut game = Game::read_FEN(fen);
+        game.tt.new_search();
+        game.tt.store(game.hash, 0, 0, Bound::Lower, Some(Move::new(square("a7"), square("a5"))));
+        assert_eq!(game.search(3), clean.search(3));
+    }
+
+    #[test]
+    fn hash_move_ordering_saves_nodes() {
+        let (mut with_nodes, mut without_nodes) = (0, 0);
+        for fen in ["r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+                    "r2q1rk1/ppp2ppp/2np1n2/2b1p1B1/2B1P1b1/2NP1N2/PPP2PPP/R2Q1RK1 w - - 0 8"] {
+            let mut with = Game::read_FEN(fen);
+            let mut without = Game::read_FEN(fen);
+            without.hash_move_ordering = false;
+            with.iterative_deepening(5, None, true);
+            without.iterative_deepening(5, None, true);
+            with_nodes += with.nodes;
+            without_nodes += without.nodes;
+        }
+        assert!(with_nodes < without_nodes);
+    }
+
+    #[test]
+    fn quiesce_is_stand_pat_in_quiet_positions() {
+        let mut game = Game::initialize();
+        assert_eq!(game.quiesce(-INFINITY, INFINITY), game.evaluate());
+    }
+
+    #[test]
+    fn search_finds_mate_in_one() {
+        let mut game = Game::read_FEN("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let (best, score) = game.search(2);
+        assert_eq!(best, Some(Move::new(square("a1"), square("a8"))));
+        assert_eq!(score, MATE - 1);
+    }
+
+    #[test]
+    fn transposition_table_does_not_change_results() {
+        let fens = ["r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1",
+                    "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"];
+        for fen in fens {
+            let mut with_tt = Game::read_FEN(fen);
+            let mut without_tt = Game::read_FEN(fen);
+            without_tt.set_tt_size(0);
+            assert_eq!(with_tt.search(3).1, without_tt.search(3).1, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn search_stores_the_root_result() {
+        let mut game = Game::read_FEN("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1");
+        game.set_tt_size(1);
+        let (best, score) = game.search(3);
+
+        let entry = game.tt.probe(game.hash).unwrap();
+        assert_eq!(entry.best_move, best);
+        assert_eq!((entry.score, entry.depth, entry.bound), (score, 3, Bound::Exact));
+    }
+
+    #[test]
+    fn mate_scores_are_stored_relative_to_the_node() {
+        assert_eq!(score_from_tt(score_to_tt(MATE - 5, 3), 3), MATE - 5);
+        assert_eq!(score_from_tt(score_to_tt(-(MATE - 4), 2), 2), -(MATE - 4));
+        // Mate in 5 plies from the root is mate in 2 from a node 3 plies deep
+        assert_eq!(score_to_tt(MATE - 5, 3), MATE - 2);
+        assert_eq!(score_to_tt(150, 7), 150);
+    }
+
+    #[test]
+    fn null_move_pruning_is_disabled_without_pieces() {
+        let pawns = Game::read_FEN("4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 1");
+        assert!(!pawns.has_non_pawn_material(Color::White));
+        assert!(!pawns.has_non_pawn_material(Color::Black));
+
+        let knight = Game::read_FEN("4k3/4p3/8/8/8/8/4P3/4KN2 w - - 0 1");
+        assert!(knight.has_non_pawn_material(Color::White));
+        assert!(!knight.has_non_pawn_material(Color::Black));
+    }
+
+    #[test]
+    fn search_parallel_finds_the_mate_with_any_thread_count() {
+        let game = Game::read_FEN("r4b1k/6pp/8/6N1/2Q5/8/8/6KR w - - 0 1");
+        for threads in [1, 2, 4] {
+            let (best, score) = game.search_parallel(threads, 4);
+            assert_eq!(best, Some(Move::new(square("h1"), square("h7"))), "{} threads", threads);
+            assert_eq!(mate_in(score), Some(1), "{} threads", threads);
+        }
+
+        let (best, score) = Game::read_FEN("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").search_parallel(3, 5);
+        assert_eq!(best, Some(Move::new(square("d1"), square("d8"))));
+        assert_eq!(mate_in(score), Some(1));
+    }
+
+    #[test]
+    fn shorter_mate_outscores_a_longer_one() {
+        // Rxh7# mates at once while Nf7+ takes three moves to mate
+        let fen = "r4b1k/6pp/8/6N1/2Q5/8/8/6KR w - - 0 1";
+        let rxh7 = Move::new(square("h1"), square("h7"));
+        let mut longer = Game::read_FEN(fen);
+        longer.make_move(&Move::new(square("g5"), square("f7")));
+        assert_eq!(longer.search(5).1, -(MATE - 4));
+
+        // Every depth stores mate scores in the table for the next to probe
+        let mut game = Game::read_FEN(fen);
+        assert_eq!(game.iterative_deepening(6, None, true), (Some(rxh7), MATE - 1));
+        assert_eq!(game.search(6), (Some(rxh7), MATE - 1));
+
+        assert_eq!(mate_in(MATE - 1), Some(1));
+        assert_eq!(mate_in(MATE - 5), Some(3));
+        assert_eq!(mate_in(-(MATE - 4)), Some(-2));
+        assert_eq!(mate_in(-MATE), Some(0));
+        assert_eq!(mate_in(900), None);
+    }
+
+    #[test]
+    fn deeper_search_keeps_finding_tactics() {
+        let mut game = Game::read_FEN("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1");
+        assert_eq!(game.search(5).0, Some(Move::new(square("d2"), square("d5"))));
+
+        let mut game = Game::read_FEN("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(game.search(4), (Some(Move::new(square("a1"), square("a8"))), MATE - 1));
+    }
+
+    #[test]
+    fn principal_variation_is_playable() {
+        for fen in ["r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1",
+                    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"] {
+            let mut game = Game::read_FEN(fen);
+            let (best, _) = game.search(4);
+            let pv = game.principal_variation();
+            assert!(pv.len() > 1);
+            assert_eq!(pv.first().copied(), best);
+
+            let mut replay = Game::read_FEN(fen);
+            for mv in &pv {
+                assert!(replay.legal_moves().contains(mv), "{} in {}", mv, replay.to_FEN());
+                replay.make_move(mv);
+            }
+        }
+
+        let mut game = Game::read_FEN("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        game.search(3);
+        assert_eq!(game.principal_variation(), vec![Move::new(square("a1"), square("a8"))]);
+    }
+
+    #[test]
+    fn search_timed_returns_a_move_in_time() {
+        let mut game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let start = Instant::now();
+        let (best, _) = game.search_timed(Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(game.legal_moves().contains(&best.unwrap()));
+        assert_eq!(game.principal_variation().first().copied(), best);
+
+        let mut game = Game::read_FEN("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(game.search_timed(Duration::from_millis(50)),
+                   (Some(Move::new(square("a1"), square("a8"))), MATE - 1));
+    }
+
+    #[test]
+    fn stop_flag_ends_the_search_promptly() {
+        let mut game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let stop = Arc::new(AtomicBool::new(false));
+        let start = Instant::now();
+        let (best, _) = thread::scope(|scope| {
+            let flag = Arc::clone(&stop);
+            scope.spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                flag.store(true, Ordering::Relaxed);
+            });
+            game.search_until_stopped(&stop)
+        });
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(game.legal_moves().contains(&best.unwrap()));
+        assert_eq!(game.principal_variation().first().copied(), best);
+        assert!(game.stop_signal.is_none());
+
+        // Set from the start, the first depth still finishes
+        let mut game = Game::read_FEN("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(game.search_until_stopped(&stop), (Some(Move::new(square("a1"), square("a8"))), MATE - 1));
+    }
+
+    #[test]
+    fn aspiration_windows_save_nodes_in_quiet_positions() {
+        // Not in every position, but over a handful of them
+        let (mut narrow_nodes, mut full_nodes) = (0, 0);
+        for fen in ["r1bq1rk1/pp2bppp/2n2n2/2pp4/3P4/2PBPN2/PP1N1PPP/R2QK2R w KQ - 0 9",
+                    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+                    "r2q1rk1/ppp2ppp/2np1n2/2b1p1B1/2B1P1b1/2NP1N2/PPP2PPP/R2Q1RK1 w - - 0 8",
+                    "rnbq1rk1/ppp1bppp/4pn2/3p4/2PP4/2N2N2/PP2PPPP/R1BQKB1R w KQ - 4 5"] {
+            let mut narrow = Game::read_FEN(fen);
+            let mut full = Game::read_FEN(fen);
+            let with = narrow.iterative_deepening(6, None, true);
+            let without = full.iterative_deepening(6, None, false);
+            assert!(with.0.is_some() && without.0.is_some());
+            narrow_nodes += narrow.nodes;
+            full_nodes += full.nodes;
+        }
+        assert!(narrow_nodes < full_nodes);
+    }
+
+    #[test]
+    fn killers_and_history_order_quiet_moves() {
+        let mut game = Game::initialize();
+        game.clear_heuristics();
+        let knight = Move::new(square("g1"), square("f3"));
+        let pawn = Move::new(square("d2"), square("d4"));
+        let killer = Move::new(square("c2"), square("c4"));
+        game.record_cutoff(knight, 3, 1);
+        game.record_cutoff(knight, 3, 1);
+        game.record_cutoff(pawn, 2, 1);
+        game.record_cutoff(killer, 1, 2);
+        assert_eq!(game.killers_at(1), [Some(pawn), Some(knight)]);
+        assert_eq!(game.history_score(&knight), 18);
+
+        // Ply 2 knows c4 as its killer; the rest go by history
+        let mut moves = game.legal_moves();
+        game.order_moves(&mut moves, game.killers_at(2), None);
+        assert_eq!(moves[..3], [killer, knight, pawn]);
+    }
+
+    #[test]
+    fn killers_and_history_save_nodes() {
+        for fen in ["r1bq1rk1/pp2bppp/2n2n2/2pp4/3P4/2PBPN2/PP1N1PPP/R2QK2R w KQ - 0 9",
+                    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"] {
+            let mut ordered = Game::read_FEN(fen);
+            let mut plain = Game::read_FEN(fen);
+            plain.ordering_heuristics = false;
+            ordered.search(5);
+            plain.search(5);
+            assert!(ordered.nodes < plain.nodes, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn late_move_reductions_keep_the_tactic_with_fewer_nodes() {
+        // Nxe5 wins the queen; Qxf7+ wins a pawn and the king's shelter
+        for (fen, best) in [("r1b1kb1r/pppp1ppp/5n2/4q3/3nP3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 0 6", ("f3", "e5")),
+                            ("2kr3r/ppp2ppp/2n5/2b1q3/4P3/2N2Q2/PPP2PPP/R1B2RK1 w - - 0 1", ("f3", "f7"))] {
+            let mut reduced = Game::read_FEN(fen);
+            let mut full = Game::read_FEN(fen);
+            full.late_move_reductions = false;
+            let best = Some(Move::new(square(best.0), square(best.1)));
+            assert_eq!(reduced.search(6).0, best);
+            assert_eq!(full.search(6).0, best);
+            assert!(reduced.nodes < full.nodes, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn check_extension_finds_a_mate_in_three_sooner() {
+        // Philidor's legacy: Nf7+ Kg8, Nh6++ Kh8, Qg8+ Rxg8, Nf7#
+        let fen = "4rb1k/6pp/8/6N1/2Q5/8/8/6K1 w - - 0 1";
+        let nf7 = Some(Move::new(square("g5"), square("f7")));
+        let mut game = Game::read_FEN(fen);
+        assert_eq!(game.find_mate(3).map(|line| line.len()), Some(5));
+        assert_eq!(game.search(4), (nf7, MATE - 5));
+
+        let mut plain = Game::read_FEN(fen);
+        plain.search_extensions = false;
+        assert!(plain.search(4).1 < MATE_BOUND);
+    }
+
+    #[test]
+    fn perpetual_check_saves_a_lost_position() {
+        // Down a queen's worth with mate on b1 coming, Qh5+ Kg8 Qe8+ Kh7
+        // repeats for ever
+        let mut game = Game::read_FEN("8/6pk/8/8/2q5/8/rr3PPP/3Q2K1 w - - 0 1");
+        assert!(game.evaluate() < -500);
+        let (best, score) = game.search(6);
+        assert_eq!(best, Some(Move::new(square("d1"), square("h5"))));
+        assert_eq!(score, 0);
+    }
+
+    #[test]
+    fn repetitions_before_the_root_need_two_occurrences() {
+        let knight_dance = [("g1", "f3"), ("g8", "f6"), ("f3", "g1"), ("f6", "g8")];
+        let mut game = Game::initialize();
+        for (from, to) in knight_dance {
+            game.make_move(&Move::new(square(from), square(to)));
+        }
+        // Once before the root: not yet a draw, but it is inside the search
+        assert!(!game.is_search_repetition(0));
+        assert!(game.is_search_repetition(4));
+
+        for (from, to) in knight_dance {
+            game.make_move(&Move::new(square(from), square(to)));
+        }
+        assert!(game.is_search_repetition(0));
+    }
+
+    #[test]
+    fn repetitions_are_not_looked_for_across_a_null_move() {
+        let play = |game: &mut Game, moves: &[(&str, &str)]| {
+            for &(from, to) in moves {
+                game.make_move(&Move::new(square(from), square(to)));
+            }
+        };
+
+        // White's rook triangulates, so the board and side to move are as
+        // after Ra2, before the null move
+        let mut game = Game::read_FEN("4k2r/8/8/8/8/8/8/R3K3 w - - 0 1");
+        play(&mut game, &[("a1", "a2"), ("h8", "h7")]);
+        game.make_null_move();
+        play(&mut game, &[("h7", "h8"), ("a2", "a3"), ("h8", "h6"), ("a3", "a4"), ("h6", "h8"), ("a4", "a2")]);
+        assert_eq!(game.to_FEN(), "4k2r/8/8/8/8/8/R7/4K3 b - - 9 5");
+        assert!(!game.is_search_repetition(7));
+
+        // Positions after the null move still count
+        play(&mut game, &[("h8", "h7"), ("a2", "a1"), ("h7", "h8"), ("a1", "a2")]);
+        assert!(game.is_search_repetition(11));
+    }
+
+    #[test]
+    fn contempt_decides_whether_to_repeat() {
+        // Black can repeat the starting position a third time with Ng8
+        let mut game = Game::initialize();
+        for (from, to) in [("g1", "f3"), ("g8", "f6"), ("f3", "g1"), ("f6", "g8"),
+                           ("g1", "f3"), ("g8", "f6"), ("f3", "g1")] {
+            game.make_move(&Move::new(square(from), square(to)));
+        }
+        let repeat = Move::new(square("f6"), square("g8"));
+
+        game.set_contempt(-300);
+        let (best, score) = game.search(3);
+        assert_eq!((best, score), (Some(repeat), 300));
+
+        game.set_contempt(300);
+        let (best, score) = game.search(3);
+        assert_ne!(best, Some(repeat));
+        assert!(score > -300);
+    }
+
+    #[test]
+    fn search_without_moves() {
+        let mut mated = Game::read_FEN("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        assert_eq!(mated.search(3), (None, -MATE));
+        let mut stalemate = Game::read_FEN("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(stalemate.search(3), (None, 0));
+    }
+
+    #[test]
+    fn dead_drawn_material_ends_the_search() {
+        let mut game = Game::read_FEN("8/3k4/4n3/8/8/3B4/4K3/8 w - - 0 1");
+        let (best, score) = game.search(8);
+        assert!(game.legal_moves().contains(&best.unwrap()));
+        assert_eq!(score, 0);
+        assert!(game.nodes < 1000, "{} nodes", game.nodes);
+        game.set_contempt(20);
+        assert_eq!(game.search(8).1, -20);
+
+        // The helpmate of a minor piece against one is still seen
+        let mut game = Game::read_FEN("7k/5K1n/7B/8/8/8/8/8 w - - 0 1");
+        assert_eq!(game.search(2), (Some(Move::new(square("h6"), square("g7"))), MATE - 1));
+    }
+
+    #[test]
+    fn search_wins_a_hanging_queen() {
+        let mut game = Game::read_FEN("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1");
+        let (best, score) = game.search(3);
+        assert_eq!(best, Some(Move::new(square("d2"), square("d5"))));
+        assert!(score > 400);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::game::*;
+use crate::movegen::*;
+
+// Material values for exchanges; the king is priced so that it only ever
+// takes last, when nothing can recapture
+fn see_value(pt: PieceType) -> i32 {
+    match pt {
+        PieceType::King => 20_000,
+        pt => pt.material_value(),
+    }
+}
+
+impl Game {
+    // Static Exchange Evaluation: material won or lost by `mv` if both sides
+    // keep recapturing on the target square with their least valuable
+    // attacker, either side free to stop when that is better.
+    pub fn see(&self, mv: &Move) -> i32 {
+        let attacker = match self.piece_at(1 << mv.from) {
+            Some(piece) => piece.piece_type,
+            None => return 0,
+        };
+
+        let mut occupancy = self.occupancy() & !(1 << mv.from);
+        let mut gain = [0; 33];
+        gain[0] = if self.is_en_passant(mv) {
+            occupancy &= !(1 << self.en_passant_victim(mv.to));
+            see_value(PieceType::Pawn)
+        } else {
+            self.piece_at(1 << mv.to).map_or(0, |piece| see_value(piece.piece_type))
+        };
+
+        // Value of the piece standing on the target square after each capture
+        let mut on_square = see_value(attacker);
+        if let Some(pt) = mv.promotion {
+            gain[0] += see_value(pt) - see_value(PieceType::Pawn);
+            on_square = see_value(pt);
+        }
+
+        let mut side = self.position.active_color.opposite();
+        let mut depth = 0;
+        loop {
+            depth += 1;
+            // Speculative: what the side to capture gets if it recaptures
+            gain[depth] = on_square - gain[depth - 1];
+            if (-gain[depth - 1]).max(gain[depth]) < 0 {
+                break;
+            }
+
+            let attackers = self.attackers_to(mv.to, occupancy) & occupancy
+                & self.position.color_bitboards[side as usize];
+            let next = PIECE_TYPES.iter()
+                .map(|pt| (*pt, attackers & self.position.piece_bitboards[*pt as usize]))
+                .filter(|(_, bits)| *bits != 0)
+                .min_by_key(|(pt, _)| see_value(*pt));
+
+            match next {
+                Some((pt, bits)) if depth < gain.len() - 1 => {
+                    occupancy &= !(bits & bits.wrapping_neg());
+                    on_square = see_value(pt);
+                    side = side.opposite();
+                }
+                _ => break,
+            }
+        }
+
+        while depth > 1 {
+            depth -= 1;
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+        }
+        gain[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::position_to_bit;
+
+    fn mv(from: &str, to: &str) -> Move {
+        let square = |pos: &str| position_to_bit(pos).unwrap().trailing_zeros() as usize;
+        Move::new(square(from), square(to))
+    }
+
+    #[test]
+    fn undefended_piece_is_won_outright() {
+        let game = Game::read_FEN("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1");
+        assert_eq!(game.see(&mv("e1", "e5")), 100);
+    }
+
+    #[test]
+    fn rook_taking_a_defended_pawn_loses_the_exchange() {
+        let game = Game::read_FEN("4k3/8/3p4/4p3/8/8/8/4RK2 w - - 0 1");
+        assert_eq!(game.see(&mv("e1", "e5")), 100 - 500);
+    }
+
+    #[test]
+    fn x_ray_attackers_join_the_exchange() {
+        // The rooks, queens and bishop lined up behind the first attackers
+        // recapture too; White ends up giving the knight for a pawn
+        let game = Game::read_FEN("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1");
+        assert_eq!(game.see(&mv("d3", "e5")), -220);
+    }
+
+    #[test]
+    fn equal_trades_and_quiet_moves() {
+        let game = Game::read_FEN("4k3/8/3p4/4n3/8/5N2/8/4K3 w - - 0 1");
+        assert_eq!(game.see(&mv("f3", "e5")), 0);
+        // Moving a knight to a square a pawn guards
+        let game = Game::read_FEN("4k3/8/2p5/8/8/2N5/8/4K3 w - - 0 1");
+        assert_eq!(game.see(&mv("c3", "b5")), -320);
+        assert_eq!(game.see(&mv("c3", "e4")), 0);
+    }
+
+    #[test]
+    fn en_passant_and_promotion_captures() {
+        let game = Game::read_FEN("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        assert_eq!(game.see(&mv("e5", "d6")), 100);
+        let game = Game::read_FEN("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        let promotion = Move { promotion: Some(PieceType::Queen), ..mv("a7", "b8") };
+        assert_eq!(game.see(&promotion), 500 + 900 - 100);
+    }
+}
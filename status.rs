@@ -0,0 +1,399 @@
+use crate::game::*;
+
+const DARK_SQUARES: u64 = 0xAA55_AA55_AA55_AA55;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GameStatus {
+    Ongoing,
+    // The winner
+    Checkmate(Color),
+    // The winner
+    Resignation(Color),
+    Stalemate,
+    // Claimable draws
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    // Draws that end the game without a claim
+    SeventyFiveMoveRule,
+    FivefoldRepetition,
+    InsufficientMaterial,
+}
+
+impl Game {
+    pub fn status(&self) -> GameStatus {
+        if let Some(loser) = self.resigned {
+            return GameStatus::Resignation(loser.opposite());
+        }
+
+        let us = self.position.active_color;
+        if !self.has_legal_move() {
+            return if self.is_in_check(us) {
+                GameStatus::Checkmate(us.opposite())
+            } else {
+                GameStatus::Stalemate
+            };
+        }
+
+        if self.is_insufficient_material() {
+            GameStatus::InsufficientMaterial
+        } else if self.halfmove_clock >= 150 {
+            GameStatus::SeventyFiveMoveRule
+        } else if self.repetitions() >= 4 {
+            GameStatus::FivefoldRepetition
+        } else if self.halfmove_clock >= 100 {
+            GameStatus::FiftyMoveRule
+        } else if self.is_repetition() {
+            GameStatus::ThreefoldRepetition
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+
+    pub fn winner(&self) -> Option<Color> {
+        match self.status() {
+            GameStatus::Checkmate(winner) | GameStatus::Resignation(winner) => Some(winner),
+            _ => None,
+        }
+    }
+
+    // The game is over only if a player claims the draw, as FIDE rules
+    // allow under the fifty-move rule or threefold repetition
+    pub fn can_claim_draw(&self) -> bool {
+        matches!(self.status(), GameStatus::FiftyMoveRule | GameStatus::ThreefoldRepetition)
+    }
+
+    // The game is drawn whether or not anyone claims it
+    pub fn is_automatic_draw(&self) -> bool {
+        matches!(self.status(), GameStatus::Stalemate | GameStatus::InsufficientMaterial
+                 | GameStatus::SeventyFiveMoveRule | GameStatus::FivefoldRepetition)
+    }
+
+    // Any end the status reports, claimable draws included, so a play loop
+    // can stop on it
+    pub fn is_game_over(&self) -> bool {
+        self.status() != GameStatus::Ongoing
+    }
+
+    // How the game ended, for showing to players; None while it goes on
+    pub fn outcome_description(&self) -> Option<String> {
+        let text = match self.status() {
+            GameStatus::Ongoing => return None,
+            GameStatus::Checkmate(winner) => format!("Checkmate \u{2014} {:?} wins", winner),
+            GameStatus::Resignation(winner) => {
+                format!("{:?} resigns \u{2014} {:?} wins", winner.opposite(), winner)
+            }
+            GameStatus::Stalemate => "Draw by stalemate".to_string(),
+            GameStatus::FiftyMoveRule => "Draw by fifty-move rule".to_string(),
+            GameStatus::ThreefoldRepetition => "Draw by threefold repetition".to_string(),
+            GameStatus::SeventyFiveMoveRule => "Draw by seventy-five-move rule".to_string(),
+            GameStatus::FivefoldRepetition => "Draw by fivefold repetition".to_string(),
+            GameStatus::InsufficientMaterial => "Draw by insufficient material".to_string(),
+        };
+        Some(text)
+    }
+
+    pub fn resign(&mut self, color: Color) {
+        self.resigned = Some(color);
+    }
+
+    // PGN result: "1-0", "0-1", "1/2-1/2", or "*" while the game goes on
+    pub fn result_token(&self) -> &'static str {
+        match self.status() {
+            GameStatus::Ongoing => "*",
+            GameStatus::Checkmate(Color::White) | GameStatus::Resignation(Color::White) => "1-0",
+            GameStatus::Checkmate(Color::Black) | GameStatus::Resignation(Color::Black) => "0-1",
+            _ => "1/2-1/2",
+        }
+    }
+
+    // Times the current position occurred before. Only positions since the
+    // last capture or pawn move can repeat.
+    pub fn repetitions(&self) -> usize {
+        self.history.iter().rev()
+            .take(self.halfmove_clock)
+            .filter(|hash| **hash == self.hash)
+            .count()
+    }
+
+    // Times the current position has occurred, this time included: 3 is a
+    // threefold repetition, so a UI can warn at 2
+    pub fn repetition_count(&self) -> usize {
+        self.repetitions() + 1
+    }
+
+    // The current position has occurred twice before
+    pub fn is_repetition(&self) -> bool {
+        self.repetitions() >= 2
+    }
+
+    // Neither side can mate by any sequence of legal moves: bare kings, a
+    // single minor piece, or only bishops that all stand on one square color.
+    // Two knights against a bare king are not included: mate is possible if
+    // the defender blunders into it, so the game goes on.
+    pub fn is_insufficient_material(&self) -> bool {
+        let of = |pt: PieceType| self.position.piece_bitboards[pt as usize];
+        if of(PieceType::Pawn) | of(PieceType::Rook) | of(PieceType::Queen) != 0 {
+            return false;
+        }
+
+        let knights = of(PieceType::Knight);
+        let bishops = of(PieceType::Bishop);
+        (knights | bishops).count_ones() <= 1
+            || (knights == 0 && (bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0))
+    }
+
+    // Whether `flagged` running out of time is a draw instead of a loss.
+    // As most servers adjudicate it, only the opponent's material counts:
+    // a bare king, one minor piece, two knights or bishops all on one
+    // square color can't force mate, so the flag draws.
+    pub fn is_draw_by_timeout_vs_insufficient(&self, flagged: Color) -> bool {
+        if self.is_insufficient_material() {
+            return true;
+        }
+        let of = |pt: PieceType| self.pieces_of(flagged.opposite(), pt);
+        if of(PieceType::Pawn) | of(PieceType::Rook) | of(PieceType::Queen) != 0 {
+            return false;
+        }
+
+        let knights = of(PieceType::Knight);
+        let bishops = of(PieceType::Bishop);
+        (knights | bishops).count_ones() <= 1
+            || (bishops == 0 && knights.count_ones() == 2)
+            || (knights == 0 && (bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::*;
+
+    #[test]
+    fn checkmate_and_stalemate() {
+        assert_eq!(Game::read_FEN("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").status(),
+                   GameStatus::Checkmate(Color::White));
+        assert_eq!(Game::read_FEN("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").status(), GameStatus::Stalemate);
+        assert_eq!(Game::initialize().status(), GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn fifty_move_rule() {
+        assert_eq!(Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").status(), GameStatus::Ongoing);
+        assert_eq!(Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 100 80").status(), GameStatus::FiftyMoveRule);
+        // Mate on the hundredth half move still counts
+        assert_eq!(Game::read_FEN("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80").status(),
+                   GameStatus::Checkmate(Color::White));
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut game = Game::initialize();
+        let shuffle = [Move::new(6, 21), Move::new(62, 45), Move::new(21, 6), Move::new(45, 62)];
+        for mv in shuffle {
+            game.make_move(&mv);
+        }
+        assert!(!game.is_repetition());
+        for mv in shuffle {
+            game.make_move(&mv);
+        }
+        assert!(game.is_repetition());
+        assert_eq!(game.status(), GameStatus::ThreefoldRepetition);
+
+        let undo = game.make_move(&Move::new(12, 28));
+        assert_eq!(game.status(), GameStatus::Ongoing);
+        game.unmake_move(&Move::new(12, 28), undo);
+        assert!(game.is_repetition());
+    }
+
+    #[test]
+    fn repetition_count_includes_the_current_position() {
+        let mut game = Game::initialize();
+        assert_eq!(game.repetition_count(), 1);
+        let shuffle = [Move::new(6, 21), Move::new(62, 45), Move::new(21, 6), Move::new(45, 62)];
+        for count in 2..=3 {
+            for mv in shuffle {
+                game.make_move(&mv);
+            }
+            assert_eq!(game.repetition_count(), count);
+        }
+        assert!(game.is_repetition());
+
+        // A pawn move starts the count over
+        game.make_move(&Move::new(12, 28));
+        assert_eq!(game.repetition_count(), 1);
+    }
+
+    #[test]
+    fn repetitions_count_from_a_loaded_fen() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let mut game = Game::read_FEN(fen);
+        assert!(game.history.is_empty());
+        assert_eq!(game.repetitions(), 0);
+
+        // Ng1 Nb8 Nf3 Nc6, twice over
+        let shuffle = [Move::new(21, 6), Move::new(42, 57), Move::new(6, 21), Move::new(57, 42)];
+        for occurrences in 2..=3 {
+            for mv in shuffle {
+                game.make_move(&mv);
+            }
+            assert_eq!(game.repetitions(), occurrences - 1);
+            assert_eq!(game.to_FEN().split(' ').next(), fen.split(' ').next());
+        }
+        assert!(game.is_repetition());
+        assert_eq!(game.status(), GameStatus::ThreefoldRepetition);
+    }
+
+    #[test]
+    fn threefold_is_claimed_but_fivefold_is_automatic() {
+        let mut game = Game::initialize();
+        let shuffle = [Move::new(6, 21), Move::new(62, 45), Move::new(21, 6), Move::new(45, 62)];
+        let mut occurrences = 1;
+        while occurrences < 5 {
+            assert_eq!(game.can_claim_draw(), occurrences >= 3, "{} occurrences", occurrences);
+            assert!(!game.is_automatic_draw(), "{} occurrences", occurrences);
+            for mv in shuffle {
+                game.make_move(&mv);
+            }
+            occurrences += 1;
+        }
+        assert_eq!(game.status(), GameStatus::FivefoldRepetition);
+        assert!(game.is_automatic_draw());
+        assert!(!game.can_claim_draw());
+        assert_eq!(game.result_token(), "1/2-1/2");
+    }
+
+    #[test]
+    fn fifty_moves_are_claimed_but_seventy_five_are_automatic() {
+        let claimable = Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 149 100");
+        assert!(claimable.can_claim_draw());
+        assert!(!claimable.is_automatic_draw());
+
+        let automatic = Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 150 100");
+        assert_eq!(automatic.status(), GameStatus::SeventyFiveMoveRule);
+        assert!(automatic.is_automatic_draw());
+        assert!(!automatic.can_claim_draw());
+
+        assert!(!Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").can_claim_draw());
+        assert!(Game::read_FEN("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").is_automatic_draw());
+    }
+
+    #[test]
+    fn resignation_ends_the_game() {
+        let mut game = Game::initialize();
+        game.make_move(&Move::new(12, 28));
+        game.resign(Color::White);
+        assert_eq!(game.status(), GameStatus::Resignation(Color::Black));
+        assert_eq!(game.winner(), Some(Color::Black));
+        assert_eq!(game.result_token(), "0-1");
+        assert!(!game.can_claim_draw());
+
+        let mut game = Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 100 80");
+        game.resign(Color::Black);
+        assert_eq!(game.result_token(), "1-0");
+    }
+
+    #[test]
+    fn insufficient_material() {
+        for fen in ["4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                    "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+                    "4k3/8/8/8/8/8/8/4KB2 w - - 0 1",
+                    "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1"] {
+            assert!(Game::read_FEN(fen).is_insufficient_material(), "{}", fen);
+            assert_eq!(Game::read_FEN(fen).status(), GameStatus::InsufficientMaterial);
+        }
+        for fen in ["4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1",
+                    "4k3/8/8/8/8/8/8/3NKN2 w - - 0 1",
+                    "4kn2/8/8/8/8/8/8/4KB2 w - - 0 1",
+                    "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"] {
+            assert!(!Game::read_FEN(fen).is_insufficient_material(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn two_knights_draw_on_time_but_play_on() {
+        let knights = Game::read_FEN("4k3/8/8/8/8/8/8/3NKN2 w - - 0 1");
+        assert!(!knights.is_insufficient_material());
+        assert_eq!(knights.status(), GameStatus::Ongoing);
+        assert!(knights.is_draw_by_timeout_vs_insufficient(Color::Black));
+        // White flagging against a bare king is a draw too
+        assert!(knights.is_draw_by_timeout_vs_insufficient(Color::White));
+
+        for fen in ["4k3/8/8/8/8/8/8/4KB2 w - - 0 1",
+                    "4k3/8/8/8/8/8/4p3/4KN2 w - - 0 1",
+                    "4k3/8/8/8/8/4B3/4p3/2B1K3 w - - 0 1"] {
+            assert!(Game::read_FEN(fen).is_draw_by_timeout_vs_insufficient(Color::Black), "{}", fen);
+        }
+        // Black's pawn could still win if White flags
+        assert!(!Game::read_FEN("4k3/8/8/8/8/8/4p3/4KN2 w - - 0 1").is_draw_by_timeout_vs_insufficient(Color::White));
+        for fen in ["4k3/8/8/8/8/8/8/2B1KB1B w - - 0 1",
+                    "4k3/8/8/8/8/8/8/3NKB2 w - - 0 1",
+                    "4k3/8/8/8/8/8/8/2NNKN2 w - - 0 1",
+                    "4k3/8/8/8/8/8/8/4K2R w - - 0 1"] {
+            assert!(!Game::read_FEN(fen).is_draw_by_timeout_vs_insufficient(Color::Black), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn outcome_descriptions() {
+        let mated = Game::read_FEN("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        assert!(mated.is_game_over());
+        assert_eq!(mated.outcome_description().as_deref(), Some("Checkmate \u{2014} White wins"));
+
+        let ongoing = Game::initialize();
+        assert!(!ongoing.is_game_over());
+        assert_eq!(ongoing.outcome_description(), None);
+
+        let mut resigned = Game::initialize();
+        resigned.resign(Color::White);
+        assert_eq!(resigned.outcome_description().as_deref(), Some("White resigns \u{2014} Black wins"));
+
+        let draws = ["7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+                     "4k3/8/8/8/8/8/8/R3K3 w - - 100 80",
+                     "4k3/8/8/8/8/8/8/R3K3 w - - 150 100",
+                     "4k3/8/8/8/8/8/8/4KN2 w - - 0 1"];
+        let mut messages: Vec<String> = draws.iter()
+            .map(|fen| Game::read_FEN(fen).outcome_description().unwrap())
+            .collect();
+        assert_eq!(messages, ["Draw by stalemate", "Draw by fifty-move rule",
+                              "Draw by seventy-five-move rule", "Draw by insufficient material"]);
+
+        let mut repeated = Game::initialize();
+        let shuffle = [Move::new(6, 21), Move::new(62, 45), Move::new(21, 6), Move::new(45, 62)];
+        for _ in 0..4 {
+            for mv in shuffle {
+                repeated.make_move(&mv);
+            }
+            messages.extend(repeated.outcome_description());
+        }
+        assert_eq!(messages[4..], ["Draw by threefold repetition", "Draw by threefold repetition",
+                                   "Draw by fivefold repetition"]);
+    }
+
+    #[test]
+    fn result_tokens() {
+        let black_mated = Game::read_FEN("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        assert_eq!(black_mated.result_token(), "1-0");
+        assert_eq!(black_mated.winner(), Some(Color::White));
+
+        let white_mated = Game::read_FEN("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert_eq!(white_mated.result_token(), "0-1");
+        assert_eq!(white_mated.winner(), Some(Color::Black));
+
+        assert_eq!(Game::initialize().result_token(), "*");
+        assert_eq!(Game::initialize().winner(), None);
+
+        let mut repeated = Game::initialize();
+        for _ in 0..2 {
+            for mv in [Move::new(6, 21), Move::new(62, 45), Move::new(21, 6), Move::new(45, 62)] {
+                repeated.make_move(&mv);
+            }
+        }
+        for game in [Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 100 80"),
+                     repeated,
+                     Game::read_FEN("4k3/8/8/8/8/8/8/4KN2 w - - 0 1"),
+                     Game::read_FEN("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1")] {
+            assert_eq!(game.result_token(), "1/2-1/2", "{:?}", game.status());
+            assert_eq!(game.winner(), None);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess};
+use shakmaty_syzygy::Tablebase;
+use crate::game::*;
+use crate::movegen::Move;
+
+// Outcome of a tablebase position for the side to move. The cursed and
+// blessed results are wins and losses that the fifty-move rule turns into
+// draws.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    // The same result seen from the other side
+    pub fn negate(self) -> Wdl {
+        match self {
+            Wdl::Loss => Wdl::Win,
+            Wdl::BlessedLoss => Wdl::CursedWin,
+            Wdl::Draw => Wdl::Draw,
+            Wdl::CursedWin => Wdl::BlessedLoss,
+            Wdl::Win => Wdl::Loss,
+        }
+    }
+}
+
+impl From<shakmaty_syzygy::Wdl> for Wdl {
+    fn from(wdl: shakmaty_syzygy::Wdl) -> Wdl {
+        match wdl {
+            shakmaty_syzygy::Wdl::Loss => Wdl::Loss,
+            shakmaty_syzygy::Wdl::BlessedLoss => Wdl::BlessedLoss,
+            shakmaty_syzygy::Wdl::Draw => Wdl::Draw,
+            shakmaty_syzygy::Wdl::CursedWin => Wdl::CursedWin,
+            shakmaty_syzygy::Wdl::Win => Wdl::Win,
+        }
+    }
+}
+
+const SIGNATURE_ORDER: [(PieceType, char); 6] = [(PieceType::King, 'K'), (PieceType::Queen, 'Q'),
+                                                 (PieceType::Rook, 'R'), (PieceType::Bishop, 'B'),
+                                                 (PieceType::Knight, 'N'), (PieceType::Pawn, 'P')];
+
+impl Game {
+    // Loads the .rtbw (WDL) and .rtbz (DTZ) files of a directory for this
+    // game. Clones of the game share the tables.
+    pub fn set_tablebase_path(&mut self, path: &Path) -> io::Result<()> {
+        let mut tablebase = Tablebase::new();
+        tablebase.add_directory(path)?;
+        self.tablebase = Some(Arc::new(tablebase));
+        Ok(())
+    }
+
+    // Pieces named as in Syzygy file names, e.g. "KRPvKR"
+    pub fn material_signature(&self) -> String {
+        let side = |color: Color| -> String {
+            SIGNATURE_ORDER.iter()
+                .map(|&(pt, ch)| ch.to_string().repeat(self.count_pieces(color, pt)))
+                .collect()
+        };
+        format!("{}v{}", side(Color::White), side(Color::Black))
+    }
+
+    // The position as shakmaty-syzygy, which decodes the tables, sees it.
+    // None with too many pieces or castling rights, which tables don't cover.
+    fn tablebase_position(&self) -> Option<(&Tablebase<Chess>, Chess)> {
+        let tablebase = self.tablebase.as_deref()?;
+        if self.occupancy().count_ones() as usize > tablebase.max_pieces()
+            || self.castling_rights != CastlingRights::NONE {
+            return None;
+        }
+        let fen: Fen = self.to_FEN().parse().ok()?;
+        Some((tablebase, fen.into_position(CastlingMode::Standard).ok()?))
+    }
+
+    // Takes the halfmove clock into account, so both kinds of file are
+    // needed. None when DTZ rounding leaves the result in doubt.
+    pub fn probe_wdl(&self) -> Option<Wdl> {
+        if self.occupancy().count_ones() == 2 {
+            return Some(Wdl::Draw);
+        }
+        let (tablebase, position) = self.tablebase_position()?;
+        Some(tablebase.probe_wdl(&position).ok()?.unambiguous()?.into())
+    }
+
+    // Plies until the next capture or pawn move with best play, negative
+    // when losing. Tables may store it rounded up by one ply.
+    pub fn probe_dtz(&self) -> Option<i32> {
+        if self.occupancy().count_ones() == 2 {
+            return Some(0);
+        }
+        let (tablebase, position) = self.tablebase_position()?;
+        Some(tablebase.probe_dtz(&position).ok()?.ignore_rounding().0)
+    }
+
+    // Keeps the root moves that reach the best tablebase result, when every
+    // move can be probed; otherwise leaves the list to the search
+    pub(crate) fn filter_tablebase_moves(&mut self, moves: &mut Vec<Move>) {
+        let mut results = Vec::with_capacity(moves.len());
+        for mv in moves.iter() {
+            let undo = self.make_move(mv);
+            let result = self.probe_wdl().map(Wdl::negate);
+            self.unmake_move(mv, undo);
+            match result {
+                Some(wdl) => results.push(wdl),
+                None => return,
+            }
+        }
+
+        if let Some(&best) = results.iter().max() {
+            let mut index = 0;
+            moves.retain(|_| {
+                index += 1;
+                results[index - 1] == best
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn material_signature_lists_white_first() {
+        assert_eq!(Game::read_FEN("4k3/8/8/8/8/8/4P3/R3K2r w - - 0 1").material_signature(), "KRPvKR");
+        assert_eq!(Game::read_FEN("4k3/8/8/8/8/8/8/4K3 w - - 0 1").material_signature(), "KvK");
+    }
+
+    #[test]
+    fn probes_without_tables() {
+        let bare_kings = Game::read_FEN("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(bare_kings.probe_wdl(), Some(Wdl::Draw));
+        assert_eq!(bare_kings.probe_dtz(), Some(0));
+
+        let game = Game::read_FEN("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        assert_eq!(game.probe_wdl(), None);
+        assert_eq!(game.probe_dtz(), None);
+        assert_eq!(Wdl::CursedWin.negate(), Wdl::BlessedLoss);
+    }
+
+    fn with_tables(fen: &str) -> Game {
+        let mut game = Game::read_FEN(fen);
+        game.set_tablebase_path(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tables")).unwrap();
+        game
+    }
+
+    #[test]
+    fn probes_read_the_tables() {
+        let game = with_tables("7k/8/5K2/8/8/8/8/6Q1 w - - 0 1");
+        assert_eq!(game.probe_wdl(), Some(Wdl::Win));
+        assert_eq!(game.probe_dtz(), Some(1));
+
+        let game = with_tables("3qk3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(game.probe_wdl(), Some(Wdl::Loss));
+        assert!(game.probe_dtz().unwrap() < 0);
+
+        // Black wins the rook at once
+        let game = with_tables("8/8/8/8/8/8/6k1/4K2R b - - 0 1");
+        assert_eq!(game.probe_wdl(), Some(Wdl::Draw));
+        assert_eq!(game.probe_dtz(), Some(0));
+
+        // No table for three minor pieces, and none for castling positions
+        assert_eq!(with_tables("4k3/8/8/8/8/8/8/2BNKB2 w - - 0 1").probe_wdl(), None);
+        assert_eq!(with_tables("4k3/8/8/8/8/8/8/4K2R w K - 0 1").probe_wdl(), None);
+    }
+
+    #[test]
+    fn root_filter_keeps_the_best_result() {
+        // Every king move but Kxh1 loses
+        let mut game = with_tables("8/8/8/8/8/8/6k1/4K2R b - - 0 1");
+        let mut moves = game.legal_moves();
+        assert!(moves.len() > 1);
+        game.filter_tablebase_moves(&mut moves);
+        assert_eq!(moves, vec![Move::new(14, 7)]);
+    }
+
+    #[test]
+    fn root_filter_leaves_unknown_positions_to_the_search() {
+        // Kxd1 leaves bare kings but the other king moves can't be probed
+        let mut game = Game::read_FEN("4k3/8/8/8/8/8/8/3rK3 w - - 0 1");
+        let mut moves = game.legal_moves();
+        game.filter_tablebase_moves(&mut moves);
+        assert_eq!(moves, game.legal_moves());
+    }
+}
@@ -0,0 +1,278 @@
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::game::PIECE_TYPES;
+use crate::movegen::Move;
+
+// How a stored score relates to the true value of the position
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Bound {
+    Exact,
+    // Failed high: the true score is at least this
+    Lower,
+    // Failed low: the true score is at most this
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TtEntry {
+    pub key: u64,
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+    age: u8,
+}
+
+// Ages are kept modulo this, in the six bits left of a packed entry
+const AGE_MASK: u8 = 0x3f;
+
+impl TtEntry {
+    // Move in bits 0-15 (0 for none), score in 16-47, depth in 48-55,
+    // bound in 56-57 and age in 58-63. Zero is an empty slot.
+    fn pack(&self) -> u64 {
+        let mv = self.best_move.map_or(0, |mv| {
+            let promotion = mv.promotion.map_or(0, |pt| pt as u64 + 1);
+            mv.from as u64 | (mv.to as u64) << 6 | promotion << 12
+        });
+        let bound = match self.bound {
+            Bound::Upper => 0,
+            Bound::Lower => 1,
+            Bound::Exact => 2,
+        };
+        mv | (self.score as u32 as u64) << 16 | (self.depth as u64) << 48 | bound << 56
+            | ((self.age & AGE_MASK) as u64) << 58
+    }
+
+    fn unpack(key: u64, data: u64) -> TtEntry {
+        let mv = data & 0xffff;
+        let best_move = (mv != 0).then(|| Move {
+            from: (mv & 63) as usize,
+            to: (mv >> 6 & 63) as usize,
+            promotion: match mv >> 12 {
+                0 => None,
+                pt => Some(PIECE_TYPES[pt as usize - 1]),
+            },
+        });
+        let bound = match data >> 56 & 3 {
+            0 => Bound::Upper,
+            1 => Bound::Lower,
+            _ => Bound::Exact,
+        };
+        TtEntry {
+            key,
+            best_move,
+            score: (data >> 16) as u32 as i32,
+            depth: (data >> 48) as u8,
+            bound,
+            age: (data >> 58) as u8,
+        }
+    }
+}
+
+// A packed entry with its key stored XORed with the data. Threads read and
+// write the two words without locking; a slot torn by a concurrent write no
+// longer matches its key and reads as a miss.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+pub const DEFAULT_TT_MB: usize = 16;
+
+// Fixed-size table indexed by `hash % size`. A slot is overwritten when it
+// holds the same position, is left over from an earlier search, or was
+// searched less deeply than the new result. Memory is only allocated when
+// the first search starts, so games that are never searched stay cheap.
+pub struct TranspositionTable {
+    entries: Arc<Vec<Slot>>,
+    size_mb: usize,
+    age: u8,
+}
+
+// A copy is a new, empty table of the same size: the entries are only a
+// cache for the search of the original game. `share` gives a handle to the
+// same entries instead.
+impl Clone for TranspositionTable {
+    fn clone(&self) -> TranspositionTable {
+        TranspositionTable::new(self.size_mb)
+    }
+}
+
+impl TranspositionTable {
+    pub fn new(mb: usize) -> TranspositionTable {
+        TranspositionTable { entries: Arc::new(Vec::new()), size_mb: mb, age: 0 }
+    }
+
+    // Another handle on the same entries, for a search thread. Handles that
+    // start the same number of searches afterwards agree on the age.
+    pub fn share(&self) -> TranspositionTable {
+        TranspositionTable { entries: Arc::clone(&self.entries), size_mb: self.size_mb, age: self.age }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        for slot in self.entries.iter() {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.age = 0;
+    }
+
+    // Called once per search so entries from earlier searches can be
+    // told apart and replaced first
+    pub fn new_search(&mut self) {
+        if self.entries.is_empty() && self.size_mb > 0 {
+            let count = self.size_mb * 1024 * 1024 / mem::size_of::<Slot>();
+            self.entries = Arc::new((0..count).map(|_| Slot::default()).collect());
+        }
+        self.age = self.age.wrapping_add(1) & AGE_MASK;
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+
+    fn read(&self, index: usize) -> (u64, u64) {
+        let slot = &self.entries[index];
+        let data = slot.data.load(Ordering::Relaxed);
+        (slot.key.load(Ordering::Relaxed) ^ data, data)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        let (stored, data) = self.read(self.index(key));
+        if stored == key {
+            Some(TtEntry::unpack(key, data))
+        } else {
+            None
+        }
+    }
+
+    pub fn store(&self, key: u64, depth: u8, score: i32, bound: Bound, best_move: Option<Move>) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        let age = self.age;
+        let index = self.index(key);
+        let (stored, data) = self.read(index);
+        let slot = TtEntry::unpack(stored, data);
+        if slot.key != key && slot.age == age && slot.depth > depth {
+            return;
+        }
+
+        // A fail-low result has no best move; keep the one found before
+        let best_move = if best_move.is_none() && slot.key == key {
+            slot.best_move
+        } else {
+            best_move
+        };
+
+        let data = TtEntry { key, best_move, score, depth, bound, age }.pack();
+        self.entries[index].key.store(key ^ data, Ordering::Relaxed);
+        self.entries[index].data.store(data, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::PieceType;
+
+    #[test]
+    fn store_and_probe() {
+        let mut tt = TranspositionTable::new(1);
+        tt.new_search();
+        assert!(tt.len() > 1000);
+        assert!(tt.probe(42).is_none());
+
+        tt.store(42, 3, 17, Bound::Exact, Some(Move::new(12, 28)));
+        let entry = tt.probe(42).unwrap();
+        assert_eq!((entry.depth, entry.score, entry.bound), (3, 17, Bound::Exact));
+        assert_eq!(entry.best_move, Some(Move::new(12, 28)));
+
+        // Same slot, different key
+        assert!(tt.probe(42 + tt.len() as u64).is_none());
+    }
+
+    #[test]
+    fn replacement_prefers_deeper_entries_within_a_search() {
+        let mut tt = TranspositionTable::new(1);
+        tt.new_search();
+        let other = 7 + tt.len() as u64;
+
+        tt.store(7, 5, 1, Bound::Exact, None);
+        tt.store(other, 2, 2, Bound::Exact, None);
+        assert_eq!(tt.probe(7).unwrap().score, 1);
+        assert!(tt.probe(other).is_none());
+
+        // Entries from an earlier search are always replaced
+        tt.new_search();
+        tt.store(other, 2, 2, Bound::Exact, None);
+        assert_eq!(tt.probe(other).unwrap().score, 2);
+    }
+
+    #[test]
+    fn same_position_keeps_the_known_best_move() {
+        let mut tt = TranspositionTable::new(1);
+        tt.new_search();
+        tt.store(9, 2, 30, Bound::Lower, Some(Move::new(1, 18)));
+        tt.store(9, 3, -5, Bound::Upper, None);
+        let entry = tt.probe(9).unwrap();
+        assert_eq!((entry.depth, entry.bound), (3, Bound::Upper));
+        assert_eq!(entry.best_move, Some(Move::new(1, 18)));
+    }
+
+    #[test]
+    fn entries_pack_into_one_word() {
+        let mut tt = TranspositionTable::new(1);
+        for _ in 0..70 {
+            tt.new_search();
+        }
+        let promotion = Move { from: 52, to: 61, promotion: Some(PieceType::Knight) };
+        for (score, bound) in [(-99_950, Bound::Upper), (0, Bound::Lower), (12_345, Bound::Exact)] {
+            tt.store(u64::MAX, 63, score, bound, Some(promotion));
+            let entry = tt.probe(u64::MAX).unwrap();
+            assert_eq!((entry.best_move, entry.score, entry.depth, entry.bound),
+                       (Some(promotion), score, 63, bound));
+            assert_eq!(entry.age, tt.age);
+        }
+    }
+
+    #[test]
+    fn shared_handles_see_each_others_entries() {
+        let mut tt = TranspositionTable::new(1);
+        tt.new_search();
+        let shared = tt.share();
+        std::thread::scope(|scope| {
+            scope.spawn(|| shared.store(5, 4, 60, Bound::Exact, Some(Move::new(6, 21))));
+        });
+        assert_eq!(tt.probe(5).unwrap().best_move, Some(Move::new(6, 21)));
+
+        // A copy starts out empty
+        let mut copy = tt.clone();
+        copy.new_search();
+        assert!(copy.probe(5).is_none());
+    }
+
+    #[test]
+    fn empty_table_stores_nothing() {
+        let mut tt = TranspositionTable::new(0);
+        tt.new_search();
+        assert!(tt.is_empty());
+        tt.store(1, 1, 1, Bound::Exact, None);
+        assert!(tt.probe(1).is_none());
+    }
+}
@@ -0,0 +1,222 @@
+use std::time::Duration;
+use crate::game::*;
+use crate::movegen::Move;
+use crate::search::mate_in;
+
+// Kept back from every time budget for the GUI's communication lag
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+// Moves the rest of the clock is spread over when `movestogo` isn't given
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// `go` without limits would need a `stop` to end, so it searches this deep
+const DEFAULT_DEPTH: u8 = 6;
+
+// Limits of a `go` command, with times in milliseconds as the GUI sends them
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+pub struct GoParams {
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u32>,
+    pub movetime: Option<u64>,
+    pub depth: Option<u8>,
+    pub infinite: bool,
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: Option<&str>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", name))?;
+    value.parse().map_err(|_| format!("Invalid value for {}: '{}'", name, value))
+}
+
+// The arguments after `go`; options this engine doesn't use are skipped
+pub fn parse_go(args: &str) -> Result<GoParams, String> {
+    let mut params = GoParams::default();
+    let mut tokens = args.split_whitespace();
+    while let Some(token) = tokens.next() {
+        match token {
+            "wtime" => params.wtime = Some(parse_value(token, tokens.next())?),
+            "btime" => params.btime = Some(parse_value(token, tokens.next())?),
+            "winc" => params.winc = Some(parse_value(token, tokens.next())?),
+            "binc" => params.binc = Some(parse_value(token, tokens.next())?),
+            "movestogo" => params.movestogo = Some(parse_value(token, tokens.next())?),
+            "movetime" => params.movetime = Some(parse_value(token, tokens.next())?),
+            "depth" => params.depth = Some(parse_value(token, tokens.next())?),
+            "infinite" => params.infinite = true,
+            _ => (),
+        }
+    }
+    Ok(params)
+}
+
+// The arguments after `position`: "startpos" or "fen" and the FEN's
+// fields, then optionally "moves" and coordinate moves to play from there.
+// The FEN has spaces in it, so it runs up to the "moves" keyword.
+pub fn parse_position(args: &str) -> Result<Game, String> {
+    let (setup, moves) = args.split_once("moves").unwrap_or((args, ""));
+    let setup = setup.trim();
+    let mut game = match setup.split_once(char::is_whitespace).unwrap_or((setup, "")) {
+        ("startpos", "") => Game::initialize(),
+        ("fen", fen) => {
+            let fen = fen.trim();
+            Game::try_read_FEN(fen).map_err(|err| format!("Invalid FEN '{}': {}", fen, err))?
+        }
+        _ => return Err(format!("Expected startpos or fen: '{}'", args)),
+    };
+    for (index, text) in moves.split_whitespace().enumerate() {
+        game.make_move_uci(text).map_err(|err| format!("{} at index {}", err, index))?;
+    }
+    Ok(game)
+}
+
+// A search score as `info` reports it: "cp 35", or "mate 3" and "mate -2"
+// counting moves to the mate
+pub fn uci_score(score: i32) -> String {
+    match mate_in(score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", score),
+    }
+}
+
+impl Game {
+    // Time to spend on this move from the side to move's clock: an equal
+    // share of what is left for the moves to go plus most of the increment,
+    // less a safety margin. Never more than the time remaining.
+    pub fn allocate_time(&self, wtime: Duration, btime: Duration, winc: Duration, binc: Duration,
+                         movestogo: Option<u32>) -> Duration {
+        let (remaining, increment) = match self.position.active_color {
+            Color::White => (wtime, winc),
+            Color::Black => (btime, binc),
+        };
+        let moves = movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        let budget = remaining / moves + increment * 3 / 4;
+
+        // With the clock nearly gone the margin would eat everything
+        let limit = if remaining > MOVE_OVERHEAD * 2 {
+            remaining - MOVE_OVERHEAD
+        } else {
+            remaining / 2
+        };
+        budget.min(limit).max(Duration::from_millis(1)).min(remaining)
+    }
+
+    // Searches as a `go` command asks and answers with an `info` line giving
+    // the score and principal variation, then the bestmove line
+    pub fn go(&mut self, params: &GoParams) -> String {
+        let (best, score) = if let Some(depth) = params.depth {
+            self.search(depth)
+        } else if let Some(movetime) = params.movetime {
+            self.search_timed(Duration::from_millis(movetime).saturating_sub(MOVE_OVERHEAD))
+        } else if !params.infinite && (params.wtime.is_some() || params.btime.is_some()) {
+            let ms = |time: Option<u64>| Duration::from_millis(time.unwrap_or(0));
+            let budget = self.allocate_time(ms(params.wtime), ms(params.btime), ms(params.winc),
+                                            ms(params.binc), params.movestogo);
+            self.search_timed(budget)
+        } else {
+            self.search(DEFAULT_DEPTH)
+        };
+
+        let mut info = format!("info score {}", uci_score(score));
+        let pv = self.principal_variation();
+        if !pv.is_empty() {
+            let moves: Vec<String> = pv.iter().map(Move::to_string).collect();
+            info.push_str(&format!(" pv {}", moves.join(" ")));
+        }
+
+        match best {
+            Some(mv) => format!("{}\nbestmove {}", info, mv),
+            None => format!("{}\nbestmove 0000", info),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn parse_go_reads_the_clock() {
+        let params = parse_go("wtime 60000 btime 55000 winc 1000 binc 1000 movestogo 20 ponder").unwrap();
+        assert_eq!(params, GoParams { wtime: Some(60000), btime: Some(55000), winc: Some(1000), binc: Some(1000),
+                                      movestogo: Some(20), ..Default::default() });
+        assert_eq!(parse_go("depth 5").unwrap().depth, Some(5));
+        assert!(parse_go("infinite").unwrap().infinite);
+        assert!(parse_go("wtime soon").is_err());
+        assert!(parse_go("movetime").is_err());
+    }
+
+    #[test]
+    fn parse_position_plays_the_moves() {
+        let game = parse_position("fen rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1 moves c7c5 g1f3").unwrap();
+        assert_eq!(game.to_FEN(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        let game = parse_position("startpos moves e2e4 c7c5 g1f3").unwrap();
+        assert_eq!(game.to_FEN(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+        assert_eq!(parse_position("startpos").unwrap().to_FEN(), Game::initialize().to_FEN());
+        let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        assert_eq!(parse_position(&format!("fen {}", fen)).unwrap().to_FEN(), fen);
+    }
+
+    #[test]
+    fn parse_position_rejects_bad_input() {
+        assert_eq!(parse_position("startpos moves e2e4 e2e4").err(), Some("Illegal move: 'e2e4' at index 1".to_string()));
+        assert!(parse_position("fen 8/8/8 w - - 0 1 moves e2e4").err().unwrap().starts_with("Invalid FEN '8/8/8 w - - 0 1'"));
+        assert!(parse_position("").is_err());
+        assert!(parse_position("moves e2e4").is_err());
+    }
+
+    #[test]
+    fn allocation_uses_the_clock_of_the_side_to_move() {
+        let white = Game::initialize();
+        let black = Game::read_FEN("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        assert_eq!(white.allocate_time(ms(60000), ms(30000), ms(0), ms(0), Some(20)), ms(3000));
+        assert_eq!(black.allocate_time(ms(60000), ms(30000), ms(0), ms(0), Some(20)), ms(1500));
+        // Three quarters of the increment on top
+        assert_eq!(white.allocate_time(ms(60000), ms(60000), ms(2000), ms(0), Some(20)), ms(4500));
+    }
+
+    #[test]
+    fn allocation_stays_positive_and_within_the_clock() {
+        let game = Game::initialize();
+        let mut previous = Duration::MAX;
+        for remaining in [600000, 60000, 5000, 1000, 200, 100, 40, 5, 2] {
+            for (increment, movestogo) in [(0, None), (5000, None), (0, Some(1)), (2000, Some(3))] {
+                let budget = game.allocate_time(ms(remaining), ms(remaining), ms(increment), ms(increment), movestogo);
+                assert!(budget > Duration::ZERO, "{} {}", remaining, increment);
+                assert!(budget <= ms(remaining), "{} {}", remaining, increment);
+            }
+            // Less time left, less time spent
+            let budget = game.allocate_time(ms(remaining), ms(remaining), ms(0), ms(0), None);
+            assert!(budget <= previous);
+            previous = budget;
+        }
+    }
+
+    #[test]
+    fn go_answers_with_a_legal_best_move() {
+        let mut game = Game::initialize();
+        let reply = game.go(&parse_go("wtime 2000 btime 2000 movestogo 40").unwrap());
+        let mv = reply.lines().last().unwrap().strip_prefix("bestmove ").unwrap();
+        assert!(game.uci_to_move(mv).is_ok(), "{}", reply);
+
+        let mut mated = Game::read_FEN("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        assert_eq!(mated.go(&parse_go("depth 2").unwrap()), "info score mate 0\nbestmove 0000");
+    }
+
+    #[test]
+    fn go_reports_mates_in_moves() {
+        let mut game = Game::read_FEN("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(game.go(&parse_go("depth 3").unwrap()), "info score mate 1 pv a1a8\nbestmove a1a8");
+
+        let mut game = Game::read_FEN("4rb1k/6pp/8/6N1/2Q5/8/8/6K1 w - - 0 1");
+        assert!(game.go(&parse_go("depth 4").unwrap()).starts_with("info score mate 3 pv g5f7"));
+
+        assert_eq!(uci_score(35), "cp 35");
+        assert_eq!(uci_score(-120), "cp -120");
+        assert_eq!(uci_score(-(MATE - 4)), "mate -2");
+    }
+}
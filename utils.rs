@@ -1,3 +1,5 @@
+use crate::game::Color;
+
 static MOD67TABLE: [usize; 67] = [
     64, 0, 1, 39, 2, 15, 40, 23,
     3, 12, 16, 59, 41, 19, 24, 54,
@@ -11,17 +13,81 @@ static MOD67TABLE: [usize; 67] = [
 
 pub fn bit_scan(bit: u64) -> usize {
     let remainder = (bit % 67) as usize;
-    return MOD67TABLE[remainder];
+    MOD67TABLE[remainder]
 }
 
-pub fn split_on(s: &str, sep: char) -> (&str, &str) {
-    for (i, item) in s.chars().enumerate() {
-        if item == sep {
-            return (&s[0..i], &s[i+1..]);
-        }
+// Index of the lowest set bit of any non-empty bitboard, unlike bit_scan
+// which needs a single bit
+pub fn bit_scan_forward(bb: u64) -> usize {
+    bb.trailing_zeros() as usize
+}
+
+// Index of the highest set bit of a non-empty bitboard
+pub fn bit_scan_reverse(bb: u64) -> usize {
+    63 - bb.leading_zeros() as usize
+}
+
+// Clears the lowest set bit and returns its index
+pub fn pop_lsb(bb: &mut u64) -> usize {
+    let index = bit_scan_forward(*bb);
+    *bb &= *bb - 1;
+    index
+}
+
+// Indices of the set bits, lowest first
+pub fn iter_bits(mut bb: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || if bb == 0 { None } else { Some(pop_lsb(&mut bb)) })
+}
+
+// FILE_MASKS[0] is the a-file, RANK_MASKS[0] the first rank
+pub const FILE_MASKS: [u64; 8] = [
+    0x0101_0101_0101_0101, 0x0202_0202_0202_0202, 0x0404_0404_0404_0404, 0x0808_0808_0808_0808,
+    0x1010_1010_1010_1010, 0x2020_2020_2020_2020, 0x4040_4040_4040_4040, 0x8080_8080_8080_8080];
+
+pub const RANK_MASKS: [u64; 8] = [
+    0x0000_0000_0000_00FF, 0x0000_0000_0000_FF00, 0x0000_0000_00FF_0000, 0x0000_0000_FF00_0000,
+    0x0000_00FF_0000_0000, 0x0000_FF00_0000_0000, 0x00FF_0000_0000_0000, 0xFF00_0000_0000_0000];
+
+// The files either side of `file`
+pub const fn adjacent_files_mask(file: usize) -> u64 {
+    let west = if file > 0 { FILE_MASKS[file - 1] } else { 0 };
+    let east = if file < 7 { FILE_MASKS[file + 1] } else { 0 };
+    west | east
+}
+
+// Squares on the ranks in front of `square` from `color`'s side
+pub const fn ranks_ahead(color: Color, square: usize) -> u64 {
+    let rank = square / 8;
+    match color {
+        Color::White if rank < 7 => u64::MAX << ((rank + 1) * 8),
+        Color::Black => (1 << (rank * 8)) - 1,
+        _ => 0,
     }
+}
+
+// Squares in front of `square` on its own file, the path of a pawn there
+pub const fn front_span(color: Color, square: usize) -> u64 {
+    FILE_MASKS[square % 8] & ranks_ahead(color, square)
+}
+
+// Squares in front of `square` on its own and the adjacent files. A pawn
+// is passed when no enemy pawn stands on them.
+pub const fn passed_pawn_mask(color: Color, square: usize) -> u64 {
+    (FILE_MASKS[square % 8] | adjacent_files_mask(square % 8)) & ranks_ahead(color, square)
+}
 
-    (&s[..], "")
+// File of a single-bit bitboard, 0 for the a-file
+pub fn file_of(bit: u64) -> u8 {
+    (bit_scan(bit) % 8) as u8
+}
+
+// Rank of a single-bit bitboard, 0 for the first rank
+pub fn rank_of(bit: u64) -> u8 {
+    (bit_scan(bit) / 8) as u8
+}
+
+pub fn split_on(s: &str, sep: char) -> (&str, &str) {
+    s.split_once(sep).unwrap_or((s, ""))
 }
 
 #[cfg(test)]
@@ -50,22 +116,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn pawn_masks_cover_the_squares_ahead() {
+        // e4 is square 28
+        let d5_to_f8 = (FILE_MASKS[3] | FILE_MASKS[4] | FILE_MASKS[5])
+            & (RANK_MASKS[4] | RANK_MASKS[5] | RANK_MASKS[6] | RANK_MASKS[7]);
+        assert_eq!(passed_pawn_mask(Color::White, 28), d5_to_f8);
+        assert_eq!(front_span(Color::White, 28), FILE_MASKS[4] & d5_to_f8);
+        // Black looks down the board, and the edge file has one neighbour
+        assert_eq!(passed_pawn_mask(Color::Black, 48), (FILE_MASKS[0] | FILE_MASKS[1]) & !(RANK_MASKS[6] | RANK_MASKS[7]));
+        assert_eq!(front_span(Color::Black, 8), 1);
+        assert_eq!(passed_pawn_mask(Color::White, 60), 0);
+    }
+
     #[test]
     fn bit_scan_works() {
         for i in 0..64 {
-            let bit = (1 as u64) << i;
+            let bit = 1u64 << i;
             let index = bit_scan(bit);
 
             assert_eq!(i, index);
         }
     }
 
+    #[test]
+    fn file_and_rank_masks_partition_the_board() {
+        let a_file = (0..8).fold(0, |mask: u64, rank| mask | 1 << (rank * 8));
+        assert_eq!(FILE_MASKS[0], a_file);
+        assert_eq!(RANK_MASKS[0], 0xFF);
+
+        for masks in [FILE_MASKS, RANK_MASKS] {
+            assert_eq!(masks.iter().fold(0, |all, mask| all | mask), u64::MAX);
+            assert_eq!(masks.iter().map(|mask| mask.count_ones()).sum::<u32>(), 64);
+        }
+
+        for i in 0..64 {
+            let bit: u64 = 1 << i;
+            assert_ne!(FILE_MASKS[file_of(bit) as usize] & bit, 0);
+            assert_ne!(RANK_MASKS[rank_of(bit) as usize] & bit, 0);
+            assert_eq!((file_of(bit), rank_of(bit)), (i as u8 % 8, i as u8 / 8));
+        }
+    }
+
+    #[test]
+    fn iter_bits_yields_indices_in_order() {
+        assert_eq!(iter_bits(RANK_MASKS[1]).collect::<Vec<usize>>(), (8..16).collect::<Vec<usize>>());
+        assert_eq!(iter_bits(0).count(), 0);
+        assert_eq!(iter_bits(1 << 63 | 1).collect::<Vec<usize>>(), [0, 63]);
+
+        let mut bb: u64 = 0b1010_0000;
+        assert_eq!((bit_scan_forward(bb), bit_scan_reverse(bb)), (5, 7));
+        assert_eq!(pop_lsb(&mut bb), 5);
+        assert_eq!(pop_lsb(&mut bb), 7);
+        assert_eq!(bb, 0);
+    }
+
     #[test]
     #[should_panic]
     fn bit_scan_works_if_highest_bit_is_one() {
         for i in 0..64 {
-            let mut bit = (1 as u64) << i;
-            bit |= (1 as u64) << 63;
+            let mut bit = 1u64 << i;
+            bit |= 1u64 << 63;
             let index = bit_scan(bit);
 
             assert_eq!(i, index);
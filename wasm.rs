@@ -0,0 +1,49 @@
+use wasm_bindgen::prelude::*;
+use crate::game::*;
+use crate::movegen::Move;
+
+// A game for JavaScript. Every method reports bad input as an exception
+// rather than panicking, which would abort the whole module.
+#[wasm_bindgen]
+pub struct WasmGame {
+    game: Game,
+}
+
+#[wasm_bindgen]
+impl WasmGame {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmGame {
+        WasmGame { game: Game::initialize() }
+    }
+
+    #[wasm_bindgen(js_name = fromFen)]
+    pub fn from_fen(fen: &str) -> Result<WasmGame, JsError> {
+        Game::try_read_FEN(fen)
+            .map(|game| WasmGame { game })
+            .map_err(|err| JsError::new(&err.to_string()))
+    }
+
+    pub fn fen(&self) -> String {
+        self.game.to_FEN()
+    }
+
+    // Legal moves in coordinate notation, e.g. "e2e4" or "e7e8q"
+    #[wasm_bindgen(js_name = legalMoves)]
+    pub fn legal_moves(&self) -> Vec<String> {
+        self.game.legal_moves().iter().map(Move::to_string).collect()
+    }
+
+    // Plays a coordinate move, refusing it unless it is legal here
+    #[wasm_bindgen(js_name = makeMove)]
+    pub fn make_move(&mut self, mv: &str) -> Result<(), JsError> {
+        let mv = self.game.uci_to_move(mv).map_err(|err| JsError::new(&err))?;
+        self.game.make_move(&mv);
+        Ok(())
+    }
+}
+
+impl Default for WasmGame {
+    fn default() -> WasmGame {
+        WasmGame::new()
+    }
+}
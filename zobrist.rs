@@ -0,0 +1,184 @@
+use crate::game::*;
+
+// Random keys for Zobrist hashing, generated at compile time from a fixed
+// seed so hashes are the same on every run.
+pub struct ZobristKeys {
+    pieces: [[[u64; 64]; 6]; 2],
+    castling: [u64; 16],
+    en_passant: [u64; 8],
+    black_to_move: u64,
+}
+
+const fn xorshift(state: u64) -> u64 {
+    let mut x = state;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    x
+}
+
+impl ZobristKeys {
+    const fn generate() -> ZobristKeys {
+        let mut state = 0x3243_F6A8_885A_308D;
+        let mut keys = ZobristKeys {
+            pieces: [[[0; 64]; 6]; 2],
+            castling: [0; 16],
+            en_passant: [0; 8],
+            black_to_move: 0,
+        };
+
+        let mut color = 0;
+        while color < 2 {
+            let mut pt = 0;
+            while pt < 6 {
+                let mut square = 0;
+                while square < 64 {
+                    state = xorshift(state);
+                    keys.pieces[color][pt][square] = state;
+                    square += 1;
+                }
+                pt += 1;
+            }
+            color += 1;
+        }
+
+        let mut i = 0;
+        while i < 16 {
+            state = xorshift(state);
+            keys.castling[i] = state;
+            i += 1;
+        }
+
+        let mut file = 0;
+        while file < 8 {
+            state = xorshift(state);
+            keys.en_passant[file] = state;
+            file += 1;
+        }
+
+        keys.black_to_move = xorshift(state);
+        keys
+    }
+}
+
+static KEYS: ZobristKeys = ZobristKeys::generate();
+
+pub fn piece_key(color: Color, pt: PieceType, square: usize) -> u64 {
+    KEYS.pieces[color as usize][pt as usize][square]
+}
+
+pub fn castling_key(rights: CastlingRights) -> u64 {
+    KEYS.castling[rights.bits() as usize]
+}
+
+pub fn en_passant_key(target: PiecePosition) -> u64 {
+    KEYS.en_passant[target.trailing_zeros() as usize % 8]
+}
+
+pub fn black_to_move_key() -> u64 {
+    KEYS.black_to_move
+}
+
+impl Game {
+    // Hash of the position from scratch; `self.hash` is the incrementally
+    // updated copy and must always agree with this.
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = castling_key(self.castling_rights);
+        for piece in &self.pieces {
+            hash ^= piece_key(piece.color, piece.piece_type, piece.position.trailing_zeros() as usize);
+        }
+        if let Some(target) = self.en_passant {
+            hash ^= en_passant_key(target);
+        }
+        if self.position.active_color == Color::Black {
+            hash ^= black_to_move_key();
+        }
+        hash
+    }
+
+    // Same placement, side to move, castling rights and en passant square,
+    // whatever the move clocks. The hashes decide unless they collide.
+    pub fn same_position(&self, other: &Game) -> bool {
+        self.hash == other.hash
+            && self.position == other.position
+            && self.castling_rights == other.castling_rights
+            && self.castling_rooks == other.castling_rooks
+            && self.en_passant == other.en_passant
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::*;
+
+    fn walk(game: &mut Game, depth: usize) {
+        if depth == 0 {
+            return;
+        }
+        for mv in game.legal_moves() {
+            let before = game.hash;
+            let undo = game.make_move(&mv);
+            assert_eq!(game.hash, game.compute_hash(), "after {} in {}", mv, game.to_FEN());
+            walk(game, depth - 1);
+            game.unmake_move(&mv, undo);
+            assert_eq!(game.hash, before);
+        }
+    }
+
+    #[test]
+    fn incremental_hash_matches_recomputation() {
+        let mut game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        walk(&mut game, 3);
+        let mut game = Game::read_FEN("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8");
+        walk(&mut game, 2);
+    }
+
+    #[test]
+    fn transpositions_hash_equally() {
+        let mut one = Game::initialize();
+        let mut two = Game::initialize();
+        // 1. Nf3 Nf6 2. Nc3 versus 1. Nc3 Nf6 2. Nf3
+        for mv in [Move::new(6, 21), Move::new(62, 45), Move::new(1, 18)] {
+            one.make_move(&mv);
+        }
+        for mv in [Move::new(1, 18), Move::new(62, 45), Move::new(6, 21)] {
+            two.make_move(&mv);
+        }
+        assert_eq!(one.hash, two.hash);
+        assert_ne!(one.hash, Game::initialize().hash);
+    }
+
+    #[test]
+    fn same_position_ignores_the_move_clocks() {
+        let mut played = Game::initialize();
+        for mv in [Move::new(6, 21), Move::new(62, 45), Move::new(21, 6), Move::new(45, 62)] {
+            played.make_move(&mv);
+        }
+        assert_eq!((played.halfmove_clock, played.fullmove_number), (4, 3));
+        assert!(played.same_position(&Game::initialize()));
+
+        let after_e4 = Game::read_FEN("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        for fen in ["rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+                    "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1",
+                    "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b Kkq e3 0 1"] {
+            assert!(!after_e4.same_position(&Game::read_FEN(fen)), "{}", fen);
+        }
+        let later = Game::read_FEN("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 7 30");
+        assert!(after_e4.same_position(&later));
+    }
+
+    #[test]
+    fn hash_covers_side_castling_and_en_passant() {
+        let hashes = ["rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                      "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+                      "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1",
+                      "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w Kkq - 0 1"]
+            .map(|fen| Game::read_FEN(fen).hash);
+        for i in 0..hashes.len() {
+            for j in i + 1..hashes.len() {
+                assert_ne!(hashes[i], hashes[j]);
+            }
+        }
+    }
+}